@@ -1,7 +1,23 @@
-use crate::Measurement;
+use crate::{datetime, flux::FluxValue, lint, LineProtocolOptions, Measurement, Precision};
+use audit::AuditLog;
+use dialect::QueryOptions;
+use futures_lite::{stream, Stream};
 use isahc::{AsyncReadResponseExt, HttpClient};
+use names::{BucketName, InvalidNameError, OrgName, Token};
 use query::Query;
-use std::{collections::HashMap, error::Error, fmt::Display};
+use schema::WriteSchema;
+use stats::WriteStats;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tail::{Delay, TailState};
 
 pub type InfluxQueryResponse = Vec<HashMap<String, String>>;
 
@@ -10,113 +26,1414 @@ pub struct InfluxClient {
     key: String,
     org: String,
     http_client: HttpClient,
+    schema: Option<Mutex<WriteSchema>>,
+    stats: Mutex<WriteStats>,
+    max_time_skew: Option<(Duration, TimeSkewPolicy)>,
+    max_line_length: Option<usize>,
+    max_batch_size: Option<usize>,
+    query_cache: Option<(Duration, Mutex<QueryCache>)>,
+    default_tags: BTreeMap<String, String>,
+    measurement_prefix: String,
+    write_lanes: usize,
+    proxy_authorization: Option<String>,
+    line_protocol_options: LineProtocolOptions,
+    audit_log: Option<AuditLog>,
+    check_field_types: bool,
+    query_options: QueryOptions,
 }
 
-impl InfluxClient {
-    fn new(url: String, key: String, org: String, http_client: HttpClient) -> Self {
-        Self {
-            url,
-            key,
-            org,
-            http_client,
-        }
-    }
+type QueryCache = HashMap<Query, (Instant, InfluxQueryResponse)>;
+
+/// A hook correcting the timestamp of a measurement, see `TimeSkewPolicy::Correct`.
+pub type TimeSkewHook = Arc<dyn Fn(&Measurement, i128) -> Option<i128> + Send + Sync>;
+
+/// What to do with measurements whose timestamps deviate too much from the local time, see
+/// `InfluxClientBuilder::max_time_skew`.
+#[derive(Clone)]
+pub enum TimeSkewPolicy {
+    /// Fail the write with `InfluxError::TimeSkew`, without writing anything.
+    Reject,
+    /// Call the hook with the measurement and the current time (in nanoseconds since the Unix
+    /// epoch). The measurement is written with the returned timestamp (in nanoseconds), or the
+    /// write fails with `InfluxError::TimeSkew` if `None` is returned.
+    Correct(TimeSkewHook),
+}
+
+/// The measurements of a write request and its serialized body.
+type WriteRequest<'a> = (Vec<&'a Measurement>, String);
 
+impl InfluxClient {
     pub fn builder(url: String, key: String, org: String) -> InfluxClientBuilder {
         InfluxClientBuilder::new(url, key, org)
     }
 
     /// Write data to the specified bucket.
+    ///
+    /// Measurements are written with the precision their timestamps were given with. If the
+    /// batch contains measurements with different precisions, it is split into one request per
+    /// precision. With `InfluxClientBuilder::write_lanes`, each request is further split by series
+    /// and sent concurrently, and the first error of any lane is returned once all lanes are done.
+    /// All requests are serialized before the first one is sent, so a line that is too long, a
+    /// batch that is too large or rejected control characters fail the write without sending
+    /// any of it.
     pub async fn write(
         &self,
         bucket: &str,
         measurements: &[Measurement],
     ) -> Result<(), InfluxError> {
-        let payload = measurements
-            .iter()
-            .map(|m| m.to_line_protocol())
-            .collect::<Vec<_>>()
-            .join("\n");
-        let url = format!(
-            "{}/api/v2/write?org={}&bucket={}&precision=ns",
-            self.url, self.org, bucket
-        );
+        BucketName::try_from(bucket)?;
+        let checked = self
+            .check_field_types(measurements)
+            .and_then(|_| self.check_time_skew(measurements));
+        let measurements = match checked {
+            Ok(measurements) => measurements,
+            Err(e) => {
+                self.lock_stats().record(measurements, false);
+                return Err(e);
+            }
+        };
+        let measurements = self.apply_default_tags(measurements);
+        let requests = match self.serialize_write(&measurements) {
+            Ok(requests) => requests,
+            Err(e) => {
+                self.lock_stats().record(measurements.iter(), false);
+                return Err(e);
+            }
+        };
 
-        let request = isahc::Request::builder()
-            .uri(url)
-            .method("POST")
-            .header("Authorization", format!("Token {}", &self.key))
-            .body(payload)?;
-        let mut response = self.http_client.send_async(request).await?;
-        if !response.status().is_success() {
-            let body = response.text().await?;
-            return Err(InfluxError::NonSuccessResponse(response.status(), body));
+        for (precision, requests) in requests {
+            let writes = requests
+                .into_iter()
+                .map(|(lane, payload)| self.write_with_precision(bucket, lane, precision, payload));
+            for result in lanes::join_all(writes).await {
+                result?;
+            }
         }
         Ok(())
     }
 
-    pub async fn query(&self, query: Query) -> Result<InfluxQueryResponse, InfluxError> {
-        let payload = query.to_string();
+    /// Check a write without sending it: fails like `write` would before sending anything (for
+    /// an invalid bucket name, conflicting field types, too much time skew, a line that is too
+    /// long, a batch that is too large or rejected control characters), and otherwise returns
+    /// the warnings of `lint::lint` for the batch.
+    pub fn write_dry_run(
+        &self,
+        bucket: &str,
+        measurements: &[Measurement],
+    ) -> Result<Vec<lint::Warning>, InfluxError> {
+        BucketName::try_from(bucket)?;
+        self.check_field_types(measurements)?;
+        let measurements = self.check_time_skew(measurements)?;
+        let measurements = self.apply_default_tags(measurements);
+        self.serialize_write(&measurements)?;
+        Ok(lint::lint(&measurements))
+    }
 
-        let url = format!("{}/api/v2/query?org={}", self.url, self.org);
+    /// Serialize the requests of a write, grouped by precision, with a request per lane and the
+    /// measurements it contains.
+    fn serialize_write<'a>(
+        &self,
+        measurements: &'a [Measurement],
+    ) -> Result<Vec<(Precision, Vec<WriteRequest<'a>>)>, InfluxError> {
+        let mut batches: Vec<(Precision, Vec<&Measurement>)> = Vec::new();
+        for measurement in measurements {
+            let precision = measurement.precision();
+            match batches.iter_mut().find(|(p, _)| *p == precision) {
+                Some((_, batch)) => batch.push(measurement),
+                None => batches.push((precision, vec![measurement])),
+            }
+        }
 
-        let request = isahc::Request::builder()
-            .uri(&url)
-            .method("POST")
-            .header("Authorization", format!("Token {}", &self.key))
-            .header("Content-Type", "application/vnd.flux")
-            .header("Accept", "application/csv")
-            .body(payload)?;
+        let mut requests = Vec::new();
+        for (precision, batch) in batches {
+            let lanes = if self.write_lanes == 1 {
+                vec![batch]
+            } else {
+                lanes::split_into_lanes(&batch, self.write_lanes)
+            };
+            let payloads = lanes
+                .into_iter()
+                .map(|lane| {
+                    let payload = self.serialize_batch(&lane, precision)?;
+                    Ok((lane, payload))
+                })
+                .collect::<Result<_, InfluxError>>()?;
+            requests.push((precision, payloads));
+        }
+        Ok(requests)
+    }
 
-        let mut response = self.http_client.send_async(request).await?;
+    /// Fail with the type conflicts of a batch, if configured to check them.
+    fn check_field_types(&self, measurements: &[Measurement]) -> Result<(), InfluxError> {
+        if !self.check_field_types {
+            return Ok(());
+        }
+        let conflicts = lint::type_conflicts(measurements);
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(InfluxError::FieldTypeConflict(conflicts))
+        }
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await?;
-            return Err(InfluxError::NonSuccessResponse(status, body));
+    /// Add the configured default tags to measurements that don't have them, only cloning the
+    /// batch if there are any.
+    fn apply_default_tags<'a>(
+        &self,
+        mut measurements: Cow<'a, [Measurement]>,
+    ) -> Cow<'a, [Measurement]> {
+        if self.default_tags.is_empty() {
+            return measurements;
         }
+        for measurement in measurements.to_mut() {
+            for (name, value) in &self.default_tags {
+                if measurement.tag(name).is_none() {
+                    measurement.add_tag(name.clone(), value.clone());
+                }
+            }
+        }
+        measurements
+    }
 
-        let body = response.text().await?;
+    /// Apply the configured maximum time skew to a batch, only cloning it if any measurement has
+    /// to be corrected.
+    fn check_time_skew<'a>(
+        &self,
+        measurements: &'a [Measurement],
+    ) -> Result<Cow<'a, [Measurement]>, InfluxError> {
+        let (max_skew, policy) = match &self.max_time_skew {
+            Some(max_time_skew) => max_time_skew,
+            None => return Ok(Cow::Borrowed(measurements)),
+        };
+        let max_skew = max_skew.as_nanos() as i128;
+        let now = datetime::now_nanos();
 
-        let lines: Vec<String> = body.lines().map(|l| l.trim().to_owned()).collect();
-        let tables: Vec<_> = lines
-            .split(|t| t.is_empty())
-            .filter(|t| !t.is_empty())
-            .map(|t| t.join("\n"))
-            .collect();
+        let mut measurements = Cow::Borrowed(measurements);
+        for i in 0..measurements.len() {
+            let measurement = &measurements[i];
+            let skew = match measurement.timestamp_nanos {
+                Some(timestamp_nanos) => timestamp_nanos - now,
+                // The server assigns the timestamp, so it can't be skewed
+                None => continue,
+            };
+            if skew.abs() <= max_skew {
+                continue;
+            }
+            let corrected = match policy {
+                TimeSkewPolicy::Reject => None,
+                TimeSkewPolicy::Correct(hook) => hook(measurement, now),
+            };
+            match corrected {
+                Some(timestamp_nanos) => {
+                    measurements.to_mut()[i].timestamp_nanos = Some(timestamp_nanos)
+                }
+                None => {
+                    return Err(InfluxError::TimeSkew(
+                        measurement.measurement_name.clone(),
+                        skew,
+                    ))
+                }
+            }
+        }
+        Ok(measurements)
+    }
+
+    /// Send a request serialized by `serialize_write`.
+    async fn write_with_precision(
+        &self,
+        bucket: &str,
+        measurements: Vec<&Measurement>,
+        precision: Precision,
+        payload: String,
+    ) -> Result<(), InfluxError> {
+        let mut request = self.write_http_request(bucket, precision, payload)?;
+        request
+            .extensions_mut()
+            .insert(audit::AuditPoints(measurements.len()));
+        let result = self.send(request).await;
+        self.lock_stats()
+            .record(measurements.iter().copied(), result.is_ok());
+        result?;
+
+        if let Some(schema) = &self.schema {
+            let mut schema = schema.lock().unwrap_or_else(PoisonError::into_inner);
+            for measurement in &measurements {
+                schema.observe(measurement);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_http_request(
+        &self,
+        bucket: &str,
+        precision: Precision,
+        payload: String,
+    ) -> Result<isahc::Request<String>, InfluxError> {
+        let path = api_path(
+            "/api/v2/write",
+            &[
+                ("org", &self.org),
+                ("bucket", bucket),
+                ("precision", precision.as_str()),
+            ],
+        );
+        Ok(self
+            .request("POST", format!("{}{}", self.url, path))
+            .body(payload)?)
+    }
 
-        let mut records = Vec::new();
-        for table in tables {
-            let mut reader = csv::Reader::from_reader(table.as_bytes());
-            for result in reader.deserialize() {
-                let mut record: HashMap<String, String> = result?;
-                record.remove("");
-                records.push(record);
+    /// Serialize a batch into the body of a write request, applying the configured maximum line
+    /// length and batch size.
+    fn serialize_batch(
+        &self,
+        measurements: &[&Measurement],
+        precision: Precision,
+    ) -> Result<String, InfluxError> {
+        let mut payload = String::new();
+        for (i, measurement) in measurements.iter().enumerate() {
+            if i > 0 {
+                payload.push('\n');
+            }
+            let line_start = payload.len();
+            measurement
+                .write_prefixed_line_protocol(
+                    &mut payload,
+                    precision,
+                    &self.measurement_prefix,
+                    &self.line_protocol_options,
+                )
+                .map_err(|e| {
+                    InfluxError::ControlCharacter(
+                        e.measurement().to_string(),
+                        e.field().to_string(),
+                    )
+                })?;
+            let line_length = payload.len() - line_start;
+            if self.max_line_length.is_some_and(|max| line_length > max) {
+                return Err(InfluxError::LineTooLong(
+                    measurement.name().to_string(),
+                    line_length,
+                ));
             }
+            if i == 0 {
+                // Assume the rest of the lines are about as long as the first one.
+                payload.reserve((payload.len() + 1) * (measurements.len() - 1));
+            }
+        }
+        if self.max_batch_size.is_some_and(|max| payload.len() > max) {
+            return Err(InfluxError::BatchTooLarge(payload.len()));
         }
+        Ok(payload)
+    }
+
+    /// The number of points written and rejected through this client so far, per measurement
+    /// name.
+    pub fn stats(&self) -> WriteStats {
+        self.lock_stats().clone()
+    }
 
+    fn lock_stats(&self) -> std::sync::MutexGuard<'_, WriteStats> {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The schema of everything successfully written through this client so far, if schema
+    /// learning was enabled with `InfluxClientBuilder::learn_schema`.
+    pub fn learned_schema(&self) -> Option<WriteSchema> {
+        self.schema.as_ref().map(|schema| {
+            schema
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        })
+    }
+
+    pub async fn query(&self, query: Query) -> Result<InfluxQueryResponse, InfluxError> {
+        Ok(split_profiles(self.cached_query(query).await?).records)
+    }
+
+    /// Like `query`, but also returns the tables of the profilers enabled with
+    /// `QueryOptions::profilers`, separately from the data.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Profiler, Query, QueryOptions};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = InfluxClient::builder(
+    ///     "http://localhost:8086".to_string(),
+    ///     "key".to_string(),
+    ///     "org".to_string(),
+    /// )
+    /// .query_options(QueryOptions::new().profilers(&[Profiler::Query, Profiler::Operator]))
+    /// .build()?;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -1h)");
+    /// let response = client.query_profiled(query).await?;
+    /// for profile in &response.profiles {
+    ///     println!("{:?}", profile);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_profiled(&self, query: Query) -> Result<ProfiledResponse, InfluxError> {
+        Ok(split_profiles(self.cached_query(query).await?))
+    }
+
+    /// Like `query`, but with the records grouped by the name of the result they belong to, as
+    /// named by `yield` (see `Query::yield_as`), for scripts with several results. Records of
+    /// results without a name are under `_result`.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"data = from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .statement("data")
+    ///     .then("mean()")
+    ///     .yield_as("mean")
+    ///     .statement("data")
+    ///     .then("max()")
+    ///     .yield_as("max");
+    /// let results = client.query_yields(query).await?;
+    /// println!("mean: {:?}, max: {:?}", results.get("mean"), results.get("max"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_yields(
+        &self,
+        query: Query,
+    ) -> Result<BTreeMap<String, InfluxQueryResponse>, InfluxError> {
+        Ok(group_by_result(self.query(query).await?))
+    }
+
+    /// Like `query`, but always sends the query to the server, for queries that have to see new
+    /// data whatever the query cache holds.
+    async fn query_uncached(&self, query: &Query) -> Result<InfluxQueryResponse, InfluxError> {
+        let body = self.query_body(query).await?;
+        Ok(split_profiles(parse_query_response(&body, &self.query_options)?).records)
+    }
+
+    async fn cached_query(&self, query: Query) -> Result<InfluxQueryResponse, InfluxError> {
+        let (ttl, cache) = match &self.query_cache {
+            Some((ttl, cache)) => (*ttl, cache),
+            None => {
+                let body = self.query_body(&query).await?;
+                return parse_query_response(&body, &self.query_options);
+            }
+        };
+
+        let cached = cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&query)
+            .filter(|(at, _)| at.elapsed() < ttl)
+            .map(|(_, records)| records.clone());
+        if let Some(records) = cached {
+            return Ok(records);
+        }
+
+        let body = self.query_body(&query).await?;
+        let records = parse_query_response(&body, &self.query_options)?;
+        let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+        cache.retain(|_, (at, _)| at.elapsed() < ttl);
+        cache.insert(query, (Instant::now(), records.clone()));
         Ok(records)
     }
+
+    /// Like `query`, with `params` sent alongside the query, which it can reference as
+    /// `params.name` instead of having the values interpolated into the script. Parameters are
+    /// supported by InfluxDB Cloud.
+    ///
+    /// Times and durations are sent as RFC3339 and Flux duration strings, so the query has to
+    /// convert them with `time(v: params.name)` and `duration(v: params.name)`. Results are never
+    /// cached, even with `InfluxClientBuilder::query_cache`.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{flux::FluxValue, InfluxClient, Query};
+    /// # use std::collections::HashMap;
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new("from(bucket: params.bucket)")
+    ///     .then("range(start: -1h)")
+    ///     .then("filter(fn: (r) => r.host == params.host)");
+    /// let params = HashMap::from([
+    ///     ("bucket".to_string(), FluxValue::from("example_bucket")),
+    ///     ("host".to_string(), FluxValue::from("web-1")),
+    /// ]);
+    /// let records = client.query_with_params(query, params).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_with_params(
+        &self,
+        query: Query,
+        params: HashMap<String, FluxValue>,
+    ) -> Result<InfluxQueryResponse, InfluxError> {
+        let payload = query_request_body(&query, &params, &HashMap::new(), &self.query_options)?;
+        let body = self.query_request(payload, "application/json").await?;
+        let records = parse_query_response(&body, &self.query_options)?;
+        Ok(split_profiles(records).records)
+    }
+
+    /// Like `query`, with `variables` bound in an `extern` block sent alongside the query, as
+    /// the official clients do. The query references them by name, as if the script started
+    /// with `option name = value` for each of them, so values such as bucket names and time
+    /// ranges don't have to be interpolated into the script. Results are never cached, even with
+    /// `InfluxClientBuilder::query_cache`.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{flux::FluxValue, InfluxClient, Query};
+    /// # use std::{collections::HashMap, time::Duration};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new("from(bucket: bucket)").then("range(start: -since)");
+    /// let variables = HashMap::from([
+    ///     ("bucket".to_string(), FluxValue::from("example_bucket")),
+    ///     ("since".to_string(), FluxValue::from(Duration::from_secs(3600))),
+    /// ]);
+    /// let records = client.query_with_extern(query, variables).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_with_extern(
+        &self,
+        query: Query,
+        variables: HashMap<String, FluxValue>,
+    ) -> Result<InfluxQueryResponse, InfluxError> {
+        let payload = query_request_body(&query, &HashMap::new(), &variables, &self.query_options)?;
+        let body = self.query_request(payload, "application/json").await?;
+        let records = parse_query_response(&body, &self.query_options)?;
+        Ok(split_profiles(records).records)
+    }
+
+    /// Like `query`, but distinguishes a query that matched nothing from one that returned rows.
+    ///
+    /// Errors reported by the server in the body of a successful response are returned as
+    /// `InfluxError::QueryError`, as they are by `query`.
+    pub async fn query_outcome(&self, query: Query) -> Result<QueryOutcome, InfluxError> {
+        let records = self.query(query).await?;
+        Ok(if records.is_empty() {
+            QueryOutcome::Empty
+        } else {
+            QueryOutcome::Rows(records)
+        })
+    }
+
+    /// Run a query that returns a single record, and parse its `_value` column, such as the
+    /// result of `count()` or `last()` over a single series.
+    ///
+    /// Returns `InfluxError::UnexpectedShape` if the query returns no or several records, the
+    /// record has no `_value` column, or its value can't be parsed into `T`.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -5m)")
+    ///     .filter_measurement("cpu")
+    ///     .filter_field("usage")
+    ///     .then("group()")
+    ///     .then("mean()");
+    /// let usage: f64 = client.query_scalar(query).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_scalar<T: FromStr>(&self, query: Query) -> Result<T, InfluxError> {
+        let record = single_record(self.query(query).await?)?;
+        let value = record.get("_value").ok_or_else(|| {
+            InfluxError::UnexpectedShape("the record has no '_value' column".to_string())
+        })?;
+        value.parse().map_err(|_| {
+            InfluxError::UnexpectedShape(format!(
+                "invalid value '{}', expected {}",
+                value,
+                std::any::type_name::<T>()
+            ))
+        })
+    }
+
+    /// Run a query that returns a single record, and deserialize it into `T`, with the values
+    /// of columns parsed into the types of the corresponding fields. Empty values deserialize as
+    /// `None`, and columns without a corresponding field are ignored.
+    ///
+    /// Returns `InfluxError::UnexpectedShape` if the query returns no or several records, or the
+    /// record can't be deserialized into `T`.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// #[derive(serde::Deserialize)]
+    /// struct Status {
+    ///     host: String,
+    ///     #[serde(rename = "_value")]
+    ///     up: bool,
+    /// }
+    ///
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -5m)")
+    ///     .filter_measurement("status")
+    ///     .filter_tag("host", "web-1")
+    ///     .then("last()");
+    /// let status: Status = client.query_one(query).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_one<T: serde::de::DeserializeOwned>(
+        &self,
+        query: Query,
+    ) -> Result<T, InfluxError> {
+        let record = single_record(self.query(query).await?)?;
+        records::from_record(record).map_err(|e| InfluxError::UnexpectedShape(e.to_string()))
+    }
+
+    /// Run a query and deserialize each record into `T`, like `query_one` does for a single
+    /// record. Use serde attributes to map columns such as `_time` and `_value` to fields.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// #[derive(serde::Deserialize)]
+    /// struct Usage {
+    ///     #[serde(rename = "_time")]
+    ///     time: String,
+    ///     host: String,
+    ///     #[serde(rename = "_value")]
+    ///     usage: f64,
+    /// }
+    ///
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_measurement("cpu")
+    ///     .filter_field("usage");
+    /// let usages: Vec<Usage> = client.query_into(query).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_into<T: serde::de::DeserializeOwned>(
+        &self,
+        query: Query,
+    ) -> Result<Vec<T>, InfluxError> {
+        self.query(query)
+            .await?
+            .into_iter()
+            .map(|record| {
+                records::from_record(record)
+                    .map_err(|e| InfluxError::UnexpectedShape(e.to_string()))
+            })
+            .collect()
+    }
+
+    /// Like `query`, but with the records pivoted into one record per row with a column per
+    /// field, see `pivot_records`. This does on the client what `Query::pivot` does on the
+    /// server.
+    pub async fn query_pivoted(&self, query: Query) -> Result<InfluxQueryResponse, InfluxError> {
+        Ok(records::pivot_records(self.query(query).await?))
+    }
+
+    /// Run a query and return the status and body of the response as they are, without parsing
+    /// the body or checking the status, such as for writing the result to a file or forwarding
+    /// it in an HTTP response.
+    ///
+    /// `accept` is sent as the `Accept` header, such as `application/csv`.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -1h)");
+    /// let (status, body) = client.query_raw_response(query, "application/csv").await?;
+    /// if status.is_success() {
+    ///     std::fs::write("export.csv", body)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_raw_response(
+        &self,
+        query: Query,
+        accept: &str,
+    ) -> Result<(isahc::http::StatusCode, Vec<u8>), InfluxError> {
+        let (payload, content_type) = self.query_payload(&query)?;
+        let request = self.query_http_request(payload, content_type, accept)?;
+        let mut response = self.send_unchecked(request).await?;
+        Ok((response.status(), response.bytes().await?))
+    }
+
+    /// Estimate the size of the result of a query, without running it in full.
+    ///
+    /// The query is run over the last 1% of the time window of its `range` stage, and the
+    /// number of rows and bytes returned is extrapolated to the whole window. This requires the
+    /// bounds of the range to be absolute times, unix timestamps, relative durations or `now()`.
+    pub async fn estimate(&self, query: Query) -> Result<QueryEstimate, InfluxError> {
+        self.estimate_with_sample(query, 0.01).await
+    }
+
+    /// Like `estimate`, but with a custom fraction (between 0 and 1) of the time window to sample.
+    pub async fn estimate_with_sample(
+        &self,
+        query: Query,
+        sample_fraction: f64,
+    ) -> Result<QueryEstimate, InfluxError> {
+        if !(sample_fraction > 0.0 && sample_fraction <= 1.0) {
+            return Err(InfluxError::InvalidQuery(format!(
+                "sample fraction must be in (0, 1], got {}",
+                sample_fraction
+            )));
+        }
+
+        let now = datetime::now_nanos();
+        let (start, stop) = query.range_arguments().ok_or_else(|| {
+            InfluxError::InvalidQuery("query has no range with a start".to_string())
+        })?;
+        let start = datetime::parse_flux_time(start, now).ok_or_else(|| {
+            InfluxError::InvalidQuery(format!("unsupported range start: '{}'", start))
+        })?;
+        let stop = match stop {
+            Some(stop) => datetime::parse_flux_time(stop, now).ok_or_else(|| {
+                InfluxError::InvalidQuery(format!("unsupported range stop: '{}'", stop))
+            })?,
+            None => now,
+        };
+        if stop <= start {
+            return Err(InfluxError::InvalidQuery(
+                "range stop must be after start".to_string(),
+            ));
+        }
+
+        let window = stop - start;
+        let sample_window = ((window as f64 * sample_fraction) as i128).clamp(1, window);
+        let sample_start = stop - sample_window;
+        let sample_query = query.with_range(
+            datetime::format_rfc3339(sample_start),
+            datetime::format_rfc3339(stop),
+        );
+
+        let body = self.query_body(&sample_query).await?;
+        let sampled_rows = parse_query_response(&body, &self.query_options)?.len();
+        let sampled_bytes = body.len();
+        let sample_fraction = sample_window as f64 / window as f64;
+
+        Ok(QueryEstimate {
+            sample_fraction,
+            sampled_rows,
+            sampled_bytes,
+            estimated_rows: (sampled_rows as f64 / sample_fraction).round() as u64,
+            estimated_bytes: (sampled_bytes as f64 / sample_fraction).round() as u64,
+        })
+    }
+
+    /// Poll a query for new records, as a simple alternative to a live subscription.
+    ///
+    /// Every `interval`, `query_template` is called with the start of the time window to query
+    /// (as an RFC3339 timestamp, usable directly as a Flux time literal) and the resulting query is
+    /// run. The first window starts `interval` ago, and every following window starts at the
+    /// latest `_time` seen so far. Records that were already yielded are skipped, so the stream
+    /// only contains each record once.
+    ///
+    /// If a query fails the error is yielded, and polling continues with the same window.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # use std::time::Duration;
+    /// # use futures_lite::StreamExt;
+    /// # async fn example(client: InfluxClient) {
+    /// let tail = client.tail(
+    ///     |start| {
+    ///         Query::new(r#"from(bucket: "example_bucket")"#)
+    ///             .then(format!("range(start: {})", start))
+    ///             .then(r#"filter(fn: (r) => r["_measurement"] == "m1")"#)
+    ///     },
+    ///     Duration::from_secs(10),
+    /// );
+    /// futures_lite::pin!(tail);
+    /// while let Some(record) = tail.next().await {
+    ///     println!("{:?}", record);
+    /// }
+    /// # }
+    /// ```
+    pub fn tail<'a, F>(
+        &'a self,
+        query_template: F,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<HashMap<String, String>, InfluxError>> + 'a
+    where
+        F: FnMut(&str) -> Query + 'a,
+    {
+        let start = datetime::now_nanos() - interval.as_nanos() as i128;
+        let state = (TailState::new(start), query_template, true);
+        stream::unfold(
+            state,
+            move |(mut state, mut query_template, first)| async move {
+                if !first && state.pending.is_empty() {
+                    Delay::new(interval).await;
+                }
+                loop {
+                    if let Some(record) = state.pending.pop_front() {
+                        return Some((Ok(record), (state, query_template, false)));
+                    }
+                    let query = query_template(&state.start());
+                    // A poll that finds nothing new repeats the same query, which has to reach
+                    // the server to see records written since.
+                    match self.query_uncached(&query).await {
+                        Ok(records) => state.push_response(records),
+                        Err(e) => return Some((Err(e), (state, query_template, false))),
+                    }
+                    if state.pending.is_empty() {
+                        Delay::new(interval).await;
+                    }
+                }
+            },
+        )
+    }
+
+    /// Send a request to a JSON endpoint of the InfluxDB API, returning the response body.
+    async fn api_request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<String, InfluxError> {
+        let builder = self
+            .request(method, format!("{}{}", self.url, path))
+            .header("Accept", "application/json");
+        let request = match body {
+            Some(body) => builder
+                .header("Content-Type", "application/json")
+                .body(body)?,
+            None => builder.body(String::new())?,
+        };
+
+        self.send(request).await
+    }
+
+    /// Start a request with the credentials of the client.
+    fn request(&self, method: &str, url: String) -> isahc::http::request::Builder {
+        let builder = isahc::Request::builder()
+            .uri(url)
+            .method(method)
+            .header("Authorization", format!("Token {}", &self.key));
+        match &self.proxy_authorization {
+            Some(credentials) => builder.header("Proxy-Authorization", credentials),
+            None => builder,
+        }
+    }
+
+    /// Send a request, returning the response body if the response was successful.
+    async fn send(&self, request: isahc::Request<String>) -> Result<String, InfluxError> {
+        let mut response = self.send_streaming(request).await?;
+        read_body(&mut response).await
+    }
+
+    /// Like `send`, but returns the response of a successful request without reading its body.
+    async fn send_streaming(
+        &self,
+        request: isahc::Request<String>,
+    ) -> Result<isahc::Response<isahc::AsyncBody>, InfluxError> {
+        let mut response = self.send_unchecked(request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = read_body(&mut response).await?;
+            return Err(InfluxError::NonSuccessResponse(status, body));
+        }
+        Ok(response)
+    }
+
+    /// Like `send_streaming`, but returns the response whatever its status.
+    async fn send_unchecked(
+        &self,
+        request: isahc::Request<String>,
+    ) -> Result<isahc::Response<isahc::AsyncBody>, InfluxError> {
+        Ok(match &self.audit_log {
+            Some(audit_log) => {
+                let record = audit_log.start(&request);
+                let response = self.http_client.send_async(request).await;
+                audit_log.finish(record, response.as_ref().ok());
+                response?
+            }
+            None => self.http_client.send_async(request).await?,
+        })
+    }
+
+    async fn query_body(&self, query: &Query) -> Result<String, InfluxError> {
+        let (payload, content_type) = self.query_payload(query)?;
+        self.query_request(payload, content_type).await
+    }
+
+    /// The body of a request for `query` and its content type, which is plain Flux unless it
+    /// needs the query options.
+    fn query_payload(&self, query: &Query) -> Result<(String, &'static str), InfluxError> {
+        if self.query_options == QueryOptions::default() {
+            return Ok((query.to_string(), "application/vnd.flux"));
+        }
+        let payload =
+            query_request_body(query, &HashMap::new(), &HashMap::new(), &self.query_options)?;
+        Ok((payload, "application/json"))
+    }
+
+    async fn query_request(
+        &self,
+        payload: String,
+        content_type: &str,
+    ) -> Result<String, InfluxError> {
+        let request = self.query_http_request(payload, content_type, "application/csv")?;
+        self.send(request).await
+    }
+
+    fn query_http_request(
+        &self,
+        payload: String,
+        content_type: &str,
+        accept: &str,
+    ) -> Result<isahc::Request<String>, InfluxError> {
+        let path = api_path("/api/v2/query", &[("org", &self.org)]);
+        Ok(self
+            .request("POST", format!("{}{}", self.url, path))
+            .header("Content-Type", content_type)
+            .header("Accept", accept)
+            .body(payload)?)
+    }
+}
+
+/// The JSON body of a query request with parameters (see `InfluxClient::query_with_params`),
+/// variables bound in the `extern` block (see `InfluxClient::query_with_extern`) and the
+/// dialect of the response, if it isn't the default one.
+fn query_request_body(
+    query: &Query,
+    params: &HashMap<String, FluxValue>,
+    variables: &HashMap<String, FluxValue>,
+    dialect: &QueryOptions,
+) -> Result<String, InfluxError> {
+    use json::Value;
+
+    fn object(members: Vec<(&str, Value)>) -> Value {
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+    fn typed(node_type: &str, value: (&str, Value)) -> Value {
+        object(vec![("type", Value::String(node_type.to_string())), value])
+    }
+    fn finite(name: &str, v: f64) -> Result<f64, InfluxError> {
+        if v.is_finite() {
+            Ok(v)
+        } else {
+            Err(InfluxError::InvalidQuery(format!(
+                "value of '{}' is not a finite number",
+                name
+            )))
+        }
+    }
+
+    let mut request = vec![
+        ("query", Value::String(dialect.profiled(query).to_string())),
+        ("type", Value::String("flux".to_string())),
+    ];
+
+    if !params.is_empty() {
+        let mut members = Vec::new();
+        for (name, value) in params.iter().collect::<BTreeMap<_, _>>() {
+            let value = match value {
+                FluxValue::String(v) => Value::String(v.clone()),
+                FluxValue::Float(v) => Value::Number(finite(name, *v)?.to_string()),
+                FluxValue::Int(v) => Value::Number(v.to_string()),
+                FluxValue::Bool(v) => Value::Bool(*v),
+                FluxValue::Duration(_) | FluxValue::Time(_) => Value::String(value.to_string()),
+            };
+            members.push((name.clone(), value));
+        }
+        request.push(("params", Value::Object(members)));
+    }
+
+    if !variables.is_empty() {
+        let mut body = Vec::new();
+        for (name, value) in variables.iter().collect::<BTreeMap<_, _>>() {
+            let literal = match value {
+                FluxValue::String(v) => typed("StringLiteral", ("value", Value::String(v.clone()))),
+                FluxValue::Float(v) => typed(
+                    "FloatLiteral",
+                    ("value", Value::Number(finite(name, *v)?.to_string())),
+                ),
+                // Integers are strings in the AST, so that they don't lose precision.
+                FluxValue::Int(v) => {
+                    typed("IntegerLiteral", ("value", Value::String(v.to_string())))
+                }
+                FluxValue::Bool(v) => typed("BooleanLiteral", ("value", Value::Bool(*v))),
+                FluxValue::Duration(nanos) => {
+                    let duration = typed(
+                        "DurationLiteral",
+                        (
+                            "values",
+                            Value::Array(vec![object(vec![
+                                ("magnitude", Value::Number(nanos.abs().to_string())),
+                                ("unit", Value::String("ns".to_string())),
+                            ])]),
+                        ),
+                    );
+                    if *nanos < 0 {
+                        object(vec![
+                            ("type", Value::String("UnaryExpression".to_string())),
+                            ("operator", Value::String("-".to_string())),
+                            ("argument", duration),
+                        ])
+                    } else {
+                        duration
+                    }
+                }
+                FluxValue::Time(_) => typed(
+                    "DateTimeLiteral",
+                    ("value", Value::String(value.to_string())),
+                ),
+            };
+            body.push(object(vec![
+                ("type", Value::String("OptionStatement".to_string())),
+                (
+                    "assignment",
+                    object(vec![
+                        ("type", Value::String("VariableAssignment".to_string())),
+                        (
+                            "id",
+                            typed("Identifier", ("name", Value::String(name.clone()))),
+                        ),
+                        ("init", literal),
+                    ]),
+                ),
+            ]));
+        }
+        request.push((
+            "extern",
+            object(vec![
+                ("type", Value::String("File".to_string())),
+                ("body", Value::Array(body)),
+            ]),
+        ));
+    }
+
+    if *dialect != QueryOptions::default() {
+        if let Some(now) = dialect.now_json() {
+            request.push(("now", now));
+        }
+        request.push(("dialect", dialect.to_json()));
+    }
+
+    Ok(json::to_string(&object(request))?)
+}
+
+/// `path` with the URL-encoded query string of `params`.
+pub(crate) fn api_path(path: &str, params: &[(&str, &str)]) -> String {
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    query.extend_pairs(params);
+    format!("{}?{}", path, query.finish())
+}
+
+/// Read the body of a response as text.
+async fn read_body(
+    response: &mut isahc::Response<isahc::AsyncBody>,
+) -> Result<String, InfluxError> {
+    let bytes = response.bytes().await?;
+    Ok(decode_body(bytes))
+}
+
+/// Decode a response body as UTF-8, ignoring a byte order mark.
+///
+/// Some proxies respond with other encodings (e.g. latin-1 error pages), in which case invalid
+/// sequences are replaced and a warning is logged, rather than failing the whole request.
+fn decode_body(bytes: Vec<u8>) -> String {
+    const BOM: &[u8] = b"\xEF\xBB\xBF";
+    let bytes = match bytes.strip_prefix(BOM) {
+        Some(stripped) => stripped.to_vec(),
+        None => bytes,
+    };
+    match String::from_utf8(bytes) {
+        Ok(body) => body,
+        Err(e) => {
+            log::warn!(
+                "response body is not valid UTF-8 ({}), decoding it lossily",
+                e.utf8_error()
+            );
+            String::from_utf8_lossy(e.as_bytes()).into_owned()
+        }
+    }
+}
+
+/// The result of a query that ran successfully, see `InfluxClient::query_outcome`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryOutcome {
+    /// The query matched no data.
+    Empty,
+    /// The query returned at least one row.
+    Rows(InfluxQueryResponse),
+}
+
+/// The result of a query with profilers, see `InfluxClient::query_profiled`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfiledResponse {
+    /// The records of the data tables
+    pub records: InfluxQueryResponse,
+    /// The records of the profiler tables
+    pub profiles: InfluxQueryResponse,
+}
+
+/// Separate the records of profiler tables, which have the result name `_profiler`, from the
+/// records of data tables.
+fn split_profiles(records: InfluxQueryResponse) -> ProfiledResponse {
+    let (profiles, records) = records
+        .into_iter()
+        .partition(|record| record.get("result").map(String::as_str) == Some("_profiler"));
+    ProfiledResponse { records, profiles }
+}
+
+/// Group records by their `result` column, the name of the result they belong to.
+fn group_by_result(records: InfluxQueryResponse) -> BTreeMap<String, InfluxQueryResponse> {
+    let mut results: BTreeMap<String, InfluxQueryResponse> = BTreeMap::new();
+    for record in records {
+        let name = record
+            .get("result")
+            .filter(|name| !name.is_empty())
+            .map_or("_result", String::as_str)
+            .to_string();
+        results.entry(name).or_default().push(record);
+    }
+    results
+}
+
+/// The only record of `records`, or `InfluxError::UnexpectedShape` if there are none or several.
+pub(crate) fn single_record(
+    mut records: InfluxQueryResponse,
+) -> Result<HashMap<String, String>, InfluxError> {
+    match records.len() {
+        1 => Ok(records.remove(0)),
+        n => Err(InfluxError::UnexpectedShape(format!(
+            "expected a single record, got {}",
+            n
+        ))),
+    }
+}
+
+fn parse_query_response(
+    body: &str,
+    dialect: &QueryOptions,
+) -> Result<InfluxQueryResponse, InfluxError> {
+    let mut records = Vec::new();
+    parse_query_records(body, dialect, |record| {
+        records.push(record);
+        Ok(())
+    })?;
+    Ok(records)
+}
+
+/// Parse the records of a query response one by one, passing each to `on_record`.
+fn parse_query_records(
+    body: &str,
+    dialect: &QueryOptions,
+    mut on_record: impl FnMut(HashMap<String, String>) -> Result<(), InfluxError>,
+) -> Result<(), InfluxError> {
+    let comment_prefix = dialect.comment_prefix_str();
+    let mut headers: Option<csv::StringRecord> = None;
+    let mut error_headers: Option<csv::StringRecord> = None;
+    for row in csv_rows(body) {
+        if row.is_empty() {
+            // Tables are separated by empty rows.
+            headers = None;
+            continue;
+        }
+        let row = parse_csv_row(row, dialect.delimiter_byte())?;
+        if let Some(error_headers) = &error_headers {
+            // Errors that occur after the response status was sent are reported as a table with
+            // `error` and `reference` columns.
+            let column = |name| {
+                let index = error_headers.iter().position(|h| h == name)?;
+                row.get(index).map(str::to_string)
+            };
+            let message = column("error").unwrap_or_default();
+            let reference = column("reference").filter(|r| !r.is_empty());
+            return Err(InfluxError::QueryError(message, reference));
+        }
+        let first = row.get(0).unwrap_or_default();
+        if !comment_prefix.is_empty() && first.starts_with(comment_prefix) {
+            // Annotation rows are skipped, and precede the header row of a table.
+            headers = None;
+            continue;
+        }
+        match &headers {
+            Some(headers) => {
+                let record = headers
+                    .iter()
+                    .zip(row.iter())
+                    .filter(|(column, _)| !column.is_empty())
+                    .map(|(column, value)| (column.to_string(), value.to_string()))
+                    .collect();
+                on_record(record)?;
+            }
+            None if is_error_table(&row) => error_headers = Some(row),
+            None => headers = Some(row),
+        }
+    }
+
+    Ok(())
+}
+
+/// Split a CSV body into rows at the line breaks outside of quoted values, without the line
+/// breaks, so values keep their embedded line breaks and surrounding spaces.
+pub(crate) fn csv_rows(body: &str) -> impl Iterator<Item = &str> {
+    let mut rest = body;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut quoted = false;
+        let end = rest
+            .bytes()
+            .position(|b| {
+                if b == b'"' {
+                    quoted = !quoted;
+                }
+                b == b'\n' && !quoted
+            })
+            .unwrap_or(rest.len());
+        let row = &rest[..end];
+        rest = rest.get(end + 1..).unwrap_or_default();
+        Some(row.strip_suffix('\r').unwrap_or(row))
+    })
+}
+
+/// Parse a single row of CSV, as split by `csv_rows`.
+pub(crate) fn parse_csv_row(row: &str, delimiter: u8) -> Result<csv::StringRecord, InfluxError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(row.as_bytes());
+    Ok(reader.records().next().transpose()?.unwrap_or_default())
+}
+
+/// Whether `headers` are the columns of the table that errors are reported in.
+fn is_error_table(headers: &csv::StringRecord) -> bool {
+    let mut headers: Vec<_> = headers.iter().filter(|h| !h.is_empty()).collect();
+    headers.sort_unstable();
+    headers == ["error", "reference"]
+}
+
+/// An extrapolated estimate of the size of a query result, see `InfluxClient::estimate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryEstimate {
+    /// The fraction of the time window that was queried.
+    pub sample_fraction: f64,
+    /// The number of records returned for the sampled window.
+    pub sampled_rows: usize,
+    /// The size of the response body for the sampled window.
+    pub sampled_bytes: usize,
+    /// The estimated number of records for the whole time window.
+    pub estimated_rows: u64,
+    /// The estimated size of the response body for the whole time window.
+    pub estimated_bytes: u64,
 }
 
 pub struct InfluxClientBuilder {
     url: String,
     key: String,
     org: String,
+    learn_schema: bool,
+    max_time_skew: Option<(Duration, TimeSkewPolicy)>,
+    max_line_length: Option<usize>,
+    max_batch_size: Option<usize>,
+    query_cache_ttl: Option<Duration>,
+    default_tags: BTreeMap<String, String>,
+    measurement_prefix: String,
+    write_lanes: usize,
+    proxy_authorization: Option<String>,
+    line_protocol_options: LineProtocolOptions,
+    audit_log: Option<AuditLog>,
+    check_field_types: bool,
+    query_options: QueryOptions,
 }
 
 impl InfluxClientBuilder {
     fn new(url: String, key: String, org: String) -> Self {
-        Self { url, key, org }
+        Self {
+            url,
+            key,
+            org,
+            learn_schema: false,
+            max_time_skew: None,
+            max_line_length: None,
+            max_batch_size: None,
+            query_cache_ttl: None,
+            default_tags: BTreeMap::new(),
+            measurement_prefix: String::new(),
+            write_lanes: 1,
+            proxy_authorization: None,
+            line_protocol_options: LineProtocolOptions::default(),
+            audit_log: None,
+            check_field_types: false,
+            query_options: QueryOptions::default(),
+        }
     }
 
+    /// Check the timestamps of written measurements against the local time, and apply `policy`
+    /// to those that deviate by more than `max_skew` in either direction.
+    ///
+    /// This catches devices with broken clocks before their points end up scattered across
+    /// decades.
+    pub fn max_time_skew(mut self, max_skew: Duration, policy: TimeSkewPolicy) -> Self {
+        self.max_time_skew = Some((max_skew, policy));
+        self
+    }
+
+    /// Fail writes with `InfluxError::LineTooLong` if the line protocol of a measurement is longer
+    /// than `max_line_length` bytes, without writing anything.
+    pub fn max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = Some(max_line_length);
+        self
+    }
+
+    /// Fail writes with `InfluxError::BatchTooLarge` if the body of a write request would be
+    /// larger than `max_batch_size` bytes. Batches with measurements in different precisions are
+    /// sent as one request per precision, and each request is checked on its own.
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = Some(max_batch_size);
+        self
+    }
+
+    /// Add a tag to every written measurement that doesn't already have a tag with that name,
+    /// such as `host` or `region`.
+    pub fn default_tag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.default_tags.insert(name.into(), value.into());
+        self
+    }
+
+    /// Prepend `prefix` to the name of every written measurement, such as `myapp_`, so that
+    /// applications sharing a bucket can't collide.
+    ///
+    /// The prefix is only applied to the written line protocol: errors, write stats and the
+    /// learned schema use the names the measurements were built with, and queries are not
+    /// rewritten.
+    pub fn measurement_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.measurement_prefix = prefix.into();
+        self
+    }
+
+    /// Split every write into up to `lanes` requests that are sent concurrently, defaults to 1.
+    ///
+    /// Measurements are assigned to lanes by a stable hash of their series (name and tags), so
+    /// the points of a series are always sent through the same lane in order, while different
+    /// series are written in parallel. A lane of 0 is treated as 1.
+    pub fn write_lanes(mut self, lanes: usize) -> Self {
+        self.write_lanes = lanes.max(1);
+        self
+    }
+
+    /// Send HTTP Basic credentials in the `Proxy-Authorization` header of every request, for
+    /// proxies in front of InfluxDB with their own authentication. The token of the client is
+    /// still sent in the `Authorization` header, so the proxy has to authenticate requests by
+    /// `Proxy-Authorization` and pass `Authorization` on.
+    pub fn proxy_basic_auth(
+        mut self,
+        username: impl AsRef<str>,
+        password: impl AsRef<str>,
+    ) -> Self {
+        self.proxy_authorization = Some(auth::basic_auth_header(
+            username.as_ref(),
+            password.as_ref(),
+        ));
+        self
+    }
+
+    /// Set how written measurements are formatted as line protocol, see `LineProtocolOptions`.
+    pub fn line_protocol_options(mut self, options: LineProtocolOptions) -> Self {
+        self.line_protocol_options = options;
+        self
+    }
+
+    /// Write a line of JSON to `writer` for every request sent by the client, with the
+    /// operation (method and path), target organization and bucket, duration, outcome, response
+    /// status, request id and number of written points. Payloads are never logged.
+    ///
+    /// ## Example
+    /// ```json
+    /// {"time":"2021-06-01T11:16:05.684Z","operation":"POST /api/v2/write","org":"org","bucket":"bucket","duration_ms":12,"outcome":"success","status":204,"request_id":null,"points":100}
+    /// ```
+    pub fn audit_log(mut self, writer: impl std::io::Write + Send + 'static) -> Self {
+        self.audit_log = Some(AuditLog::new(writer));
+        self
+    }
+
+    /// Fail writes with `InfluxError::FieldTypeConflict` if the batch has fields written with
+    /// different types (see `lint::type_conflicts`), without writing anything. Otherwise
+    /// InfluxDB writes the points matching the type it sees first, and rejects the rest with an
+    /// error that only names the first conflict.
+    pub fn check_field_types(mut self, check_field_types: bool) -> Self {
+        self.check_field_types = check_field_types;
+        self
+    }
+
+    /// Set the CSV dialect of query responses, see `QueryOptions`. With the default options
+    /// queries are sent as plain Flux, and otherwise as JSON with a `dialect` object.
+    pub fn query_options(mut self, options: QueryOptions) -> Self {
+        self.query_options = options;
+        self
+    }
+
+    /// Cache the results of `InfluxClient::query` for `ttl`, so that repeating an equivalent
+    /// query (see `Query`) within that time doesn't hit the server.
+    ///
+    /// Relative time ranges such as `range(start: -1h)` are not resolved, so a cached result can
+    /// be up to `ttl` older than the range suggests. `InfluxClient::tail` and
+    /// `InfluxClient::query_windowed` always query the server, as do the methods that return the
+    /// response as it is, such as `InfluxClient::query_raw_response`.
+    pub fn query_cache(mut self, ttl: Duration) -> Self {
+        self.query_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Record the measurements, tags and fields (with types) of everything written through the
+    /// client, retrievable with `InfluxClient::learned_schema`.
+    pub fn learn_schema(mut self, learn_schema: bool) -> Self {
+        self.learn_schema = learn_schema;
+        self
+    }
+
+    /// Build the client, failing if the organization name, the token or a default tag is invalid.
     pub fn build(self) -> Result<InfluxClient, InfluxClientBuilderError> {
-        Ok(InfluxClient::new(
-            self.url,
-            self.key,
-            self.org,
-            isahc::HttpClient::new().unwrap(),
-        ))
+        OrgName::try_from(self.org.as_str())?;
+        Token::try_from(self.key.as_str())?;
+        if let Some((name, _)) = self
+            .default_tags
+            .iter()
+            .find(|(name, value)| name.is_empty() || value.is_empty())
+        {
+            return Err(InfluxClientBuilderError::InvalidDefaultTag(name.clone()));
+        }
+        Ok(InfluxClient {
+            url: self.url,
+            key: self.key,
+            org: self.org,
+            http_client: isahc::HttpClient::new().unwrap(),
+            schema: self
+                .learn_schema
+                .then(|| Mutex::new(WriteSchema::default())),
+            stats: Mutex::new(WriteStats::default()),
+            max_time_skew: self.max_time_skew,
+            max_line_length: self.max_line_length,
+            max_batch_size: self.max_batch_size,
+            query_cache: self
+                .query_cache_ttl
+                .map(|ttl| (ttl, Mutex::new(HashMap::new()))),
+            default_tags: self.default_tags,
+            measurement_prefix: self.measurement_prefix,
+            write_lanes: self.write_lanes,
+            proxy_authorization: self.proxy_authorization,
+            line_protocol_options: self.line_protocol_options,
+            audit_log: self.audit_log,
+            check_field_types: self.check_field_types,
+            query_options: self.query_options,
+        })
     }
 }
 
@@ -126,7 +1443,37 @@ pub enum InfluxError {
     IsahcError(isahc::Error),
     IoError(std::io::Error),
     CsvError(csv::Error),
+    JsonError(json::JsonError),
     NonSuccessResponse(isahc::http::StatusCode, String),
+    InvalidQuery(String),
+    /// The timestamp of the named measurement deviates from the local time by the contained
+    /// number of nanoseconds, which is more than the configured maximum.
+    TimeSkew(String, i128),
+    /// The named bucket exists, but with the contained retention period (`None` meaning
+    /// infinite) instead of the expected one.
+    BucketRetentionMismatch(String, Option<Duration>),
+    /// The server responded with something unexpected.
+    InvalidResponse(String),
+    /// An InfluxQL statement failed, with the contained error message from the server.
+    InfluxQlError(String),
+    /// A Flux query failed, with the contained error message and error reference from the server.
+    QueryError(String, Option<String>),
+    /// A bucket name, organization name or token is invalid.
+    InvalidName(InvalidNameError),
+    /// The line protocol of the named measurement is the contained number of bytes long, which
+    /// is more than the configured maximum.
+    LineTooLong(String, usize),
+    /// The body of a write request would be the contained number of bytes long, which is more
+    /// than the configured maximum.
+    BatchTooLarge(usize),
+    /// Fields of the batch are written with different types.
+    FieldTypeConflict(Vec<lint::TypeConflict>),
+    /// The string field (second) of the named measurement (first) has control characters, which
+    /// the configured `LineProtocolOptions` reject.
+    ControlCharacter(String, String),
+    /// The result of a query doesn't have the shape the caller expected, as described by the
+    /// contained message.
+    UnexpectedShape(String),
 }
 
 impl Error for InfluxError {}
@@ -149,6 +1496,18 @@ impl From<isahc::http::Error> for InfluxError {
     }
 }
 
+impl From<InvalidNameError> for InfluxError {
+    fn from(err: InvalidNameError) -> Self {
+        Self::InvalidName(err)
+    }
+}
+
+impl From<json::JsonError> for InfluxError {
+    fn from(err: json::JsonError) -> Self {
+        Self::JsonError(err)
+    }
+}
+
 impl From<csv::Error> for InfluxError {
     fn from(err: csv::Error) -> Self {
         Self::CsvError(err)
@@ -162,9 +1521,48 @@ impl Display for InfluxError {
                 format!("non-success response: '{}', body: '{}'", status, body)
             }
             InfluxError::CsvError(err) => format!("csv error: '{}'", err),
+            InfluxError::JsonError(err) => format!("json error: '{}'", err),
             InfluxError::HttpError(err) => format!("http error: '{}'", err),
             InfluxError::IsahcError(err) => format!("isahc error: '{}'", err),
             InfluxError::IoError(err) => format!("io error: '{}'", err),
+            InfluxError::InvalidQuery(reason) => format!("invalid query: '{}'", reason),
+            InfluxError::TimeSkew(name, skew) => format!(
+                "timestamp of measurement '{}' deviates {}ns from local time",
+                name, skew
+            ),
+            InfluxError::BucketRetentionMismatch(name, retention) => format!(
+                "bucket '{}' exists with a different retention: {:?}",
+                name, retention
+            ),
+            InfluxError::InvalidResponse(reason) => format!("invalid response: '{}'", reason),
+            InfluxError::InfluxQlError(message) => format!("influxql error: '{}'", message),
+            InfluxError::QueryError(message, None) => format!("query error: '{}'", message),
+            InfluxError::QueryError(message, Some(reference)) => {
+                format!("query error: '{}' (reference {})", message, reference)
+            }
+            InfluxError::InvalidName(e) => e.to_string(),
+            InfluxError::LineTooLong(name, length) => format!(
+                "line protocol of measurement '{}' is too long ({} bytes)",
+                name, length
+            ),
+            InfluxError::BatchTooLarge(size) => {
+                format!("write batch is too large ({} bytes)", size)
+            }
+            InfluxError::ControlCharacter(name, field) => format!(
+                "field '{}' of measurement '{}' has control characters",
+                field, name
+            ),
+            InfluxError::FieldTypeConflict(conflicts) => format!(
+                "conflicting field types: {}",
+                conflicts
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+            InfluxError::UnexpectedShape(message) => {
+                format!("unexpected query result: {}", message)
+            }
         };
 
         write!(f, "{}", output)
@@ -172,76 +1570,632 @@ impl Display for InfluxError {
 }
 
 #[derive(Debug)]
-pub enum InfluxClientBuilderError {}
+pub enum InfluxClientBuilderError {
+    /// The organization name or the token is invalid.
+    InvalidName(InvalidNameError),
+    /// The named default tag has an empty name or value.
+    InvalidDefaultTag(String),
+}
 
 impl Error for InfluxClientBuilderError {}
 
 impl Display for InfluxClientBuilderError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "error building influx client")
+        match self {
+            InfluxClientBuilderError::InvalidName(e) => {
+                write!(f, "error building influx client: {}", e)
+            }
+            InfluxClientBuilderError::InvalidDefaultTag(name) => write!(
+                f,
+                "error building influx client: default tag '{}' is empty",
+                name
+            ),
+        }
+    }
+}
+
+impl From<InvalidNameError> for InfluxClientBuilderError {
+    fn from(err: InvalidNameError) -> Self {
+        Self::InvalidName(err)
     }
 }
 
-pub(crate) mod query {
-    use std::fmt::Display;
-    #[derive(Debug, Clone, PartialEq)]
-    pub struct Query {
-        lines: Vec<String>,
+pub(crate) mod analyze;
+mod audit;
+mod auth;
+mod buckets;
+mod codegen;
+mod counts;
+pub(crate) mod dialect;
+pub(crate) mod downsample;
+pub(crate) mod export;
+pub(crate) mod influxql;
+pub(crate) mod json;
+mod lanes;
+mod latest;
+pub(crate) mod names;
+pub(crate) mod prepared;
+pub(crate) mod query;
+pub(crate) mod records;
+pub(crate) mod schema;
+pub(crate) mod series;
+pub(crate) mod spill;
+mod sql;
+pub(crate) mod stats;
+pub(crate) mod suggestions;
+mod tail;
+pub(crate) mod tasks;
+pub(crate) mod typed;
+pub(crate) mod v1;
+mod windowed;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ControlCharacters;
+
+    fn client(policy: TimeSkewPolicy) -> InfluxClient {
+        InfluxClient::builder(
+            "http://localhost:8086".to_string(),
+            "key".to_string(),
+            "org".to_string(),
+        )
+        .max_time_skew(Duration::from_secs(60), policy)
+        .build()
+        .unwrap()
     }
 
-    impl Query {
-        pub fn new(line: impl Into<String>) -> Self {
-            let lines = vec![line.into()];
-            Self { lines }
-        }
+    #[test]
+    fn decode_bodies() {
+        assert_eq!(
+            decode_body(b"\xEF\xBB\xBF,result,table".to_vec()),
+            ",result,table"
+        );
+        assert_eq!(decode_body(b"caf\xE9".to_vec()), "caf\u{FFFD}");
+        assert_eq!(decode_body("ok".into()), "ok");
+
+        let records = parse_query_response(
+            &decode_body(b"\xEF\xBB\xBF,result,table,_value\n,_result,0,1\n".to_vec()),
+            &QueryOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(records[0].get("result"), Some(&"_result".to_string()));
+    }
+
+    #[test]
+    fn query_errors() {
+        assert_eq!(
+            parse_query_response("", &QueryOptions::default()).unwrap(),
+            Vec::new()
+        );
+        assert_eq!(
+            parse_query_response(",result,table\r\n\r\n", &QueryOptions::default()).unwrap(),
+            Vec::new()
+        );
+
+        let body = ",error,reference\r\n,\"runtime error: division by zero\",897\r\n\r\n";
+        assert!(matches!(
+            parse_query_response(body, &QueryOptions::default()),
+            Err(InfluxError::QueryError(message, Some(reference)))
+                if message == "runtime error: division by zero" && reference == "897"
+        ));
+        assert!(matches!(
+            parse_query_response(",error,reference\n,timeout,\n", &QueryOptions::default()),
+            Err(InfluxError::QueryError(message, None)) if message == "timeout"
+        ));
+    }
+
+    #[test]
+    fn untrimmed_values() {
+        let body = ",result,table,_value,note\r\n\
+            ,_result,0,1,\"first line\r\n\r\nsecond line\"\r\n\
+            ,_result,0,2,\" padded \"\r\n\
+            \r\n\
+            ,result,table,host\r\n\
+            ,_result,1,\"a\r\n\"\r\n";
+        let records = parse_query_response(body, &QueryOptions::default()).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["note"], "first line\r\n\r\nsecond line");
+        assert_eq!(records[1]["note"], " padded ");
+        assert_eq!(records[2]["host"], "a\r\n");
+        assert_eq!(records[2].get("note"), None);
+    }
+
+    #[test]
+    fn query_dialect() {
+        use dialect::{Annotation, DateTimeFormat};
+
+        let dialect = QueryOptions::new()
+            .annotations(&[Annotation::Datatype, Annotation::Group])
+            .delimiter(b';')
+            .date_time_format(DateTimeFormat::Rfc3339Nano);
+        let query = Query::new(r#"from(bucket: "b")"#);
+        assert_eq!(
+            query_request_body(&query, &HashMap::new(), &HashMap::new(), &dialect).unwrap(),
+            r##"{"query":"from(bucket: \"b\")","type":"flux","dialect":{"annotations":["datatype","group"],"header":true,"delimiter":";","commentPrefix":"#","dateTimeFormat":"RFC3339Nano"}}"##
+        );
+
+        let dialect = QueryOptions::new().now(std::time::UNIX_EPOCH);
+        assert_eq!(
+            query_request_body(&query, &HashMap::new(), &HashMap::new(), &dialect).unwrap(),
+            r##"{"query":"from(bucket: \"b\")","type":"flux","now":"1970-01-01T00:00:00Z","dialect":{"annotations":[],"header":true,"delimiter":",","commentPrefix":"#","dateTimeFormat":"RFC3339"}}"##
+        );
+
+        let dialect = QueryOptions::new()
+            .annotations(&[Annotation::Datatype, Annotation::Group])
+            .delimiter(b';');
+        let body = "#datatype;string;long;double\r\n#group;false;false;false\r\n;result;table;_value\r\n;_result;0;1.5\r\n\r\n\
+            #datatype;string;long;string\r\n#group;false;false;true\r\n;result;table;host\r\n;_result;1;a\r\n";
+        let records = parse_query_response(body, &dialect).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["_value"], "1.5");
+        assert_eq!(records[1]["host"], "a");
+    }
+
+    #[test]
+    fn query_profilers() {
+        use dialect::Profiler;
+
+        let dialect = QueryOptions::new().profilers(&[Profiler::Query, Profiler::Operator]);
+        let query = Query::new(r#"from(bucket: "b")"#);
+        assert_eq!(
+            query_request_body(&query, &HashMap::new(), &HashMap::new(), &dialect).unwrap(),
+            r##"{"query":"import \"profiler\"\noption profiler.enabledProfilers = [\"query\", \"operator\"]\n\nfrom(bucket: \"b\")","type":"flux","dialect":{"annotations":[],"header":true,"delimiter":",","commentPrefix":"#","dateTimeFormat":"RFC3339"}}"##
+        );
+
+        let body = "result,table,_value\r\n_result,0,1.5\r\n\r\n\
+            result,table,TotalDuration\r\n_profiler,0,1000\r\n";
+        let response = split_profiles(parse_query_response(body, &dialect).unwrap());
+        assert_eq!(response.records.len(), 1);
+        assert_eq!(response.records[0]["_value"], "1.5");
+        assert_eq!(response.profiles.len(), 1);
+        assert_eq!(response.profiles[0]["TotalDuration"], "1000");
+    }
+
+    #[test]
+    fn multiple_yields() {
+        let body = "result,table,_value\r\nmean,0,1.5\r\nmean,1,2.5\r\n\r\n\
+            result,table,_value\r\nmax,0,3\r\n\r\n\
+            table,_value\r\n0,4\r\n";
+        let results = group_by_result(parse_query_response(body, &QueryOptions::new()).unwrap());
+        assert_eq!(
+            results.keys().collect::<Vec<_>>(),
+            ["_result", "max", "mean"]
+        );
+        assert_eq!(results["mean"].len(), 2);
+        assert_eq!(results["max"][0]["_value"], "3");
+        assert_eq!(results["_result"][0]["_value"], "4");
+    }
+
+    #[test]
+    fn size_guards() {
+        let client = InfluxClient::builder(
+            "http://localhost:8086".to_string(),
+            "key".to_string(),
+            "org".to_string(),
+        )
+        .max_line_length(32)
+        .max_batch_size(40)
+        .measurement_prefix("p_")
+        .build()
+        .unwrap();
+        let short = Measurement::builder("m")
+            .field("f", 1)
+            .timestamp_s(1)
+            .build()
+            .unwrap();
+        let long = Measurement::builder("long")
+            .field("f", "x".repeat(32))
+            .timestamp_s(1)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            client
+                .serialize_batch(&[&short, &short], Precision::Seconds)
+                .unwrap(),
+            "p_m f=1i 1\np_m f=1i 1"
+        );
+        assert!(matches!(
+            client.serialize_batch(&[&short, &long], Precision::Seconds),
+            Err(InfluxError::LineTooLong(name, 45)) if name == "long"
+        ));
+        assert!(matches!(
+            client.serialize_batch(&[&short; 5], Precision::Seconds),
+            Err(InfluxError::BatchTooLarge(54))
+        ));
+
+        let rejecting = InfluxClient::builder(
+            "http://localhost:8086".to_string(),
+            "key".to_string(),
+            "org".to_string(),
+        )
+        .line_protocol_options(
+            LineProtocolOptions::new().control_characters(ControlCharacters::Reject),
+        )
+        .build()
+        .unwrap();
+        let multiline = Measurement::builder("m")
+            .field("log", "a\nb")
+            .build()
+            .unwrap();
+        assert!(matches!(
+            rejecting.serialize_batch(&[&short, &multiline], Precision::Seconds),
+            Err(InfluxError::ControlCharacter(name, field)) if name == "m" && field == "log"
+        ));
+    }
+
+    #[test]
+    fn invalid_requests_fail_whole_write() {
+        // Nothing listens on port 1, so a request that is sent fails with another error.
+        let client = InfluxClient::builder(
+            "http://127.0.0.1:1".to_string(),
+            "key".to_string(),
+            "org".to_string(),
+        )
+        .max_line_length(32)
+        .write_lanes(2)
+        .build()
+        .unwrap();
+        let measurements = [
+            Measurement::builder("short")
+                .field("f", 1)
+                .timestamp_s(1)
+                .build()
+                .unwrap(),
+            Measurement::builder("long")
+                .field("f", "x".repeat(32))
+                .timestamp_ms(1)
+                .build()
+                .unwrap(),
+        ];
+
+        assert!(matches!(
+            client.write_dry_run("bucket", &measurements),
+            Err(InfluxError::LineTooLong(name, _)) if name == "long"
+        ));
+        let result = futures_lite::future::block_on(client.write("bucket", &measurements));
+        assert!(matches!(
+            result,
+            Err(InfluxError::LineTooLong(name, _)) if name == "long"
+        ));
+        assert_eq!(client.stats().measurement("short").rejected, 1);
+        assert_eq!(client.stats().measurement("long").rejected, 1);
+    }
+
+    #[test]
+    fn default_tags() {
+        let client = InfluxClient::builder(
+            "http://localhost:8086".to_string(),
+            "key".to_string(),
+            "org".to_string(),
+        )
+        .default_tag("host", "web-1")
+        .default_tag("region", "eu")
+        .build()
+        .unwrap();
+        let measurements = vec![Measurement::builder("m")
+            .tag("region", "us")
+            .field("f", 1)
+            .build()
+            .unwrap()];
+
+        let tagged = client.apply_default_tags(Cow::Borrowed(&measurements));
+        assert_eq!(
+            tagged[0].tags().collect::<Vec<_>>(),
+            vec![("host", "web-1"), ("region", "us")]
+        );
+
+        let result = InfluxClient::builder(
+            "http://localhost:8086".to_string(),
+            "key".to_string(),
+            "org".to_string(),
+        )
+        .default_tag("host", "")
+        .build();
+        assert!(matches!(
+            result,
+            Err(InfluxClientBuilderError::InvalidDefaultTag(name)) if name == "host"
+        ));
+    }
+
+    /// Accept one connection on a local port, respond with `status`, and return the request
+    /// head that was received.
+    /// Serve a single request with a response with `status` and `body`, returning the URL to
+    /// send it to and a handle that returns the head and body of the request.
+    pub(super) fn serve_once(
+        status: &str,
+        body: &str,
+    ) -> (String, std::thread::JoinHandle<(String, String)>) {
+        use std::io::{BufRead, BufReader, Read, Write};
 
-        /// Create a query from a raw string.
-        ///
-        /// ## Example
-        /// ```rust
-        /// # use influxrs::Query;
-        /// let query = Query::raw(r#"from(bucket: "server")
-        ///     |> range(start: v.timeRangeStart, stop: v.timeRangeStop)
-        ///     |> filter(fn: (r) => r["_measurement"] == "example_measurement")
-        ///     |> keys()"#);
-        /// ```
-        pub fn raw(query: impl Into<String>) -> Self {
-            let lines = query
-                .into()
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                if reader.read_line(&mut head).unwrap() == 0 {
+                    break;
+                }
+            }
+            let content_length = head
                 .lines()
-                .map(|l| match l.strip_prefix("|>") {
-                    Some(stripped) => stripped.trim().to_owned(),
-                    None => l.trim().to_owned(),
+                .find_map(|l| {
+                    l.to_ascii_lowercase()
+                        .strip_prefix("content-length:")
+                        .map(|v| v.trim().parse().unwrap())
                 })
-                .collect();
-            Self { lines }
-        }
+                .unwrap_or(0);
+            let mut body = String::new();
+            reader
+                .by_ref()
+                .take(content_length)
+                .read_to_string(&mut body)
+                .unwrap();
+            reader.into_inner().write_all(response.as_bytes()).unwrap();
+            (head, body)
+        });
+        (url, server)
+    }
 
-        /// Append a line to the query.
-        ///
-        /// ## Example
-        /// ```rust
-        /// # use influxrs::Query;
-        /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
-        ///     .then(r#"filter(fn: (r) => r["_measurement"] == "example_measurement")"#);
-        /// ```
-        pub fn then(mut self, line: impl Into<String>) -> Self {
-            self.lines.push(line.into());
-            self
-        }
+    #[test]
+    fn query_params() {
+        let query = Query::new("from(bucket: params.bucket)").then("range(start: -1h)");
+        let params = HashMap::from([
+            ("bucket".to_string(), FluxValue::from("b \"1\"")),
+            ("limit".to_string(), FluxValue::from(10)),
+            ("threshold".to_string(), FluxValue::from(0.5)),
+            ("enabled".to_string(), FluxValue::from(true)),
+            (
+                "every".to_string(),
+                FluxValue::from(Duration::from_secs(60)),
+            ),
+        ]);
+        assert_eq!(
+            query_request_body(&query, &params, &HashMap::new(), &QueryOptions::default()).unwrap(),
+            r#"{"query":"from(bucket: params.bucket)\n |> range(start: -1h)","type":"flux","params":{"bucket":"b \"1\"","enabled":true,"every":"1m","limit":10,"threshold":0.5}}"#
+        );
+
+        let params = HashMap::from([("nan".to_string(), FluxValue::from(f64::NAN))]);
+        assert!(matches!(
+            query_request_body(&query, &params, &HashMap::new(), &QueryOptions::default()),
+            Err(InfluxError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            query_request_body(&query, &HashMap::new(), &params, &QueryOptions::default()),
+            Err(InfluxError::InvalidQuery(_))
+        ));
     }
 
-    impl Display for Query {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(
-                f,
-                "{}",
-                self.lines
-                    .iter()
-                    .map(|l| l.to_string())
-                    .collect::<Vec<_>>()
-                    .join("\n |> ")
+    #[test]
+    fn query_extern() {
+        let query = Query::new("from(bucket: bucket)").then("range(start: start, stop: -since)");
+        let variables = HashMap::from([
+            ("bucket".to_string(), FluxValue::from("b")),
+            ("since".to_string(), FluxValue::Duration(-1_000)),
+            ("start".to_string(), FluxValue::Time(0)),
+            ("n".to_string(), FluxValue::from(5)),
+        ]);
+        assert_eq!(
+            query_request_body(
+                &query,
+                &HashMap::new(),
+                &variables,
+                &QueryOptions::default()
+            )
+            .unwrap(),
+            concat!(
+                r#"{"query":"from(bucket: bucket)\n |> range(start: start, stop: -since)","type":"flux","extern":{"type":"File","body":["#,
+                r#"{"type":"OptionStatement","assignment":{"type":"VariableAssignment","id":{"type":"Identifier","name":"bucket"},"init":{"type":"StringLiteral","value":"b"}}},"#,
+                r#"{"type":"OptionStatement","assignment":{"type":"VariableAssignment","id":{"type":"Identifier","name":"n"},"init":{"type":"IntegerLiteral","value":"5"}}},"#,
+                r#"{"type":"OptionStatement","assignment":{"type":"VariableAssignment","id":{"type":"Identifier","name":"since"},"init":{"type":"UnaryExpression","operator":"-","argument":{"type":"DurationLiteral","values":[{"magnitude":1000,"unit":"ns"}]}}}},"#,
+                r#"{"type":"OptionStatement","assignment":{"type":"VariableAssignment","id":{"type":"Identifier","name":"start"},"init":{"type":"DateTimeLiteral","value":"1970-01-01T00:00:00Z"}}}"#,
+                "]}}"
             )
+        );
+    }
+
+    #[test]
+    fn proxy_basic_auth() {
+        let measurements = [Measurement::builder("m")
+            .field("field1", 1)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap()];
+
+        let (url, server) = serve_once("204 No Content", "");
+        let client = InfluxClient::builder(url, "key".to_string(), "org".to_string())
+            .proxy_basic_auth("user", "pass")
+            .build()
+            .unwrap();
+        futures_lite::future::block_on(client.write("bucket", &measurements)).unwrap();
+        let head = server.join().unwrap().0.to_ascii_lowercase();
+        assert!(head.starts_with("post /api/v2/write?org=org&bucket=bucket&precision=ms "));
+        assert!(head.contains("\r\nauthorization: token key\r\n"));
+        assert!(head.contains("\r\nproxy-authorization: basic dxnlcjpwyxnz\r\n"));
+
+        let (url, server) = serve_once("407 Proxy Authentication Required", "");
+        let client = InfluxClient::builder(url, "key".to_string(), "org".to_string())
+            .build()
+            .unwrap();
+        let result =
+            futures_lite::future::block_on(client.query(Query::new(r#"from(bucket: "b")"#)));
+        assert!(matches!(
+            result,
+            Err(InfluxError::NonSuccessResponse(status, _)) if status.as_u16() == 407
+        ));
+        let head = server.join().unwrap().0.to_ascii_lowercase();
+        assert!(head.contains("\r\nauthorization: token key\r\n"));
+        assert!(!head.contains("proxy-authorization"));
+    }
+
+    #[test]
+    fn estimate_extrapolates_sample() {
+        let body = ",result,table,_value\r\n,_result,0,1\r\n,_result,0,2\r\n";
+        let (url, server) = serve_once("200 OK", body);
+        let client = InfluxClient::builder(url, "key".to_string(), "org".to_string())
+            .build()
+            .unwrap();
+        let query = Query::new(r#"from(bucket: "b")"#)
+            .then("range(start: 1000, stop: 2000)")
+            .then("count()");
+        let estimate =
+            futures_lite::future::block_on(client.estimate_with_sample(query, 0.1)).unwrap();
+        let (_, sent) = server.join().unwrap();
+        assert_eq!(
+            sent,
+            Query::new(r#"from(bucket: "b")"#)
+                .then("range(start: 1970-01-01T00:31:40Z, stop: 1970-01-01T00:33:20Z)")
+                .then("count()")
+                .to_string()
+        );
+        assert_eq!(estimate.sample_fraction, 0.1);
+        assert_eq!(estimate.sampled_rows, 2);
+        assert_eq!(estimate.sampled_bytes, body.len());
+        assert_eq!(estimate.estimated_rows, 20);
+        assert_eq!(estimate.estimated_bytes, body.len() as u64 * 10);
+
+        let unbounded = Query::new(r#"from(bucket: "b")"#).then("range(stop: 2000)");
+        assert!(matches!(
+            futures_lite::future::block_on(client.estimate(unbounded)),
+            Err(InfluxError::InvalidQuery(_))
+        ));
+        let reversed = Query::new(r#"from(bucket: "b")"#).then("range(start: 2000, stop: 1000)");
+        assert!(matches!(
+            futures_lite::future::block_on(client.estimate(reversed)),
+            Err(InfluxError::InvalidQuery(_))
+        ));
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
     }
+
+    #[test]
+    fn audit_log() {
+        let measurements = [Measurement::builder("m")
+            .field("secret", "payload")
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap()];
+        let log = SharedBuffer::default();
+
+        let (url, server) = serve_once("204 No Content", "");
+        let client = InfluxClient::builder(url, "key".to_string(), "org".to_string())
+            .audit_log(log.clone())
+            .build()
+            .unwrap();
+        futures_lite::future::block_on(client.write("bucket", &measurements)).unwrap();
+        server.join().unwrap();
+        // The server only accepted one connection, so this fails without a response.
+        let result = futures_lite::future::block_on(client.query(Query::new("buckets()")));
+        assert!(result.is_err());
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[0].contains(r#""operation":"POST /api/v2/write","org":"org","bucket":"bucket""#)
+        );
+        assert!(
+            lines[0].contains(r#""outcome":"success","status":204,"request_id":null,"points":1}"#)
+        );
+        assert!(lines[1].contains(r#""operation":"POST /api/v2/query","org":"org","bucket":null"#));
+        assert!(lines[1].contains(r#""outcome":"failure","status":null"#));
+        assert!(!log.contains("payload") && !log.contains("buckets()"));
+    }
+
+    #[test]
+    fn invalid_names() {
+        let build = |key: &str, org: &str| {
+            InfluxClient::builder("http://localhost:8086".to_string(), key.into(), org.into())
+                .build()
+        };
+        assert!(build("key", "org").is_ok());
+        assert!(matches!(
+            build("key", ""),
+            Err(InfluxClientBuilderError::InvalidName(_))
+        ));
+        assert!(matches!(
+            build("a key", "org"),
+            Err(InfluxClientBuilderError::InvalidName(_))
+        ));
+
+        let client = client(TimeSkewPolicy::Reject);
+        let result = futures_lite::future::block_on(client.write("a\"b", &[]));
+        assert!(matches!(result, Err(InfluxError::InvalidName(_))));
+        // System buckets can be written to, just not created.
+        let result = futures_lite::future::block_on(client.write("_tasks", &[]));
+        assert!(!matches!(result, Err(InfluxError::InvalidName(_))));
+    }
+
+    #[test]
+    fn time_skew() {
+        let now = Measurement::builder("now").field("f", 1).build().unwrap();
+        let old = Measurement::builder("old")
+            .field("f", 1)
+            .timestamp_s(0)
+            .build()
+            .unwrap();
+
+        let measurements = vec![now.clone()];
+        let checked = client(TimeSkewPolicy::Reject)
+            .check_time_skew(&measurements)
+            .unwrap();
+        assert!(matches!(checked, Cow::Borrowed(_)));
+
+        let measurements = vec![now.clone(), old];
+        assert!(matches!(
+            client(TimeSkewPolicy::Reject).check_time_skew(&measurements),
+            Err(InfluxError::TimeSkew(name, skew)) if name == "old" && skew < 0
+        ));
+
+        let correct = client(TimeSkewPolicy::Correct(Arc::new(|_, now| Some(now))));
+        let checked = correct.check_time_skew(&measurements).unwrap();
+        assert_eq!(checked[0], now);
+        assert!(checked[1].timestamp_nanos > Some(0));
+    }
+
+    #[test]
+    fn field_type_conflicts() {
+        let measurements = vec![
+            Measurement::builder("m").field("f", 1).build().unwrap(),
+            Measurement::builder("m").field("f", 1.0).build().unwrap(),
+        ];
+        assert!(client(TimeSkewPolicy::Reject)
+            .write_dry_run("bucket", &measurements)
+            .is_ok());
+
+        let client = InfluxClient::builder(
+            "http://localhost:8086".to_string(),
+            "key".to_string(),
+            "org".to_string(),
+        )
+        .check_field_types(true)
+        .build()
+        .unwrap();
+        let result = futures_lite::future::block_on(client.write("bucket", &measurements));
+        assert!(matches!(
+            result,
+            Err(InfluxError::FieldTypeConflict(conflicts)) if conflicts.len() == 1
+        ));
+        assert_eq!(client.stats().measurement("m").rejected, 2);
+    }
 }
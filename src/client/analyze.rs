@@ -0,0 +1,101 @@
+//! Validation of Flux scripts through the `/api/v2/query/analyze` API, see
+//! `InfluxClient::analyze_query`.
+
+use super::{json, query::Query, InfluxClient, InfluxError};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Serialize)]
+struct AnalyzeRequest<'a> {
+    query: &'a str,
+    #[serde(rename = "type")]
+    query_type: &'a str,
+}
+
+#[derive(Deserialize)]
+struct AnalyzeResponse {
+    #[serde(default)]
+    errors: Vec<FluxError>,
+}
+
+/// An error in a Flux script, as found by `InfluxClient::analyze_query`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FluxError {
+    /// The line of the error, starting at 1
+    #[serde(default)]
+    pub line: u32,
+    /// The column of the error, starting at 1
+    #[serde(default)]
+    pub column: u32,
+    /// The offset of the error (in characters) from the start of the script
+    #[serde(default)]
+    pub character: u32,
+    pub message: String,
+}
+
+impl Display for FluxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl InfluxClient {
+    /// Check a query for errors without running it, such as syntax errors and calls of unknown
+    /// functions. An empty list means that the query is valid.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -1h");
+    /// for error in client.analyze_query(&query).await? {
+    ///     println!("{}", error);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn analyze_query(&self, query: &Query) -> Result<Vec<FluxError>, InfluxError> {
+        let script = query.to_string();
+        let payload = json::to_string(&AnalyzeRequest {
+            query: &script,
+            query_type: "flux",
+        })?;
+        let request = self
+            .request("POST", format!("{}/api/v2/query/analyze", self.url))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .body(payload)?;
+        let body = self.send(request).await?;
+        let response: AnalyzeResponse = json::from_str(&body)?;
+        Ok(response.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors() {
+        let response: AnalyzeResponse = json::from_str(
+            r#"{"errors": [{"line": 2, "column": 5, "character": 24, "message": "expected RPAREN, got EOF"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            response.errors,
+            vec![FluxError {
+                line: 2,
+                column: 5,
+                character: 24,
+                message: "expected RPAREN, got EOF".to_string(),
+            }]
+        );
+        assert_eq!(
+            response.errors[0].to_string(),
+            "2:5: expected RPAREN, got EOF"
+        );
+
+        let response: AnalyzeResponse = json::from_str("{}").unwrap();
+        assert!(response.errors.is_empty());
+    }
+}
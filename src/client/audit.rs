@@ -0,0 +1,110 @@
+//! A structured log of the API operations performed by a client, see
+//! `InfluxClientBuilder::audit_log`.
+
+use super::json;
+use crate::datetime;
+use serde::Serialize;
+use std::{
+    io::Write,
+    sync::{Mutex, PoisonError},
+    time::Instant,
+};
+
+/// Response headers that InfluxDB and common proxies use to identify a request.
+const REQUEST_ID_HEADERS: &[&str] = &[
+    "request-id",
+    "x-request-id",
+    "x-influxdb-request-id",
+    "trace-id",
+];
+
+/// The number of points written by a request, attached to it as an extension so that it can be
+/// audited.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AuditPoints(pub(crate) usize);
+
+/// Writes one JSON object per line for every request, without any payload contents.
+pub(crate) struct AuditLog {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AuditRecord {
+    /// RFC3339 time the request was sent
+    time: String,
+    /// Method and path of the request, e.g. `POST /api/v2/write`
+    operation: String,
+    org: Option<String>,
+    bucket: Option<String>,
+    duration_ms: u64,
+    /// `success` or `failure`
+    outcome: &'static str,
+    status: Option<u16>,
+    request_id: Option<String>,
+    points: Option<usize>,
+    #[serde(skip)]
+    started: Instant,
+}
+
+impl AuditLog {
+    pub(crate) fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Start the record of a request that is about to be sent.
+    pub(crate) fn start<T>(&self, request: &isahc::Request<T>) -> AuditRecord {
+        let mut org = None;
+        let mut bucket = None;
+        if let Some(query) = request.uri().query() {
+            for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+                match name.as_ref() {
+                    "org" => org = Some(value.into_owned()),
+                    "bucket" | "db" => bucket = Some(value.into_owned()),
+                    _ => {}
+                }
+            }
+        }
+        AuditRecord {
+            time: datetime::format_rfc3339(datetime::now_nanos()),
+            operation: format!("{} {}", request.method(), request.uri().path()),
+            org,
+            bucket,
+            duration_ms: 0,
+            outcome: "failure",
+            status: None,
+            request_id: None,
+            points: request.extensions().get::<AuditPoints>().map(|p| p.0),
+            started: Instant::now(),
+        }
+    }
+
+    /// Finish and write the record of a request, with its response or `None` if it failed before
+    /// a response was received.
+    pub(crate) fn finish(
+        &self,
+        mut record: AuditRecord,
+        response: Option<&isahc::Response<isahc::AsyncBody>>,
+    ) {
+        record.duration_ms = record.started.elapsed().as_millis() as u64;
+        if let Some(response) = response {
+            if response.status().is_success() {
+                record.outcome = "success";
+            }
+            record.status = Some(response.status().as_u16());
+            record.request_id = REQUEST_ID_HEADERS
+                .iter()
+                .find_map(|h| Some(response.headers().get(*h)?.to_str().ok()?.to_string()));
+        }
+
+        let line = match json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => return log::warn!("failed to serialize audit record: {}", e),
+        };
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+            log::warn!("failed to write audit record: {}", e);
+        }
+    }
+}
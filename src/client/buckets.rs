@@ -0,0 +1,181 @@
+//! Bucket management through the `/api/v2/buckets` API.
+
+use super::{api_path, json, names::BucketName, InfluxClient, InfluxError};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, time::Duration};
+
+#[derive(Deserialize)]
+struct Orgs {
+    orgs: Vec<Org>,
+}
+
+#[derive(Deserialize)]
+struct Org {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Buckets {
+    buckets: Vec<Bucket>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Bucket {
+    name: String,
+    #[serde(default)]
+    retention_rules: Vec<RetentionRule>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RetentionRule {
+    #[serde(rename = "type")]
+    rule_type: String,
+    every_seconds: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateBucket<'a> {
+    #[serde(rename = "orgID")]
+    org_id: &'a str,
+    name: &'a str,
+    retention_rules: Vec<RetentionRule>,
+}
+
+impl Bucket {
+    /// The retention period of the bucket, or `None` if data is kept forever.
+    fn retention(&self) -> Option<Duration> {
+        self.retention_rules
+            .iter()
+            .find(|rule| rule.rule_type == "expire" && rule.every_seconds > 0)
+            .map(|rule| Duration::from_secs(rule.every_seconds))
+    }
+}
+
+impl InfluxClient {
+    /// Create a bucket in the organization of the client if it doesn't exist yet.
+    ///
+    /// `retention` is how long data is kept in the bucket, or `None` to keep data forever. If the
+    /// bucket already exists, its retention is checked and `InfluxError::BucketRetentionMismatch`
+    /// is returned if it differs.
+    ///
+    /// Returns `true` if the bucket was created, and `false` if it already existed.
+    pub async fn ensure_bucket(
+        &self,
+        name: &str,
+        retention: Option<Duration>,
+    ) -> Result<bool, InfluxError> {
+        BucketName::try_from(name)?.check_not_reserved()?;
+        let path = api_path("/api/v2/buckets", &[("org", &self.org), ("name", name)]);
+        let existing: Buckets = json::from_str(&self.api_request("GET", &path, None).await?)?;
+        if let Some(bucket) = existing.buckets.iter().find(|b| b.name == name) {
+            let actual = bucket.retention();
+            // The server stores retention in whole seconds.
+            let expected = retention.map(|r| Duration::from_secs(r.as_secs()));
+            return if actual == expected {
+                Ok(false)
+            } else {
+                Err(InfluxError::BucketRetentionMismatch(
+                    name.to_string(),
+                    actual,
+                ))
+            };
+        }
+
+        let path = api_path("/api/v2/orgs", &[("org", &self.org)]);
+        let orgs: Orgs = json::from_str(&self.api_request("GET", &path, None).await?)?;
+        let org = orgs.orgs.first().ok_or_else(|| {
+            InfluxError::InvalidResponse(format!("organization '{}' not found", self.org))
+        })?;
+
+        let body = CreateBucket {
+            org_id: &org.id,
+            name,
+            retention_rules: retention
+                .map(|retention| RetentionRule {
+                    rule_type: "expire".to_string(),
+                    every_seconds: retention.as_secs(),
+                })
+                .into_iter()
+                .collect(),
+        };
+        self.api_request("POST", "/api/v2/buckets", Some(json::to_string(&body)?))
+            .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Precision;
+
+    #[test]
+    fn encoded_paths() {
+        assert_eq!(
+            api_path(
+                "/api/v2/buckets",
+                &[("org", "my org"), ("name", "a&b=c#d+e")]
+            ),
+            "/api/v2/buckets?org=my+org&name=a%26b%3Dc%23d%2Be"
+        );
+    }
+
+    #[test]
+    fn encoded_write_and_query_urls() {
+        let client = InfluxClient::builder(
+            "http://localhost:8086".to_string(),
+            "key".to_string(),
+            "my org".to_string(),
+        )
+        .build()
+        .unwrap();
+
+        let write = client
+            .write_http_request("a&b c#d+e", Precision::Seconds, String::new())
+            .unwrap();
+        assert_eq!(
+            write.uri().to_string(),
+            "http://localhost:8086/api/v2/write?org=my+org&bucket=a%26b+c%23d%2Be&precision=s"
+        );
+        let query = client
+            .query_http_request(String::new(), "application/vnd.flux", "application/csv")
+            .unwrap();
+        assert_eq!(
+            query.uri().to_string(),
+            "http://localhost:8086/api/v2/query?org=my+org"
+        );
+    }
+
+    #[test]
+    fn bucket_retention() {
+        let buckets: Buckets = json::from_str(
+            r#"{"buckets": [
+                {"id": "1", "name": "forever", "retentionRules": []},
+                {"id": "2", "name": "zero", "retentionRules": [{"type": "expire", "everySeconds": 0}]},
+                {"id": "3", "name": "hour", "retentionRules": [{"type": "expire", "everySeconds": 3600, "shardGroupDurationSeconds": 3600}]}
+            ]}"#,
+        )
+        .unwrap();
+        let retentions: Vec<_> = buckets.buckets.iter().map(|b| b.retention()).collect();
+        assert_eq!(
+            retentions,
+            vec![None, None, Some(Duration::from_secs(3600))]
+        );
+
+        let body = CreateBucket {
+            org_id: "abc",
+            name: "hour",
+            retention_rules: vec![RetentionRule {
+                rule_type: "expire".to_string(),
+                every_seconds: 3600,
+            }],
+        };
+        assert_eq!(
+            json::to_string(&body).unwrap(),
+            r#"{"orgID":"abc","name":"hour","retentionRules":[{"type":"expire","everySeconds":3600}]}"#
+        );
+    }
+}
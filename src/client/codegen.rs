@@ -0,0 +1,314 @@
+//! Generating Rust constants and accessors from the schema of a bucket, see
+//! `InfluxClient::bucket_schema` and `WriteSchema::to_rust`.
+
+use super::{names::BucketName, query::Query, schema::WriteSchema, InfluxClient, InfluxError};
+use crate::flux::FluxValue;
+use std::{
+    collections::HashMap, collections::HashSet, convert::TryFrom, fmt::Write, io, path::Path,
+};
+
+/// A stage that adds a `_type` column with the line protocol type of `_value`, which the script
+/// has to import `types` for.
+pub(crate) const TYPE_COLUMN: &str = r#"map(fn: (r) => ({r with _type:
+        if types.isType(v: r._value, type: "float") then "float"
+        else if types.isType(v: r._value, type: "int") then "integer"
+        else if types.isType(v: r._value, type: "uint") then "unsigned"
+        else if types.isType(v: r._value, type: "bool") then "boolean"
+        else "string"}))"#;
+
+/// Columns of query results that are not tags.
+const NON_TAG_COLUMNS: &[&str] = &["result", "table"];
+
+impl InfluxClient {
+    /// Query the measurements, tags and fields (with their types) written to a bucket since
+    /// `start`, which can be any Flux time, such as `-30d` or `2021-06-01T00:00:00Z`.
+    ///
+    /// The schema is read from the last point of every series, so tags and fields that haven't
+    /// been written since `start` are missing.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::InfluxClient;
+    /// # async fn example(client: InfluxClient) -> Result<(), Box<dyn std::error::Error>> {
+    /// let schema = client.bucket_schema("example_bucket", "-30d").await?;
+    /// schema.write_rust("src/schema.rs")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bucket_schema(
+        &self,
+        bucket: &str,
+        start: &str,
+    ) -> Result<WriteSchema, InfluxError> {
+        BucketName::try_from(bucket)?;
+        let query = Query::new(format!("from(bucket: {})", FluxValue::from(bucket)))
+            .import("types")
+            .then(format!("range(start: {})", start))
+            .then("last()")
+            .then(TYPE_COLUMN);
+        let records = self.query(query).await?;
+        Ok(schema_from_records(&records))
+    }
+}
+
+/// Build a schema from the last records of every series in a bucket, with a `_type` column
+/// holding the type of the field.
+fn schema_from_records(records: &[HashMap<String, String>]) -> WriteSchema {
+    let mut schema = WriteSchema::default();
+    for record in records {
+        let (measurement, field) = match (record.get("_measurement"), record.get("_field")) {
+            (Some(measurement), Some(field)) => (measurement, field),
+            _ => continue,
+        };
+        let data_type = match record.get("_type").map(String::as_str) {
+            Some("float") => "float",
+            Some("integer") => "integer",
+            Some("unsigned") => "unsigned",
+            Some("boolean") => "boolean",
+            _ => "string",
+        };
+        let tags = record
+            .keys()
+            .filter(|column| {
+                !column.starts_with('_') && !NON_TAG_COLUMNS.contains(&column.as_str())
+            })
+            .map(String::as_str);
+        schema.observe_field(measurement, tags, field, data_type);
+    }
+    schema
+}
+
+impl WriteSchema {
+    /// Generate a Rust module with constants for the measurement, tag and field names in the
+    /// schema, and typed accessors for reading them from a `Measurement`.
+    ///
+    /// Every measurement gets a module with a `NAME` constant and `tags` and `fields` submodules,
+    /// which hold a constant and an accessor for every tag and field. Names are converted to
+    /// valid identifiers, e.g. the field `usage-user` of the measurement `cpu` becomes
+    /// `cpu::fields::USAGE_USER` and `cpu::fields::usage_user(&measurement)`. Accessors of fields
+    /// that have been written with more than one type return the `Field` itself.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::WriteSchema;
+    /// let code = WriteSchema::default().to_rust();
+    /// assert!(code.starts_with("// Generated by influxrs"));
+    /// ```
+    pub fn to_rust(&self) -> String {
+        let mut out = String::from(
+            "// Generated by influxrs from the schema of a bucket, do not edit.\n\
+             #![allow(dead_code)]\n",
+        );
+        let mut modules = Identifiers::default();
+        for (name, schema) in self.measurements() {
+            out.push('\n');
+            writeln!(out, "/// The `{}` measurement.", name.escape_debug()).unwrap();
+            writeln!(out, "pub mod {} {{", modules.snake(name)).unwrap();
+            writeln!(out, "    pub const NAME: &str = {:?};", name).unwrap();
+
+            out.push_str("\n    pub mod tags {\n");
+            let mut constants = Identifiers::default();
+            let mut functions = Identifiers::default();
+            for tag in schema.tags() {
+                let constant = constants.screaming(tag);
+                writeln!(out, "        pub const {}: &str = {:?};", constant, tag).unwrap();
+                writeln!(
+                    out,
+                    "        pub fn {}(measurement: &influxrs::Measurement) -> Option<&str> {{\n            measurement.tag({})\n        }}",
+                    functions.snake(tag),
+                    constant
+                )
+                .unwrap();
+            }
+            out.push_str("    }\n");
+
+            out.push_str("\n    pub mod fields {\n");
+            let mut constants = Identifiers::default();
+            let mut functions = Identifiers::default();
+            for (field, types) in schema.fields() {
+                let constant = constants.screaming(field);
+                let (return_type, conversion) = match types.as_slice() {
+                    ["float"] => ("Option<f64>", "?.as_f64()"),
+                    ["integer"] => ("Option<i64>", "?.as_i64()"),
+                    ["unsigned"] => ("Option<u64>", "?.as_u64()"),
+                    ["boolean"] => ("Option<bool>", "?.as_bool()"),
+                    ["string"] => ("Option<&str>", "?.as_str()"),
+                    _ => ("Option<&influxrs::Field>", ""),
+                };
+                writeln!(out, "        /// Written as {}.", types.join(", ")).unwrap();
+                writeln!(out, "        pub const {}: &str = {:?};", constant, field).unwrap();
+                writeln!(
+                    out,
+                    "        pub fn {}(measurement: &influxrs::Measurement) -> {} {{\n            measurement.field({}){}\n        }}",
+                    functions.snake(field),
+                    return_type,
+                    constant,
+                    conversion
+                )
+                .unwrap();
+            }
+            out.push_str("    }\n}\n");
+        }
+        out
+    }
+
+    /// Write the module generated by `to_rust` to a file.
+    pub fn write_rust(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_rust())
+    }
+}
+
+/// Converts names to unique Rust identifiers within a scope.
+#[derive(Default)]
+struct Identifiers {
+    used: HashSet<String>,
+}
+
+impl Identifiers {
+    /// A `snake_case` identifier for a module or function.
+    fn snake(&mut self, name: &str) -> String {
+        let ident = identifier(name, false);
+        let ident = match ident.as_str() {
+            "self" | "super" | "crate" => format!("{}_", ident),
+            _ if KEYWORDS.contains(&ident.as_str()) => format!("r#{}", ident),
+            _ => ident,
+        };
+        self.unique(ident)
+    }
+
+    /// A `SCREAMING_SNAKE_CASE` identifier for a constant.
+    fn screaming(&mut self, name: &str) -> String {
+        let ident = identifier(name, true);
+        let ident = if ident == "NAME" {
+            String::from("NAME_")
+        } else {
+            ident
+        };
+        self.unique(ident)
+    }
+
+    fn unique(&mut self, ident: String) -> String {
+        let mut candidate = ident.clone();
+        let mut suffix = 2;
+        while !self.used.insert(candidate.clone()) {
+            candidate = format!("{}_{}", ident, suffix);
+            suffix += 1;
+        }
+        candidate
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+
+/// Replace everything but ASCII letters and digits with `_`, splitting camel case words.
+fn identifier(name: &str, upper: bool) -> String {
+    let mut ident = String::with_capacity(name.len());
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && previous_lower {
+                ident.push('_');
+            }
+            ident.push(if upper {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            });
+            previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else {
+            if !ident.ends_with('_') {
+                ident.push('_');
+            }
+            previous_lower = false;
+        }
+    }
+    let ident = ident.trim_matches('_');
+    match ident.chars().next() {
+        None => String::from("_unnamed"),
+        Some(c) if c.is_ascii_digit() => format!("_{}", ident),
+        Some(_) => ident.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::records::tests::record;
+
+    #[test]
+    fn generate_rust() {
+        let records = vec![
+            record(&[
+                ("result", "_result"),
+                ("table", "0"),
+                ("_measurement", "cpu"),
+                ("_field", "usageUser"),
+                ("_value", "0.5"),
+                ("_type", "float"),
+                ("host-name", "a"),
+            ]),
+            record(&[
+                ("_measurement", "cpu"),
+                ("_field", "type"),
+                ("_value", "x"),
+                ("_type", "string"),
+            ]),
+            record(&[
+                ("_measurement", "cpu"),
+                ("_field", "type"),
+                ("_value", "1"),
+                ("_type", "integer"),
+            ]),
+        ];
+        let schema = schema_from_records(&records);
+        assert_eq!(
+            schema.to_rust(),
+            r#"// Generated by influxrs from the schema of a bucket, do not edit.
+#![allow(dead_code)]
+
+/// The `cpu` measurement.
+pub mod cpu {
+    pub const NAME: &str = "cpu";
+
+    pub mod tags {
+        pub const HOST_NAME: &str = "host-name";
+        pub fn host_name(measurement: &influxrs::Measurement) -> Option<&str> {
+            measurement.tag(HOST_NAME)
+        }
+    }
+
+    pub mod fields {
+        /// Written as integer, string.
+        pub const TYPE: &str = "type";
+        pub fn r#type(measurement: &influxrs::Measurement) -> Option<&influxrs::Field> {
+            measurement.field(TYPE)
+        }
+        /// Written as float.
+        pub const USAGE_USER: &str = "usageUser";
+        pub fn usage_user(measurement: &influxrs::Measurement) -> Option<f64> {
+            measurement.field(USAGE_USER)?.as_f64()
+        }
+    }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        let mut identifiers = Identifiers::default();
+        assert_eq!(identifiers.snake("CPU load"), "cpu_load");
+        assert_eq!(identifiers.snake("cpu_load"), "cpu_load_2");
+        assert_eq!(identifiers.snake("2xx"), "_2xx");
+        assert_eq!(identifiers.snake("self"), "self_");
+        assert_eq!(identifiers.snake("ünïcode"), "n_code");
+        assert_eq!(identifiers.snake("---"), "_unnamed");
+        assert_eq!(identifiers.screaming("name"), "NAME_");
+    }
+}
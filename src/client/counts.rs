@@ -0,0 +1,104 @@
+//! Presence checks for measurements, see `InfluxClient::count` and `InfluxClient::exists`.
+
+use super::{
+    names::BucketName,
+    query::{Query, RangeBound},
+    single_record, InfluxClient, InfluxError,
+};
+use crate::flux::FluxValue;
+use std::{convert::TryFrom, ops::Range};
+
+impl InfluxClient {
+    /// The number of values (of all fields and series) of the measurement `measurement` in
+    /// `bucket` within `range`, such as for finding gaps in data that should be written
+    /// regularly.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, RangeBound};
+    /// # use std::time::Duration;
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let range = RangeBound::ago(Duration::from_secs(7200))..RangeBound::ago(Duration::from_secs(3600));
+    /// let count = client.count("example_bucket", "heartbeat", range).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn count(
+        &self,
+        bucket: &str,
+        measurement: &str,
+        range: Range<RangeBound>,
+    ) -> Result<u64, InfluxError> {
+        let query = count_query(bucket, measurement, range)?;
+        let records = self.query(query).await?;
+        if records.is_empty() {
+            return Ok(0);
+        }
+        let record = single_record(records)?;
+        let value = record.get("_value").ok_or_else(|| {
+            InfluxError::UnexpectedShape("the record has no '_value' column".to_string())
+        })?;
+        value
+            .parse()
+            .map_err(|_| InfluxError::UnexpectedShape(format!("invalid count '{}'", value)))
+    }
+
+    /// Whether the measurement `measurement` has any values in `bucket` within `range`. This is
+    /// cheaper than `count`, since the server can stop at the first value.
+    pub async fn exists(
+        &self,
+        bucket: &str,
+        measurement: &str,
+        range: Range<RangeBound>,
+    ) -> Result<bool, InfluxError> {
+        let query = measurement_query(bucket, measurement, range)?.limit(1);
+        Ok(!self.query(query).await?.is_empty())
+    }
+}
+
+/// Count the values of each table, then sum the counts into a single record.
+fn count_query(
+    bucket: &str,
+    measurement: &str,
+    range: Range<RangeBound>,
+) -> Result<Query, InfluxError> {
+    Ok(measurement_query(bucket, measurement, range)?
+        .then("count()")
+        .group(Vec::<String>::new())
+        .then("sum()"))
+}
+
+fn measurement_query(
+    bucket: &str,
+    measurement: &str,
+    range: Range<RangeBound>,
+) -> Result<Query, InfluxError> {
+    BucketName::try_from(bucket)?;
+    Ok(
+        Query::new(format!("from(bucket: {})", FluxValue::from(bucket)))
+            .range(range.start, range.end)
+            .filter_measurement(measurement),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn count_of_all_tables() {
+        let range = RangeBound::ago(Duration::from_secs(3600))..RangeBound::Now;
+        let query = count_query("b", "cpu", range).unwrap();
+        assert_eq!(
+            query.to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h, stop: now())
+ |> filter(fn: (r) => r._measurement == "cpu")
+ |> count()
+ |> group(columns: [])
+ |> sum()"#
+        );
+        assert!(measurement_query("", "cpu", RangeBound::Now..RangeBound::Now).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! The CSV dialect of query responses, see `InfluxClientBuilder::query_options`.
+
+use super::{json::Value, query::Query};
+use crate::flux::FluxValue;
+use std::time::SystemTime;
+
+/// Annotation rows of a query response, see `QueryOptions::annotations`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Annotation {
+    /// The data type of each column, such as `double` or `dateTime:RFC3339`
+    Datatype,
+    /// Whether each column is part of the group key
+    Group,
+    /// The default value of each column
+    Default,
+}
+
+impl Annotation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Annotation::Datatype => "datatype",
+            Annotation::Group => "group",
+            Annotation::Default => "default",
+        }
+    }
+}
+
+/// A Flux profiler, see `QueryOptions::profilers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profiler {
+    /// Statistics about the whole query, such as how long it was queued, compiled and executed
+    Query,
+    /// Statistics about each operation of the query, such as how long it took
+    Operator,
+}
+
+impl Profiler {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Profiler::Query => "query",
+            Profiler::Operator => "operator",
+        }
+    }
+}
+
+/// How times are formatted in query responses, see `QueryOptions::date_time_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateTimeFormat {
+    /// RFC3339 with as many fractional digits as needed, e.g. `2021-06-01T11:16:05.684Z`
+    #[default]
+    Rfc3339,
+    /// RFC3339 with all nine fractional digits, e.g. `2021-06-01T11:16:05.684000000Z`
+    Rfc3339Nano,
+}
+
+/// Options for the CSV dialect of query responses and the time queries are evaluated at, sent
+/// with every query instead of relying on the defaults of the server.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::{Annotation, QueryOptions};
+/// let options = QueryOptions::new().annotations(&[Annotation::Datatype, Annotation::Group]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryOptions {
+    annotations: Vec<Annotation>,
+    header: bool,
+    delimiter: u8,
+    comment_prefix: String,
+    date_time_format: DateTimeFormat,
+    now: Option<SystemTime>,
+    profilers: Vec<Profiler>,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            annotations: Vec::new(),
+            header: true,
+            delimiter: b',',
+            comment_prefix: "#".to_string(),
+            date_time_format: DateTimeFormat::default(),
+            now: None,
+            profilers: Vec::new(),
+        }
+    }
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the annotation rows written before the header of each table, defaults to none.
+    /// Annotation rows are skipped when parsing records.
+    pub fn annotations(mut self, annotations: &[Annotation]) -> Self {
+        self.annotations = annotations.to_vec();
+        self
+    }
+
+    /// Set whether each table starts with a header row, defaults to `true`. Records are keyed by
+    /// the columns in the header row, so without it the first row of each table is taken as the
+    /// header.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set the (ASCII) character separating columns, defaults to `,`.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Set the prefix of annotation rows, defaults to `#`.
+    pub fn comment_prefix(mut self, comment_prefix: impl Into<String>) -> Self {
+        self.comment_prefix = comment_prefix.into();
+        self
+    }
+
+    /// Set how times are formatted, defaults to `DateTimeFormat::Rfc3339`.
+    pub fn date_time_format(mut self, date_time_format: DateTimeFormat) -> Self {
+        self.date_time_format = date_time_format;
+        self
+    }
+
+    /// Evaluate queries as if they ran at `now`, sent as the `now` field of query requests. Like
+    /// `Query::now`, this resolves relative ranges against a fixed point in time, but for every
+    /// query of the client.
+    pub fn now(mut self, now: SystemTime) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    /// Enable Flux profilers for every query, defaults to none. The tables of the profilers are
+    /// left out of the results of `InfluxClient::query`, and returned separately by
+    /// `InfluxClient::query_profiled`.
+    pub fn profilers(mut self, profilers: &[Profiler]) -> Self {
+        self.profilers = profilers.to_vec();
+        self
+    }
+
+    /// `query` with the profilers enabled, if any.
+    pub(crate) fn profiled(&self, query: &Query) -> Query {
+        if self.profilers.is_empty() {
+            return query.clone();
+        }
+        let names: Vec<_> = self
+            .profilers
+            .iter()
+            .map(|p| FluxValue::from(p.as_str()).to_string())
+            .collect();
+        query.clone().import("profiler").option(
+            "profiler.enabledProfilers",
+            format!("[{}]", names.join(", ")),
+        )
+    }
+
+    /// The `now` field of a query request, if set.
+    pub(crate) fn now_json(&self) -> Option<Value> {
+        self.now
+            .map(|now| Value::String(FluxValue::from(now).to_string()))
+    }
+
+    pub(crate) fn delimiter_byte(&self) -> u8 {
+        self.delimiter
+    }
+
+    pub(crate) fn comment_prefix_str(&self) -> &str {
+        &self.comment_prefix
+    }
+
+    /// The `dialect` object of a query request.
+    pub(crate) fn to_json(&self) -> Value {
+        let annotations = self
+            .annotations
+            .iter()
+            .map(|a| Value::String(a.as_str().to_string()))
+            .collect();
+        let date_time_format = match self.date_time_format {
+            DateTimeFormat::Rfc3339 => "RFC3339",
+            DateTimeFormat::Rfc3339Nano => "RFC3339Nano",
+        };
+        Value::Object(vec![
+            ("annotations".to_string(), Value::Array(annotations)),
+            ("header".to_string(), Value::Bool(self.header)),
+            (
+                "delimiter".to_string(),
+                Value::String(char::from(self.delimiter).to_string()),
+            ),
+            (
+                "commentPrefix".to_string(),
+                Value::String(self.comment_prefix.clone()),
+            ),
+            (
+                "dateTimeFormat".to_string(),
+                Value::String(date_time_format.to_string()),
+            ),
+        ])
+    }
+}
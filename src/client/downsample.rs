@@ -0,0 +1,167 @@
+//! Generating the Flux for copying data into a downsampled bucket, see `Downsample`.
+
+use super::{
+    names::BucketName,
+    query::{Query, RangeBound},
+};
+use crate::flux::FluxValue;
+use std::{fmt::Display, time::Duration};
+
+/// An aggregate function that `aggregateWindow` can reduce each window with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aggregate {
+    Mean,
+    Median,
+    Min,
+    Max,
+    Sum,
+    Count,
+    First,
+    Last,
+}
+
+impl Aggregate {
+    /// The name of the Flux function.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Median => "median",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::Sum => "sum",
+            Aggregate::Count => "count",
+            Aggregate::First => "first",
+            Aggregate::Last => "last",
+        }
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The canonical downsampling query, which aggregates the data of `source` into windows and
+/// writes the result into `target`.
+///
+/// By default the query covers the last `window`, which suits a task that runs every `window`.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::{Aggregate, BucketName, Downsample, Query, RangeBound};
+/// # use std::{convert::TryFrom, time::Duration};
+/// let downsample = Downsample::new(
+///     BucketName::try_from("raw").unwrap(),
+///     BucketName::try_from("raw_5m").unwrap(),
+///     Duration::from_secs(300),
+///     Aggregate::Mean,
+/// )
+/// .measurement("cpu")
+/// .start(RangeBound::ago(Duration::from_secs(3600)));
+/// assert_eq!(
+///     Query::from(downsample).to_string(),
+///     r#"from(bucket: "raw")
+///  |> range(start: -1h)
+///  |> filter(fn: (r) => r._measurement == "cpu")
+///  |> aggregateWindow(every: 5m, fn: mean, createEmpty: false)
+///  |> to(bucket: "raw_5m")"#
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Downsample {
+    source: BucketName,
+    target: BucketName,
+    window: Duration,
+    function: Aggregate,
+    start: Option<RangeBound>,
+    measurement: Option<String>,
+    field: Option<String>,
+}
+
+impl Downsample {
+    pub fn new(
+        source: BucketName,
+        target: BucketName,
+        window: Duration,
+        function: Aggregate,
+    ) -> Self {
+        Self {
+            source,
+            target,
+            window,
+            function,
+            start: None,
+            measurement: None,
+            field: None,
+        }
+    }
+
+    /// Downsample the data from `start` up to now, instead of the last window.
+    pub fn start(mut self, start: impl Into<RangeBound>) -> Self {
+        self.start = Some(start.into());
+        self
+    }
+
+    /// Only downsample records of the measurement `measurement`.
+    pub fn measurement(mut self, measurement: impl Into<String>) -> Self {
+        self.measurement = Some(measurement.into());
+        self
+    }
+
+    /// Only downsample records of the field `field`.
+    pub fn field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+}
+
+impl From<Downsample> for Query {
+    fn from(downsample: Downsample) -> Self {
+        let start = downsample
+            .start
+            .unwrap_or_else(|| RangeBound::ago(downsample.window));
+        let mut query = Query::new(format!(
+            "from(bucket: {})",
+            FluxValue::from(downsample.source.as_str())
+        ))
+        .range_from(start);
+        if let Some(measurement) = &downsample.measurement {
+            query = query.filter_measurement(measurement);
+        }
+        if let Some(field) = &downsample.field {
+            query = query.filter_field(field);
+        }
+        query
+            .aggregate_window(downsample.window, downsample.function.as_str(), false)
+            .then(format!(
+                "to(bucket: {})",
+                FluxValue::from(downsample.target.as_str())
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn default_range_is_one_window() {
+        let downsample = Downsample::new(
+            BucketName::try_from("raw").unwrap(),
+            BucketName::try_from("raw_1h").unwrap(),
+            Duration::from_secs(3600),
+            Aggregate::Max,
+        )
+        .field("usage");
+        assert_eq!(
+            Query::from(downsample).to_string(),
+            r#"from(bucket: "raw")
+ |> range(start: -1h)
+ |> filter(fn: (r) => r._field == "usage")
+ |> aggregateWindow(every: 1h, fn: max, createEmpty: false)
+ |> to(bucket: "raw_1h")"#
+        );
+    }
+}
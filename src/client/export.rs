@@ -0,0 +1,138 @@
+//! Writing query results straight to a file, see `InfluxClient::query_to_file`.
+
+use super::{query::Query, InfluxClient, InfluxError};
+use futures_lite::{AsyncReadExt, StreamExt};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// The format of a file written by `InfluxClient::query_to_file`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The CSV of the response, as it is
+    Csv,
+    /// A JSON object per line and record, see `FluxRecord::to_json`
+    JsonLines,
+}
+
+impl InfluxClient {
+    /// Run a query and write its result to the file at `path` as it arrives, without holding
+    /// the result in memory. `progress` is called with the number of bytes written so far after
+    /// every write.
+    ///
+    /// Returns the number of bytes written. If the query fails after the response started, the
+    /// file holds the part of the result received until then. With `ExportFormat::Csv` errors
+    /// reported in the body of the response are written to the file like any other table.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{ExportFormat, InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -1d)");
+    /// client
+    ///     .query_to_file(query, "export.jsonl", ExportFormat::JsonLines, |bytes| {
+    ///         println!("{} bytes written", bytes)
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_to_file(
+        &self,
+        query: Query,
+        path: impl AsRef<Path>,
+        format: ExportFormat,
+        mut progress: impl FnMut(u64),
+    ) -> Result<u64, InfluxError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let mut written = 0;
+        match format {
+            ExportFormat::Csv => {
+                let (payload, content_type) = self.query_payload(&query)?;
+                let request = self.query_http_request(payload, content_type, "application/csv")?;
+                let mut body = self.send_streaming(request).await?.into_body();
+                let mut buffer = vec![0; 64 * 1024];
+                loop {
+                    let read = body.read(&mut buffer).await?;
+                    if read == 0 {
+                        break;
+                    }
+                    file.write_all(&buffer[..read])?;
+                    written += read as u64;
+                    progress(written);
+                }
+            }
+            ExportFormat::JsonLines => {
+                let mut records = Box::pin(self.query_stream(query));
+                while let Some(record) = records.next().await {
+                    let mut line = record?.to_json();
+                    line.push('\n');
+                    file.write_all(line.as_bytes())?;
+                    written += line.len() as u64;
+                    progress(written);
+                }
+            }
+        }
+        file.flush()?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::serve_once;
+
+    fn export(format: ExportFormat, body: &str) -> (String, u64, Vec<u64>) {
+        let (url, server) = serve_once("200 OK", body);
+        let client = InfluxClient::builder(url, "key".to_string(), "org".to_string())
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "influxrs-export-test-{}-{:?}",
+            std::process::id(),
+            format
+        ));
+        let mut progress = Vec::new();
+        let written = futures_lite::future::block_on(client.query_to_file(
+            Query::new(r#"from(bucket: "b")"#),
+            &path,
+            format,
+            |bytes| progress.push(bytes),
+        ))
+        .unwrap();
+        server.join().unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        (contents, written, progress)
+    }
+
+    #[test]
+    fn export_csv() {
+        let body = ",result,table,_value\r\n,_result,0,1\r\n,_result,0,2\r\n";
+        let (contents, written, progress) = export(ExportFormat::Csv, body);
+        assert_eq!(contents, body);
+        assert_eq!(written, body.len() as u64);
+        assert_eq!(progress.last(), Some(&written));
+    }
+
+    #[test]
+    fn export_json_lines() {
+        let body = "#datatype,string,long,double,string\r\n\
+            #group,false,false,false,true\r\n\
+            #default,_result,,,\r\n\
+            ,result,table,_value,host\r\n\
+            ,,0,1.5,a\r\n\
+            ,,1,2.5,b\r\n";
+        let (contents, written, progress) = export(ExportFormat::JsonLines, body);
+        assert_eq!(
+            contents,
+            "{\"result\":\"_result\",\"table\":0,\"_value\":1.5,\"host\":\"a\"}\n\
+            {\"result\":\"_result\",\"table\":1,\"_value\":2.5,\"host\":\"b\"}\n"
+        );
+        assert_eq!(written, contents.len() as u64);
+        assert_eq!(progress, [contents.find('\n').unwrap() as u64 + 1, written]);
+    }
+}
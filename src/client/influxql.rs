@@ -0,0 +1,209 @@
+//! A builder for InfluxQL `SELECT` statements, see `Select`.
+
+use super::{downsample::Aggregate, query::RangeBound, v1::quote_identifier};
+use crate::datetime;
+use std::{fmt::Display, time::Duration};
+
+/// An InfluxQL `SELECT` statement, for `InfluxClient::query_influxql`. Identifiers and string
+/// literals are always quoted and escaped.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::{RangeBound, Select};
+/// # use std::time::Duration;
+/// let select = Select::from("cpu")
+///     .field_mean("usage")
+///     .where_tag("host", "a")
+///     .where_time(RangeBound::ago(Duration::from_secs(3600)), RangeBound::Now)
+///     .group_by_time(Duration::from_secs(300))
+///     .group_by_tag("region");
+/// assert_eq!(
+///     select.to_string(),
+///     r#"SELECT mean("usage") FROM "cpu" WHERE "host" = 'a' AND time >= now() - 1h AND time < now() GROUP BY time(5m), "region""#
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    measurement: String,
+    retention_policy: Option<String>,
+    fields: Vec<String>,
+    conditions: Vec<String>,
+    group_by: Vec<String>,
+    limit: Option<usize>,
+}
+
+impl Select {
+    /// Select from the measurement `measurement`.
+    pub fn from(measurement: impl Into<String>) -> Self {
+        Self {
+            measurement: measurement.into(),
+            retention_policy: None,
+            fields: Vec::new(),
+            conditions: Vec::new(),
+            group_by: Vec::new(),
+            limit: None,
+        }
+    }
+
+    /// Select from the measurement in the retention policy `retention_policy`, instead of the
+    /// default one.
+    pub fn retention_policy(mut self, retention_policy: impl Into<String>) -> Self {
+        self.retention_policy = Some(retention_policy.into());
+        self
+    }
+
+    /// Select the field or tag `field`. Without any selected fields, all of them are selected.
+    pub fn field(mut self, field: &str) -> Self {
+        self.fields.push(quote_identifier(field));
+        self
+    }
+
+    /// Select `function` applied to the field `field`.
+    pub fn field_aggregate(mut self, function: Aggregate, field: &str) -> Self {
+        self.fields
+            .push(format!("{}({})", function, quote_identifier(field)));
+        self
+    }
+
+    /// Select the mean of the field `field`.
+    pub fn field_mean(self, field: &str) -> Self {
+        self.field_aggregate(Aggregate::Mean, field)
+    }
+
+    /// Select the maximum of the field `field`.
+    pub fn field_max(self, field: &str) -> Self {
+        self.field_aggregate(Aggregate::Max, field)
+    }
+
+    /// Select the minimum of the field `field`.
+    pub fn field_min(self, field: &str) -> Self {
+        self.field_aggregate(Aggregate::Min, field)
+    }
+
+    /// Keep only points where the tag `key` has the value `value`.
+    pub fn where_tag(mut self, key: &str, value: &str) -> Self {
+        self.conditions.push(format!(
+            "{} = {}",
+            quote_identifier(key),
+            string_literal(value)
+        ));
+        self
+    }
+
+    /// Keep only points from `start` up to (but not including) `stop`.
+    pub fn where_time(mut self, start: impl Into<RangeBound>, stop: impl Into<RangeBound>) -> Self {
+        self.conditions
+            .push(format!("time >= {}", time_literal(start.into())));
+        self.conditions
+            .push(format!("time < {}", time_literal(stop.into())));
+        self
+    }
+
+    /// Group the points into intervals of `interval`, which the selected aggregates are applied
+    /// to.
+    pub fn group_by_time(mut self, interval: Duration) -> Self {
+        self.group_by.push(format!(
+            "time({})",
+            duration_literal(interval.as_nanos() as i128)
+        ));
+        self
+    }
+
+    /// Group the points by the values of the tag `tag`, with a series per value.
+    pub fn group_by_tag(mut self, tag: &str) -> Self {
+        self.group_by.push(quote_identifier(tag));
+        self
+    }
+
+    /// Return at most `n` points per series.
+    pub fn limit(mut self, n: usize) -> Self {
+        self.limit = Some(n);
+        self
+    }
+}
+
+impl Display for Select {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields = if self.fields.is_empty() {
+            "*".to_string()
+        } else {
+            self.fields.join(", ")
+        };
+        write!(f, "SELECT {} FROM ", fields)?;
+        if let Some(retention_policy) = &self.retention_policy {
+            write!(f, "{}.", quote_identifier(retention_policy))?;
+        }
+        write!(f, "{}", quote_identifier(&self.measurement))?;
+        if !self.conditions.is_empty() {
+            write!(f, " WHERE {}", self.conditions.join(" AND "))?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY {}", self.group_by.join(", "))?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// `s` as an InfluxQL string literal.
+fn string_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn time_literal(bound: RangeBound) -> String {
+    match bound {
+        RangeBound::Time(nanos) => string_literal(&datetime::format_rfc3339(nanos)),
+        RangeBound::Relative(nanos) if nanos < 0 => format!("now() - {}", duration_literal(-nanos)),
+        RangeBound::Relative(nanos) if nanos > 0 => format!("now() + {}", duration_literal(nanos)),
+        RangeBound::Relative(_) | RangeBound::Now => "now()".to_string(),
+    }
+}
+
+/// `nanos` as an InfluxQL duration literal, in the largest unit it's a whole number of.
+pub(crate) fn duration_literal(nanos: i128) -> String {
+    const UNITS: [(&str, i128); 7] = [
+        ("w", 604_800_000_000_000),
+        ("d", 86_400_000_000_000),
+        ("h", 3_600_000_000_000),
+        ("m", 60_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("u", 1_000),
+    ];
+    UNITS
+        .iter()
+        .find(|(_, unit)| nanos != 0 && nanos % unit == 0)
+        .map_or_else(
+            || format!("{}ns", nanos),
+            |(name, unit)| format!("{}{}", nanos / unit, name),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_statement() {
+        let select = Select::from("cpu \"load\"")
+            .retention_policy("one_week")
+            .field("host")
+            .field_aggregate(Aggregate::Last, "usage")
+            .where_tag("host", "it's")
+            .where_time(
+                RangeBound::Time(0),
+                RangeBound::from_now(Duration::from_millis(1500)),
+            )
+            .group_by_time(Duration::from_secs(14 * 86400))
+            .limit(5);
+        assert_eq!(
+            select.to_string(),
+            r#"SELECT "host", last("usage") FROM "one_week"."cpu \"load\"" WHERE "host" = 'it\'s' AND time >= '1970-01-01T00:00:00Z' AND time < now() + 1500ms GROUP BY time(2w) LIMIT 5"#
+        );
+        assert_eq!(Select::from("m").to_string(), r#"SELECT * FROM "m""#);
+        assert_eq!(duration_literal(1), "1ns");
+        assert_eq!(duration_literal(0), "0ns");
+    }
+}
@@ -0,0 +1,113 @@
+//! Splitting writes into concurrent lanes by series, see `InfluxClientBuilder::write_lanes`.
+
+use crate::Measurement;
+use std::{
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+    task::Poll,
+};
+
+/// Split a batch into `lanes` batches, so that all points of a series end up in the same lane
+/// in their original order. Empty lanes are left out.
+pub(crate) fn split_into_lanes<'a>(
+    measurements: &[&'a Measurement],
+    lanes: usize,
+) -> Vec<Vec<&'a Measurement>> {
+    let mut split = vec![Vec::new(); lanes];
+    for measurement in measurements {
+        split[lane_of(measurement, lanes)].push(*measurement);
+    }
+    split.retain(|lane| !lane.is_empty());
+    split
+}
+
+/// The lane of the series of a measurement. `DefaultHasher::new` always uses the same keys, so
+/// a series maps to the same lane across writes and clients.
+fn lane_of(measurement: &Measurement, lanes: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    measurement.measurement_name.hash(&mut hasher);
+    for (name, value) in &measurement.tags {
+        name.hash(&mut hasher);
+        value.as_str().hash(&mut hasher);
+    }
+    (hasher.finish() % lanes as u64) as usize
+}
+
+/// Run futures concurrently, returning their outputs in the order the futures were given.
+pub(crate) async fn join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> Vec<F::Output> {
+    let mut pending: Vec<_> = futures.into_iter().map(|f| Some(Box::pin(f))).collect();
+    let mut outputs: Vec<Option<F::Output>> = pending.iter().map(|_| None).collect();
+    futures_lite::future::poll_fn(|cx| {
+        let mut done = true;
+        for (slot, output) in pending.iter_mut().zip(outputs.iter_mut()) {
+            if let Some(future) = slot {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(value) => {
+                        *output = Some(value);
+                        *slot = None;
+                    }
+                    Poll::Pending => done = false,
+                }
+            }
+        }
+        if done {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+    outputs.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_locality() {
+        let measurements: Vec<_> = (0..40)
+            .map(|i| {
+                Measurement::builder("m")
+                    .tag("host", format!("host{}", i % 8))
+                    .field("i", i)
+                    .timestamp_s(i)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        let batch: Vec<_> = measurements.iter().collect();
+
+        let lanes = split_into_lanes(&batch, 4);
+        assert!(lanes.len() > 1 && lanes.len() <= 4);
+        assert_eq!(lanes.iter().map(Vec::len).sum::<usize>(), 40);
+        for lane in &lanes {
+            let mut times: Vec<_> = lane.iter().map(|m| m.timestamp()).collect();
+            let sorted = times.clone();
+            times.sort();
+            assert_eq!(times, sorted, "points of a lane keep their order");
+            for other in &lanes {
+                if !std::ptr::eq(lane, other) {
+                    assert!(lane
+                        .iter()
+                        .all(|m| other.iter().all(|o| m.tag("host") != o.tag("host"))));
+                }
+            }
+        }
+        assert_eq!(split_into_lanes(&batch, 4), lanes, "lanes are stable");
+        assert_eq!(split_into_lanes(&batch, 1), vec![batch.clone()]);
+    }
+
+    #[test]
+    fn join_in_order() {
+        let futures = (0..3).map(|i| async move {
+            futures_lite::future::yield_now().await;
+            i * 2
+        });
+        assert_eq!(
+            futures_lite::future::block_on(join_all(futures)),
+            vec![0, 2, 4]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Looking up the latest value of a field, see `InfluxClient::last_value`.
+
+use super::{
+    codegen::TYPE_COLUMN,
+    names::BucketName,
+    query::{Query, RangeBound},
+    InfluxClient, InfluxError,
+};
+use crate::{datetime, flux::FluxValue, Field};
+use std::{collections::HashMap, convert::TryFrom};
+
+impl InfluxClient {
+    /// The latest value of `field` of the measurement `measurement` in `bucket`, with its
+    /// timestamp in nanoseconds since the Unix epoch, or `None` if the field was never written.
+    ///
+    /// Only series with the given `tags` are considered. If several series match, the latest
+    /// value of all of them is returned.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{Field, InfluxClient};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// if let Some((timestamp, Field::Float(temperature))) = client
+    ///     .last_value("example_bucket", "weather", "temperature", [("city", "Stockholm")])
+    ///     .await?
+    /// {
+    ///     println!("{} degrees at {}", temperature, timestamp);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn last_value<K: AsRef<str>, V: AsRef<str>>(
+        &self,
+        bucket: &str,
+        measurement: &str,
+        field: &str,
+        tags: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Option<(i128, Field)>, InfluxError> {
+        BucketName::try_from(bucket)?;
+        let mut query = Query::new(format!("from(bucket: {})", FluxValue::from(bucket)))
+            .import("types")
+            .range_from(RangeBound::Time(0))
+            .filter_measurement(measurement)
+            .filter_field(field);
+        for (key, value) in tags {
+            query = query.filter_tag(key, value);
+        }
+        let query = query.then("last()").then(TYPE_COLUMN).optimize();
+        latest_value(&self.query(query).await?)
+    }
+}
+
+/// The value of the latest of `records`, the last records of each matching series.
+fn latest_value(records: &[HashMap<String, String>]) -> Result<Option<(i128, Field)>, InfluxError> {
+    let mut latest = None;
+    for record in records {
+        let time = record
+            .get("_time")
+            .and_then(|time| datetime::parse_rfc3339(time))
+            .ok_or_else(|| {
+                InfluxError::UnexpectedShape("the record has no valid '_time' column".to_string())
+            })?;
+        if latest.is_none_or(|(latest_time, _)| time > latest_time) {
+            latest = Some((time, record));
+        }
+    }
+    latest
+        .map(|(time, record)| Ok((time, parse_field(record)?)))
+        .transpose()
+}
+
+/// The `_value` of `record`, as the type in its `_type` column.
+fn parse_field(record: &HashMap<String, String>) -> Result<Field, InfluxError> {
+    let value = record.get("_value").ok_or_else(|| {
+        InfluxError::UnexpectedShape("the record has no '_value' column".to_string())
+    })?;
+    let data_type = record.get("_type").map_or("string", String::as_str);
+    let field = match data_type {
+        "float" => value.parse().ok().map(Field::Float),
+        "integer" => value.parse().ok().map(Field::Integer),
+        "unsigned" => value.parse().ok().map(Field::UInteger),
+        "boolean" => value.parse().ok().map(Field::Bool),
+        _ => Some(Field::String(value.clone())),
+    };
+    field.ok_or_else(|| {
+        InfluxError::UnexpectedShape(format!("invalid {} value '{}'", data_type, value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::records::tests::record;
+
+    #[test]
+    fn latest_of_series() {
+        assert_eq!(latest_value(&[]).unwrap(), None);
+
+        let records = [
+            record(&[
+                ("_time", "2021-06-01T11:16:05Z"),
+                ("_value", "-3"),
+                ("_type", "integer"),
+            ]),
+            record(&[
+                ("_time", "2021-06-01T11:16:06Z"),
+                ("_value", "true"),
+                ("_type", "boolean"),
+            ]),
+            record(&[
+                ("_time", "2021-06-01T11:16:04Z"),
+                ("_value", "a"),
+                ("_type", "string"),
+            ]),
+        ];
+        assert_eq!(
+            latest_value(&records).unwrap(),
+            Some((1_622_546_166_000_000_000, Field::Bool(true)))
+        );
+        assert_eq!(parse_field(&records[0]).unwrap(), Field::Integer(-3));
+
+        let invalid = record(&[("_value", "1.5"), ("_type", "unsigned")]);
+        assert!(matches!(
+            parse_field(&invalid),
+            Err(InfluxError::UnexpectedShape(message)) if message == "invalid unsigned value '1.5'"
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+//! Validated names of buckets and organizations, and API tokens.
+//!
+//! These types only validate: the methods of `InfluxClient` keep taking names as `&str` and
+//! check them with these types before sending anything, failing with `InfluxError::InvalidName`.
+//! Valid names can still contain characters such as spaces, `&` and `#`, which are URL-encoded
+//! wherever a name ends up in a URL.
+
+use std::{convert::TryFrom, error::Error, fmt::Display, ops::Deref, str::FromStr};
+
+/// The error returned when a bucket name, organization name or token is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidNameError {
+    kind: &'static str,
+    /// The invalid value, `None` for tokens so that they don't end up in logs
+    value: Option<String>,
+    reason: &'static str,
+}
+
+impl Display for InvalidNameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "invalid {} '{}': {}", self.kind, value, self.reason),
+            None => write!(f, "invalid {}: {}", self.kind, self.reason),
+        }
+    }
+}
+
+impl Error for InvalidNameError {}
+
+macro_rules! validated_string {
+    ($(#[$attr:meta])* $name:ident, $kind:literal, $validate:expr, $redact:literal) => {
+        $(#[$attr])*
+        #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(String);
+
+        impl $name {
+            /// The validated value.
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            fn validate(value: &str) -> Result<(), InvalidNameError> {
+                let validate: fn(&str) -> Result<(), &'static str> = $validate;
+                validate(value).map_err(|reason| InvalidNameError {
+                    kind: $kind,
+                    value: if $redact { None } else { Some(value.to_string()) },
+                    reason,
+                })
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = InvalidNameError;
+
+            fn try_from(value: &str) -> Result<Self, Self::Error> {
+                Self::validate(value)?;
+                Ok(Self(value.to_string()))
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = InvalidNameError;
+
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                Self::validate(&value)?;
+                Ok(Self(value))
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = InvalidNameError;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                Self::try_from(value)
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> String {
+                value.0
+            }
+        }
+    };
+}
+
+validated_string!(
+    /// A bucket name accepted by InfluxDB: not empty and without double quotes.
+    ///
+    /// Names starting with `_` are those of system buckets such as `_monitoring`, which can be
+    /// read and written, but not created.
+    ///
+    /// Dereferences to `str`, so it can be passed to `InfluxClient::write` and friends directly,
+    /// which check the names they are given the same way.
+    BucketName,
+    "bucket name",
+    |name| {
+        if name.is_empty() {
+            Err("must not be empty")
+        } else if name.contains('"') {
+            Err("must not contain '\"'")
+        } else {
+            Ok(())
+        }
+    },
+    false
+);
+
+validated_string!(
+    /// An organization name accepted by InfluxDB: not empty.
+    OrgName,
+    "organization name",
+    |name| {
+        if name.is_empty() {
+            Err("must not be empty")
+        } else {
+            Ok(())
+        }
+    },
+    false
+);
+
+validated_string!(
+    /// An API token that can be sent in an `Authorization` header: not empty, and without
+    /// whitespace or control characters. Its `Debug` output doesn't reveal the token.
+    Token,
+    "token",
+    |token| {
+        if token.is_empty() {
+            Err("must not be empty")
+        } else if token.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            Err("must not contain whitespace or control characters")
+        } else {
+            Ok(())
+        }
+    },
+    true
+);
+
+impl std::fmt::Debug for BucketName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("BucketName").field(&self.0).finish()
+    }
+}
+
+impl std::fmt::Debug for OrgName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OrgName").field(&self.0).finish()
+    }
+}
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(..)")
+    }
+}
+
+impl BucketName {
+    /// Check that the name isn't reserved for system buckets, for creating a bucket.
+    pub(crate) fn check_not_reserved(&self) -> Result<(), InvalidNameError> {
+        if self.0.starts_with('_') {
+            return Err(InvalidNameError {
+                kind: "bucket name",
+                value: Some(self.0.clone()),
+                reason: "must not start with '_', which is reserved for system buckets",
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Display for BucketName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Display for OrgName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation() {
+        assert_eq!(BucketName::try_from("metrics").unwrap().as_str(), "metrics");
+        assert!(BucketName::try_from("").is_err());
+        let monitoring = BucketName::try_from("_monitoring").unwrap();
+        assert_eq!(
+            monitoring.check_not_reserved().unwrap_err().to_string(),
+            "invalid bucket name '_monitoring': must not start with '_', which is reserved for system buckets"
+        );
+        assert!(BucketName::try_from("metrics")
+            .unwrap()
+            .check_not_reserved()
+            .is_ok());
+        assert_eq!(
+            BucketName::try_from("a\"b").unwrap_err().to_string(),
+            "invalid bucket name 'a\"b': must not contain '\"'"
+        );
+
+        assert!("my org".parse::<OrgName>().is_ok());
+        assert!("".parse::<OrgName>().is_err());
+
+        let token: Token = "abc==".parse().unwrap();
+        assert_eq!(format!("{:?}", token), "Token(..)");
+        assert_eq!(
+            Token::try_from("abc\n").unwrap_err().to_string(),
+            "invalid token: must not contain whitespace or control characters"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Flux scripts with named placeholders, see `PreparedQuery`.
+
+use super::{query::Query, InfluxClient, InfluxError, InfluxQueryResponse};
+use crate::flux::FluxValue;
+use std::collections::BTreeMap;
+
+/// A Flux script with named placeholders, written `$name`, which are replaced with Flux literals
+/// of the bound values when the query is run. Values are always escaped, so a vetted script can
+/// be stored centrally and bound to untrusted input.
+///
+/// Placeholders are only recognized outside of string literals and comments.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::PreparedQuery;
+/// # use std::time::Duration;
+/// let prepared = PreparedQuery::new(
+///     "from(bucket: $bucket)
+///     |> range(start: -$since)
+///     |> filter(fn: (r) => r.device == $device)",
+/// );
+/// let query = prepared
+///     .clone()
+///     .bind("bucket", "example_bucket")
+///     .bind("since", Duration::from_secs(3600))
+///     .bind("device", r#"a" or true or ""#)
+///     .to_query()
+///     .unwrap();
+/// assert_eq!(
+///     query.to_string(),
+///     r#"from(bucket: "example_bucket")
+///     |> range(start: -1h)
+///     |> filter(fn: (r) => r.device == "a\" or true or \"")"#
+/// );
+/// assert!(prepared.bind("bucket", "example_bucket").to_query().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedQuery {
+    segments: Vec<Segment>,
+    values: BTreeMap<String, FluxValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Placeholder(String),
+}
+
+impl PreparedQuery {
+    pub fn new(script: impl AsRef<str>) -> Self {
+        Self {
+            segments: parse_segments(script.as_ref()),
+            values: BTreeMap::new(),
+        }
+    }
+
+    /// The names of the placeholders of the script, in order of first appearance.
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Placeholder(name) = segment {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// Bind the placeholder `name` to `value`, replacing any value bound before.
+    pub fn bind(mut self, name: impl Into<String>, value: impl Into<FluxValue>) -> Self {
+        self.values.insert(name.into(), value.into());
+        self
+    }
+
+    /// The script with every placeholder replaced by the literal of its value.
+    ///
+    /// Returns `InfluxError::InvalidQuery` if a placeholder isn't bound, or a value is bound to a
+    /// name that isn't a placeholder of the script, which is usually a typo.
+    pub fn to_query(&self) -> Result<Query, InfluxError> {
+        let placeholders = self.placeholders();
+        if let Some(name) = self
+            .values
+            .keys()
+            .find(|name| !placeholders.contains(&name.as_str()))
+        {
+            return Err(InfluxError::InvalidQuery(format!(
+                "'{}' is not a placeholder of the query",
+                name
+            )));
+        }
+
+        let mut script = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => script.push_str(text),
+                Segment::Placeholder(name) => {
+                    let value = self.values.get(name).ok_or_else(|| {
+                        InfluxError::InvalidQuery(format!("placeholder '${}' is not bound", name))
+                    })?;
+                    script.push_str(&value.to_string());
+                }
+            }
+        }
+        // The script is kept as it is, since it may have several statements.
+        Ok(Query::new(script))
+    }
+}
+
+/// Split `script` into text and placeholders, skipping string literals and comments.
+fn parse_segments(script: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = script.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                text.push(c);
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    text.push(c);
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                text.push(c);
+                while let Some(c) = chars.next_if(|c| *c != '\n') {
+                    text.push(c);
+                }
+            }
+            '$' if chars.peek().is_some_and(|c| c.is_alphabetic() || *c == '_') => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Placeholder(name));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+impl InfluxClient {
+    /// Run a prepared query with the values bound to it, see `PreparedQuery::to_query`.
+    pub async fn query_prepared(
+        &self,
+        prepared: &PreparedQuery,
+    ) -> Result<InfluxQueryResponse, InfluxError> {
+        self.query(prepared.to_query()?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_outside_strings() {
+        let prepared = PreparedQuery::new(
+            r#"// filter by $host
+from(bucket: $bucket) |> filter(fn: (r) => r.host == $host and r.note == "costs $5 \" $host")"#,
+        );
+        assert_eq!(prepared.placeholders(), ["bucket", "host"]);
+
+        let query = prepared
+            .clone()
+            .bind("bucket", "b")
+            .bind("host", "${token}")
+            .to_query()
+            .unwrap();
+        assert_eq!(
+            query.to_string(),
+            r#"// filter by $host
+from(bucket: "b") |> filter(fn: (r) => r.host == "\${token}" and r.note == "costs $5 \" $host")"#
+        );
+
+        let error = prepared
+            .bind("bucket", "b")
+            .bind("host", "a")
+            .bind("hots", "a")
+            .to_query()
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            InfluxError::InvalidQuery(message) if message == "'hots' is not a placeholder of the query"
+        ));
+    }
+}
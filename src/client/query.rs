@@ -0,0 +1,1629 @@
+use super::InfluxError;
+use crate::{datetime, flux::FluxValue};
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// A Flux query.
+///
+/// Queries compare and hash by their meaning rather than their exact text: whitespace outside of
+/// strings is ignored, and so is the order of adjacent `filter` stages. Equivalent queries built
+/// in different ways therefore share entries in hash maps, such as the query cache of
+/// `InfluxClientBuilder::query_cache`.
+#[derive(Debug, Clone)]
+pub struct Query {
+    /// Paths of imported packages
+    imports: Vec<String>,
+    /// Option statements, such as `option location = timezone.utc`
+    options: Vec<String>,
+    lines: Vec<String>,
+    /// Indices of lines that start a statement of their own, instead of being piped into the
+    /// previous line
+    statement_starts: Vec<usize>,
+}
+
+impl Query {
+    pub fn new(line: impl Into<String>) -> Self {
+        Self::from_lines(vec![line.into()])
+    }
+
+    fn from_lines(lines: Vec<String>) -> Self {
+        Self {
+            imports: Vec::new(),
+            options: Vec::new(),
+            lines,
+            statement_starts: Vec::new(),
+        }
+    }
+
+    /// Create a query from a raw string. Lines starting with `|>` are piped into the previous
+    /// line, other lines start a new statement, and blank lines are dropped.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::raw(r#"from(bucket: "server")
+    ///     |> range(start: v.timeRangeStart, stop: v.timeRangeStop)
+    ///     |> filter(fn: (r) => r["_measurement"] == "example_measurement")
+    ///     |> keys()"#);
+    /// ```
+    pub fn raw(query: impl Into<String>) -> Self {
+        let query = query.into();
+        let mut lines = query.lines().map(str::trim).filter(|l| !l.is_empty());
+        let mut raw = Self::new(lines.next().unwrap_or_default());
+        for line in lines {
+            raw = match line.strip_prefix("|>") {
+                Some(stripped) => raw.then(stripped.trim()),
+                None => raw.statement(line),
+            };
+        }
+        raw
+    }
+
+    /// Append a line, piped into the previous line or starting a new statement.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::{Query, QueryLine};
+    /// let query = Query::new(r#"data = from(bucket: "example_bucket")"#)
+    ///     .line(QueryLine::Piped("range(start: -1h)".to_string()))
+    ///     .line(QueryLine::Statement("data".to_string()))
+    ///     .line(QueryLine::Piped("count()".to_string()));
+    /// assert_eq!(query.lines()[1], QueryLine::Piped("range(start: -1h)".to_string()));
+    /// assert_eq!(query, Query::raw(&query.to_string()));
+    /// ```
+    pub fn line(self, line: QueryLine) -> Self {
+        match line {
+            QueryLine::Piped(line) => self.then(line),
+            QueryLine::Statement(line) => self.statement(line),
+        }
+    }
+
+    /// The lines of the query, without imports and options. The first line always starts a
+    /// statement.
+    pub fn lines(&self) -> Vec<QueryLine> {
+        self.lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                if i == 0 || self.statement_starts.contains(&i) {
+                    QueryLine::Statement(line.clone())
+                } else {
+                    QueryLine::Piped(line.clone())
+                }
+            })
+            .collect()
+    }
+
+    /// Append a line to the query.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then(r#"filter(fn: (r) => r["_measurement"] == "example_measurement")"#);
+    /// ```
+    pub fn then(mut self, line: impl Into<String>) -> Self {
+        self.lines.push(line.into());
+        self
+    }
+
+    /// Append a line that starts a new statement, instead of being piped into the previous line,
+    /// such as a variable assignment or another `yield`. Lines added with `then` afterwards are
+    /// piped into this one.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::new(r#"data = from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .statement("data")
+    ///     .then("mean()")
+    ///     .then(r#"yield(name: "mean")"#)
+    ///     .statement("data")
+    ///     .then("max()")
+    ///     .then(r#"yield(name: "max")"#);
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"data = from(bucket: "example_bucket")
+    ///  |> range(start: -1h)
+    /// data
+    ///  |> mean()
+    ///  |> yield(name: "mean")
+    /// data
+    ///  |> max()
+    ///  |> yield(name: "max")"#
+    /// );
+    /// ```
+    pub fn statement(mut self, line: impl Into<String>) -> Self {
+        self.statement_starts.push(self.lines.len());
+        self.lines.push(line.into());
+        self
+    }
+
+    /// The statements of the query, each a line followed by the lines piped into it.
+    fn pipelines(&self) -> impl Iterator<Item = &[String]> + '_ {
+        let starts = std::iter::once(0).chain(self.statement_starts.iter().copied());
+        let ends = self
+            .statement_starts
+            .iter()
+            .copied()
+            .chain(std::iter::once(self.lines.len()));
+        starts
+            .zip(ends)
+            .map(move |(start, end)| &self.lines[start..end])
+    }
+
+    /// Import the package `path`, such as `timezone` or `experimental/aggregate`. Imports are
+    /// written at the top of the script, in the order they are added, and importing a package
+    /// twice has no effect.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1d)")
+    ///     .then("aggregateWindow(every: 1d, fn: mean)")
+    ///     .import("timezone")
+    ///     .option("location", r#"timezone.location(name: "Europe/Stockholm")"#);
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"import "timezone"
+    /// option location = timezone.location(name: "Europe/Stockholm")
+    ///
+    /// from(bucket: "example_bucket")
+    ///  |> range(start: -1d)
+    ///  |> aggregateWindow(every: 1d, fn: mean)"#
+    /// );
+    /// ```
+    pub fn import(mut self, path: impl Into<String>) -> Self {
+        let path = path.into();
+        if !self.imports.contains(&path) {
+            self.imports.push(path);
+        }
+        self
+    }
+
+    /// Set the option `name` to the Flux expression `expression`, such as `location` for the
+    /// time zone of windows. Options are written at the top of the script after the imports, and
+    /// setting an option again replaces it.
+    pub fn option(mut self, name: &str, expression: impl Display) -> Self {
+        let prefix = format!("option {} =", name);
+        self.options.retain(|option| !option.starts_with(&prefix));
+        self.options.push(format!("{} {}", prefix, expression));
+        self
+    }
+
+    /// Evaluate the query as if it ran at `time`, by setting the `now` option, so that relative
+    /// ranges such as `range(start: -1h)` resolve against a fixed point in time.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// # use std::time::{Duration, SystemTime};
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .now(SystemTime::UNIX_EPOCH + Duration::from_secs(1622505600));
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"option now = () => 2021-06-01T00:00:00Z
+    ///
+    /// from(bucket: "example_bucket")
+    ///  |> range(start: -1h)"#
+    /// );
+    /// ```
+    pub fn now(self, time: SystemTime) -> Self {
+        self.option("now", format!("() => {}", FluxValue::from(time)))
+    }
+
+    /// The `import` and `option` statements, in the order they are written.
+    fn preamble(&self) -> impl Iterator<Item = String> + '_ {
+        self.imports
+            .iter()
+            .map(|path| format!("import {}", FluxValue::from(path)))
+            .chain(self.options.iter().cloned())
+    }
+
+    /// Add a `range` stage with the given bounds.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::{Query, RangeBound};
+    /// # use std::time::{Duration, SystemTime};
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .range(RangeBound::ago(Duration::from_secs(3600)), RangeBound::Now);
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: -1h, stop: now())"#
+    /// );
+    ///
+    /// let start = SystemTime::UNIX_EPOCH + Duration::from_millis(1622546165684);
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .range(start, "-5m".parse::<RangeBound>().unwrap());
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: 2021-06-01T11:16:05.684Z, stop: -5m)"#
+    /// );
+    /// ```
+    pub fn range(self, start: impl Into<RangeBound>, stop: impl Into<RangeBound>) -> Self {
+        let line = format!("range(start: {}, stop: {})", start.into(), stop.into());
+        self.then(line)
+    }
+
+    /// Add a `range` stage from `start` up to now.
+    pub fn range_from(self, start: impl Into<RangeBound>) -> Self {
+        let line = format!("range(start: {})", start.into());
+        self.then(line)
+    }
+
+    /// Keep only records of the measurement `measurement`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_measurement("cpu")
+    ///     .filter_field("usage")
+    ///     .filter_tag("host", r#"web "1""#);
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: -1h)
+    ///  |> filter(fn: (r) => r._measurement == "cpu")
+    ///  |> filter(fn: (r) => r._field == "usage")
+    ///  |> filter(fn: (r) => r.host == "web \"1\"")"#
+    /// );
+    /// ```
+    pub fn filter_measurement(self, measurement: impl AsRef<str>) -> Self {
+        self.filter_eq("_measurement", measurement.as_ref())
+    }
+
+    /// Keep only records of the field `field`.
+    pub fn filter_field(self, field: impl AsRef<str>) -> Self {
+        self.filter_eq("_field", field.as_ref())
+    }
+
+    /// Keep only records where the tag `key` has the value `value`.
+    pub fn filter_tag(self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        self.filter_eq(key, value.as_ref())
+    }
+
+    /// Keep only records where `column` has the value `value`. Both are escaped, so they can
+    /// come from user input: columns that aren't identifiers are accessed as `r["column"]`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_eq("_value", 1.0);
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: -1h)
+    ///  |> filter(fn: (r) => r._value == 1.0)"#
+    /// );
+    /// ```
+    pub fn filter_eq(self, column: impl AsRef<str>, value: impl Into<FluxValue>) -> Self {
+        let line = format!(
+            "filter(fn: (r) => {} == {})",
+            column_access("r", column.as_ref()),
+            value.into()
+        );
+        self.then(line)
+    }
+
+    /// Sort the records of each table by `_time` on the server.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .sort_by_time();
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: -1h)
+    ///  |> sort(columns: ["_time"])"#
+    /// );
+    /// ```
+    pub fn sort_by_time(self) -> Self {
+        self.then(r#"sort(columns: ["_time"])"#)
+    }
+
+    /// Aggregate the records of each table into windows of length `every` with the aggregate
+    /// function `function`, such as `mean` or `last`. With `create_empty`, windows without
+    /// records are kept with a null value.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// # use std::time::Duration;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .aggregate_window(Duration::from_secs(300), "mean", false)
+    ///     .group(["host"])
+    ///     .pivot()
+    ///     .sort(["_time"], true)
+    ///     .limit(10);
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: -1h)
+    ///  |> aggregateWindow(every: 5m, fn: mean, createEmpty: false)
+    ///  |> group(columns: ["host"])
+    ///  |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")
+    ///  |> sort(columns: ["_time"], desc: true)
+    ///  |> limit(n: 10)"#
+    /// );
+    /// ```
+    pub fn aggregate_window(self, every: Duration, function: &str, create_empty: bool) -> Self {
+        let line = format!(
+            "aggregateWindow(every: {}, fn: {}, createEmpty: {})",
+            FluxValue::from(every),
+            function,
+            create_empty
+        );
+        self.then(line)
+    }
+
+    /// Group the records by the values of `columns`, or into a single table without any columns.
+    pub fn group<C: AsRef<str>>(self, columns: impl IntoIterator<Item = C>) -> Self {
+        let line = format!("group(columns: {})", string_array(columns));
+        self.then(line)
+    }
+
+    /// Turn the values of fields into columns, with one row per timestamp.
+    pub fn pivot(self) -> Self {
+        self.then(r#"pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")"#)
+    }
+
+    /// Sort the records of each table by `columns`, in descending order if `desc` is set.
+    pub fn sort<C: AsRef<str>>(self, columns: impl IntoIterator<Item = C>, desc: bool) -> Self {
+        let line = format!("sort(columns: {}, desc: {})", string_array(columns), desc);
+        self.then(line)
+    }
+
+    /// Keep only the first `n` records of each table.
+    pub fn limit(self, n: usize) -> Self {
+        self.then(format!("limit(n: {})", n))
+    }
+
+    /// Replace each record with the result of the Flux function `function`, such as
+    /// `(r) => ({r with _value: r._value * 100.0})`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// # use std::time::Duration;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_field("bytes_sent")
+    ///     .keep(["_time", "_value", "host"])
+    ///     .derivative(Duration::from_secs(1), true)
+    ///     .map("(r) => ({r with _value: r._value * 8.0})")
+    ///     .rename([("_value", "bits per second")]);
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: -1h)
+    ///  |> filter(fn: (r) => r._field == "bytes_sent")
+    ///  |> keep(columns: ["_time", "_value", "host"])
+    ///  |> derivative(unit: 1s, nonNegative: true)
+    ///  |> map(fn: (r) => ({r with _value: r._value * 8.0}))
+    ///  |> rename(columns: {_value: "bits per second"})"#
+    /// );
+    /// ```
+    pub fn map(self, function: impl AsRef<str>) -> Self {
+        self.then(format!("map(fn: {})", function.as_ref()))
+    }
+
+    /// Keep only `columns`, dropping all others.
+    pub fn keep<C: AsRef<str>>(self, columns: impl IntoIterator<Item = C>) -> Self {
+        self.then(format!("keep(columns: {})", string_array(columns)))
+    }
+
+    /// Drop `columns`, keeping all others.
+    pub fn drop_columns<C: AsRef<str>>(self, columns: impl IntoIterator<Item = C>) -> Self {
+        self.then(format!("drop(columns: {})", string_array(columns)))
+    }
+
+    /// Rename columns, from the first to the second element of each pair in `columns`.
+    pub fn rename<K: AsRef<str>, V: AsRef<str>>(
+        self,
+        columns: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        let properties: Vec<_> = columns
+            .into_iter()
+            .map(|(from, to)| {
+                format!(
+                    "{}: {}",
+                    record_key(from.as_ref()),
+                    FluxValue::from(to.as_ref())
+                )
+            })
+            .collect();
+        self.then(format!("rename(columns: {{{}}})", properties.join(", ")))
+    }
+
+    /// Replace values with their rate of change per `unit`. With `non_negative`, negative rates,
+    /// such as when a counter is reset, are replaced with null.
+    pub fn derivative(self, unit: Duration, non_negative: bool) -> Self {
+        let line = format!(
+            "derivative(unit: {}, nonNegative: {})",
+            FluxValue::from(unit),
+            non_negative
+        );
+        self.then(line)
+    }
+
+    /// Replace values with the difference from the previous value.
+    pub fn difference(self) -> Self {
+        self.then("difference()")
+    }
+
+    /// Name the result of the statement with `yield`, so that it can be told apart from the
+    /// results of other statements, see `InfluxClient::query_yields`.
+    pub fn yield_as(self, name: &str) -> Self {
+        self.then(format!("yield(name: {})", FluxValue::from(name)))
+    }
+
+    /// Merge the tables of `self` and `other` with `union`. The result of each query is
+    /// assigned to a variable (unless its last statement already is an assignment), and lines
+    /// added with `then` afterwards are piped into the union.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let cpu = Query::new(r#"from(bucket: "a")"#).then("range(start: -1h)");
+    /// let mem = Query::new(r#"from(bucket: "b")"#).then("range(start: -1h)");
+    /// assert_eq!(
+    ///     cpu.union(mem).group(["host"]).to_string(),
+    ///     r#"table0 = from(bucket: "a")
+    ///  |> range(start: -1h)
+    /// table1 = from(bucket: "b")
+    ///  |> range(start: -1h)
+    /// union(tables: [table0, table1])
+    ///  |> group(columns: ["host"])"#
+    /// );
+    /// ```
+    pub fn union(self, other: Query) -> Self {
+        self.combine(other, |left, right| {
+            format!("union(tables: [{}, {}])", left, right)
+        })
+    }
+
+    /// Join the tables of `self` and `other` on the values of `columns`, with `join`. Columns
+    /// that are in both tables but not in `columns` are suffixed with `_` and the name of the
+    /// table, `name` for `self` and `other_name` for `other`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let errors = Query::new(r#"from(bucket: "http")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_field("errors");
+    /// let requests = Query::new(r#"from(bucket: "http")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_field("requests");
+    /// let ratio = errors
+    ///     .join("errors", requests, "requests", ["_time", "host"])
+    ///     .map("(r) => ({r with _value: float(v: r._value_errors) / float(v: r._value_requests)})");
+    /// assert_eq!(
+    ///     ratio.to_string(),
+    ///     r#"table0 = from(bucket: "http")
+    ///  |> range(start: -1h)
+    ///  |> filter(fn: (r) => r._field == "errors")
+    /// table1 = from(bucket: "http")
+    ///  |> range(start: -1h)
+    ///  |> filter(fn: (r) => r._field == "requests")
+    /// join(tables: {errors: table0, requests: table1}, on: ["_time", "host"])
+    ///  |> map(fn: (r) => ({r with _value: float(v: r._value_errors) / float(v: r._value_requests)}))"#
+    /// );
+    /// ```
+    pub fn join<C: AsRef<str>>(
+        self,
+        name: &str,
+        other: Query,
+        other_name: &str,
+        columns: impl IntoIterator<Item = C>,
+    ) -> Self {
+        let columns = string_array(columns);
+        self.combine(other, |left, right| {
+            format!(
+                "join(tables: {{{}: {}, {}: {}}}, on: {})",
+                record_key(name),
+                left,
+                record_key(other_name),
+                right,
+                columns
+            )
+        })
+    }
+
+    /// Join the tables of `self` and `other` with `experimental.join`, which joins rows with the
+    /// same group key and `_time`, and merges them with the Flux function `function` of `left`
+    /// and `right`, such as `(left, right) => ({left with used: right._value})`.
+    pub fn join_with(self, other: Query, function: &str) -> Self {
+        self.import("experimental").combine(other, |left, right| {
+            format!(
+                "experimental.join(left: {}, right: {}, fn: {})",
+                left, right, function
+            )
+        })
+    }
+
+    /// Append the statements, imports and options of `other`, assign the last statements of
+    /// `self` and `other` to variables, and start a new statement with `call` of the two
+    /// variable names.
+    ///
+    /// Variables of `other` that `self` also assigns are renamed, so that neither query refers
+    /// to a variable of the other.
+    fn combine(mut self, mut other: Query, call: impl FnOnce(&str, &str) -> String) -> Self {
+        let mut used = self.identifiers();
+        used.extend(other.identifiers());
+        let left = self.assign_last_statement(&mut used);
+        let assigned = self.assigned_variables();
+        for name in other.assigned_variables() {
+            if assigned.contains(&name) {
+                let renamed = fresh_variable(&mut used);
+                other.rename_variable(&name, &renamed);
+            }
+        }
+        let right = other.assign_last_statement(&mut used);
+
+        for import in other.imports {
+            self = self.import(import);
+        }
+        for option in other.options {
+            let name = option.split(" =").next();
+            if !self.options.iter().any(|o| o.split(" =").next() == name) {
+                self.options.push(option);
+            }
+        }
+        let offset = self.lines.len();
+        self.statement_starts.push(offset);
+        self.statement_starts
+            .extend(other.statement_starts.iter().map(|start| start + offset));
+        self.lines.extend(other.lines);
+        let line = call(&left, &right);
+        self.statement(line)
+    }
+
+    /// The name of the variable the last statement is assigned to, assigning it to a new
+    /// variable not in `used` if it isn't assigned yet.
+    fn assign_last_statement(&mut self, used: &mut Vec<String>) -> String {
+        let start = self.statement_starts.last().copied().unwrap_or(0);
+        if let Some(name) = assigned_variable(&self.lines[start]) {
+            return name.to_string();
+        }
+        let name = fresh_variable(used);
+        self.lines[start] = format!("{} = {}", name, self.lines[start]);
+        name
+    }
+
+    /// The names of the variables assigned by the statements of the query.
+    fn assigned_variables(&self) -> Vec<String> {
+        std::iter::once(0)
+            .chain(self.statement_starts.iter().copied())
+            .filter_map(|start| assigned_variable(self.lines.get(start)?))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// All identifiers in the lines of the query, outside of string literals and comments.
+    fn identifiers(&self) -> Vec<String> {
+        let mut identifiers = Vec::new();
+        for line in &self.lines {
+            map_identifiers(line, |identifier| {
+                identifiers.push(identifier.to_string());
+                None
+            });
+        }
+        identifiers
+    }
+
+    fn rename_variable(&mut self, from: &str, to: &str) {
+        for line in &mut self.lines {
+            *line = map_identifiers(line, |identifier| {
+                (identifier == from).then(|| to.to_string())
+            });
+        }
+    }
+
+    /// Rewrite the query so that it matches the patterns InfluxDB can push down to storage.
+    ///
+    /// Filters that only look at `_measurement` and `_field` are moved directly after `range`,
+    /// as long as they only have to move past stages that don't affect which rows they match.
+    /// Adjacent filters are then merged into a single `filter` call.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Query;
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .then("aggregateWindow(every: 1m, fn: mean)")
+    ///     .then(r#"filter(fn: (r) => r._measurement == "cpu")"#)
+    ///     .then(r#"filter(fn: (r) => r._field == "usage")"#)
+    ///     .optimize();
+    /// assert_eq!(
+    ///     query.to_string(),
+    ///     r#"from(bucket: "example_bucket")
+    ///  |> range(start: -1h)
+    ///  |> filter(fn: (r) => r._measurement == "cpu" and r._field == "usage")
+    ///  |> aggregateWindow(every: 1m, fn: mean)"#
+    /// );
+    /// ```
+    pub fn optimize(mut self) -> Self {
+        let pipelines: Vec<_> = self
+            .pipelines()
+            .map(|pipeline| optimize_lines(pipeline.to_vec()))
+            .collect();
+        self.lines.clear();
+        self.statement_starts.clear();
+        for (i, pipeline) in pipelines.into_iter().enumerate() {
+            if i > 0 {
+                self.statement_starts.push(self.lines.len());
+            }
+            self.lines.extend(pipeline);
+        }
+        self
+    }
+
+    /// The `start` and (if present) `stop` arguments of the first `range` stage.
+    pub(crate) fn range_arguments(&self) -> Option<(&str, Option<&str>)> {
+        let line = self.lines.iter().find(|l| stage_name(l) == "range")?;
+        let args = line.trim().strip_prefix("range(")?.strip_suffix(')')?;
+        let mut start = None;
+        let mut stop = None;
+        for arg in split_top_level(args)? {
+            match arg.split_once(':') {
+                Some((name, value)) if name.trim() == "start" => start = Some(value.trim()),
+                Some((name, value)) if name.trim() == "stop" => stop = Some(value.trim()),
+                _ => {}
+            }
+        }
+        Some((start?, stop))
+    }
+
+    /// The normalized form of the query that equality and hashing are based on.
+    fn semantic_form(&self) -> Vec<String> {
+        let pipelines = self.pipelines().map(|pipeline| {
+            let mut lines: Vec<String> = pipeline
+                .iter()
+                .map(|line| {
+                    let line = line.trim();
+                    crate::flux::format_flux(line.strip_prefix("|>").unwrap_or(line))
+                })
+                .collect();
+            // Adjacent filters commute, so their order doesn't matter.
+            let mut start = 0;
+            while start < lines.len() {
+                let end = start
+                    + lines[start..]
+                        .iter()
+                        .take_while(|line| stage_name(line) == "filter")
+                        .count();
+                lines[start..end].sort();
+                start = end.max(start + 1);
+            }
+            lines
+        });
+        // Statements are separated by an empty line, which no normalized line can be.
+        let lines = pipelines
+            .enumerate()
+            .flat_map(|(i, lines)| (i > 0).then(String::new).into_iter().chain(lines));
+        // The order of imports doesn't matter either.
+        let mut imports = self.imports.clone();
+        imports.sort();
+        let options = self.options.iter().map(|o| crate::flux::format_flux(o));
+        imports.into_iter().chain(options).chain(lines).collect()
+    }
+
+    /// Replace the first `range` stage with one using the given bounds.
+    pub(crate) fn with_range(mut self, start: impl Display, stop: impl Display) -> Self {
+        if let Some(line) = self.lines.iter_mut().find(|l| stage_name(l) == "range") {
+            *line = format!("range(start: {}, stop: {})", start, stop);
+        }
+        self
+    }
+}
+
+/// A line of a `Query`, see `Query::line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryLine {
+    /// A line piped into the previous line with `|>`, see `Query::then`
+    Piped(String),
+    /// A line that starts a new statement, see `Query::statement`
+    Statement(String),
+}
+
+/// A bound of a time range, see `Query::range`.
+///
+/// Bounds can also be parsed from Flux time literals: relative durations such as `-1h30m`,
+/// RFC3339 timestamps, and `now()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeBound {
+    /// An absolute time, in nanoseconds since the Unix epoch.
+    Time(i128),
+    /// A time relative to when the query runs, in nanoseconds (negative for the past).
+    Relative(i128),
+    /// The time when the query runs.
+    Now,
+}
+
+impl RangeBound {
+    /// The time `duration` before the query runs.
+    pub fn ago(duration: Duration) -> Self {
+        RangeBound::Relative(-(duration.as_nanos() as i128))
+    }
+
+    /// The time `duration` after the query runs.
+    pub fn from_now(duration: Duration) -> Self {
+        RangeBound::Relative(duration.as_nanos() as i128)
+    }
+
+    /// The bound in nanoseconds since the Unix epoch, for a query running at `now_nanos`.
+    pub(crate) fn resolve(self, now_nanos: i128) -> i128 {
+        match self {
+            RangeBound::Time(nanos) => nanos,
+            RangeBound::Relative(nanos) => now_nanos + nanos,
+            RangeBound::Now => now_nanos,
+        }
+    }
+}
+
+impl From<SystemTime> for RangeBound {
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since) => RangeBound::Time(since.as_nanos() as i128),
+            Err(e) => RangeBound::Time(-(e.duration().as_nanos() as i128)),
+        }
+    }
+}
+
+impl FromStr for RangeBound {
+    type Err = InfluxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "now()" {
+            return Ok(RangeBound::Now);
+        }
+        datetime::parse_duration(s)
+            .map(RangeBound::Relative)
+            .or_else(|| datetime::parse_rfc3339(s).map(RangeBound::Time))
+            .ok_or_else(|| InfluxError::InvalidQuery(format!("invalid range bound '{}'", s)))
+    }
+}
+
+impl Display for RangeBound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RangeBound::Time(nanos) => write!(f, "{}", FluxValue::Time(*nanos)),
+            RangeBound::Relative(nanos) => write!(f, "{}", FluxValue::Duration(*nanos)),
+            RangeBound::Now => f.write_str("now()"),
+        }
+    }
+}
+
+/// Stages that a filter on `_measurement` or `_field` can be moved in front of without changing
+/// the result, because they keep both columns and either filter rows or work on whole tables.
+const PUSHDOWN_SAFE_STAGES: &[&str] = &[
+    "filter",
+    "aggregateWindow",
+    "window",
+    "mean",
+    "median",
+    "sum",
+    "count",
+    "min",
+    "max",
+    "first",
+    "last",
+    "spread",
+    "stddev",
+    "sort",
+];
+
+/// Columns that InfluxDB can filter on in storage.
+const PUSHDOWN_COLUMNS: &[&str] = &["_measurement", "_field"];
+
+fn optimize_lines(lines: Vec<String>) -> Vec<String> {
+    let range = match lines.iter().position(|l| stage_name(l) == "range") {
+        Some(range) => range,
+        None => return lines,
+    };
+
+    let mut lines = lines.into_iter();
+    let mut optimized: Vec<String> = lines.by_ref().take(range + 1).collect();
+    let mut rest = Vec::new();
+    let mut blocked = false;
+    for line in lines {
+        if !blocked {
+            if FilterStage::parse(&line).is_some_and(|f| f.is_pushdown()) {
+                optimized.push(line);
+                continue;
+            }
+            blocked = !PUSHDOWN_SAFE_STAGES.contains(&stage_name(&line));
+        }
+        rest.push(line);
+    }
+    optimized.extend(rest);
+
+    merge_adjacent_filters(optimized)
+}
+
+fn merge_adjacent_filters(lines: Vec<String>) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::with_capacity(lines.len());
+    for line in lines {
+        let combined = match (merged.last(), FilterStage::parse(&line)) {
+            (Some(previous), Some(current)) => {
+                FilterStage::parse(previous).and_then(|previous| previous.merge(&current))
+            }
+            _ => None,
+        };
+        match combined {
+            Some(combined) => *merged.last_mut().unwrap() = combined,
+            None => merged.push(line),
+        }
+    }
+    merged
+}
+
+/// The name of the function called in a stage, e.g. `filter` for `filter(fn: (r) => true)`.
+fn stage_name(line: &str) -> &str {
+    let line = line.trim();
+    line.find('(').map_or(line, |i| line[..i].trim_end())
+}
+
+/// A stage of the form `filter(fn: (param) => body)`, without any other arguments.
+struct FilterStage<'a> {
+    param: &'a str,
+    body: &'a str,
+}
+
+impl<'a> FilterStage<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let inner = line.trim().strip_prefix("filter(")?.strip_suffix(')')?;
+        if !is_single_argument(inner) {
+            return None;
+        }
+        let function = inner.trim_start().strip_prefix("fn:")?.trim_start();
+        let (param, body) = function.strip_prefix('(')?.split_once(')')?;
+        let param = param.trim();
+        if param.is_empty() || !param.chars().all(is_ident_char) {
+            return None;
+        }
+        let body = body.trim_start().strip_prefix("=>")?.trim();
+        Some(Self { param, body })
+    }
+
+    /// Whether the predicate only references columns that can be filtered on in storage.
+    fn is_pushdown(&self) -> bool {
+        match self.referenced_columns() {
+            Some(columns) => {
+                !columns.is_empty() && columns.iter().all(|c| PUSHDOWN_COLUMNS.contains(c))
+            }
+            None => false,
+        }
+    }
+
+    /// The columns of the record referenced in the predicate, or `None` if the record is used in
+    /// a way that can't be analyzed (e.g. passed to another function).
+    fn referenced_columns(&self) -> Option<Vec<&'a str>> {
+        let body = self.body;
+        let mut columns = Vec::new();
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut previous = None;
+        let mut chars = body.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+            } else if c == '"' {
+                in_string = true;
+            } else if body[i..].starts_with(self.param) && !previous.is_some_and(is_ident_char) {
+                let after = &body[i + self.param.len()..];
+                if after.starts_with(is_ident_char) {
+                    // Some other identifier that starts with the parameter name.
+                } else if let Some(member) = after.strip_prefix('.') {
+                    let end = member.find(|c| !is_ident_char(c)).unwrap_or(member.len());
+                    columns.push(&member[..end]);
+                } else if let Some(index) = after.strip_prefix("[\"") {
+                    let end = index.find('"')?;
+                    if !index[end + 1..].starts_with(']') {
+                        return None;
+                    }
+                    columns.push(&index[..end]);
+                } else {
+                    return None;
+                }
+                // Skip past the parameter name itself, the accessor is plain text from here.
+                for _ in 0..self.param.chars().count() - 1 {
+                    chars.next();
+                }
+            }
+            previous = Some(c);
+        }
+        Some(columns)
+    }
+
+    /// Combine two filters with the same parameter name into one using `and`.
+    fn merge(&self, other: &FilterStage) -> Option<String> {
+        if self.param != other.param {
+            return None;
+        }
+        Some(format!(
+            "filter(fn: ({}) => {} and {})",
+            self.param,
+            parenthesize_or(self.body),
+            parenthesize_or(other.body)
+        ))
+    }
+}
+
+/// Access `column` of the record `param`, as `r.column` if the column is an identifier and as
+/// `r["column"]` otherwise.
+fn column_access(param: &str, column: &str) -> String {
+    if is_identifier(column) {
+        format!("{}.{}", param, column)
+    } else {
+        format!("{}[{}]", param, FluxValue::from(column))
+    }
+}
+
+/// `key` as the key of a property in a Flux record literal, quoted unless it's an identifier.
+fn record_key(key: &str) -> String {
+    if is_identifier(key) {
+        key.to_string()
+    } else {
+        FluxValue::from(key).to_string()
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && s.chars().all(is_ident_char)
+}
+
+/// The variable `line` assigns the result of its statement to, if any.
+fn assigned_variable(line: &str) -> Option<&str> {
+    let (name, rest) = line.split_once('=')?;
+    let name = name.trim();
+    (is_identifier(name) && !rest.starts_with('=')).then_some(name)
+}
+
+/// A variable name of the form `tableN` that is not in `used`, which it's added to.
+fn fresh_variable(used: &mut Vec<String>) -> String {
+    let name = (0..)
+        .map(|i| format!("table{}", i))
+        .find(|name| !used.contains(name))
+        .unwrap_or_default();
+    used.push(name.clone());
+    name
+}
+
+/// `line` with every identifier outside of string literals and comments replaced by the result
+/// of `f`, if it returns one. Members (such as `r.host`) aren't identifiers.
+fn map_identifiers(line: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut mapped = String::with_capacity(line.len());
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                mapped.push(c);
+                let mut escaped = false;
+                for (_, c) in chars.by_ref() {
+                    mapped.push(c);
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek().is_some_and(|(_, c)| *c == '/') => {
+                mapped.push_str(&line[i..]);
+                break;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = i + c.len_utf8();
+                while let Some((j, c)) = chars.next_if(|(_, c)| is_ident_char(*c)) {
+                    end = j + c.len_utf8();
+                }
+                let identifier = &line[i..end];
+                let is_member = line[..i].trim_end().ends_with('.');
+                match f(identifier).filter(|_| !is_member) {
+                    Some(replacement) => mapped.push_str(&replacement),
+                    None => mapped.push_str(identifier),
+                }
+            }
+            c => mapped.push(c),
+        }
+    }
+    mapped
+}
+
+/// `strings` as a Flux array of string literals.
+fn string_array<S: AsRef<str>>(strings: impl IntoIterator<Item = S>) -> String {
+    let literals: Vec<_> = strings
+        .into_iter()
+        .map(|s| FluxValue::from(s.as_ref()).to_string())
+        .collect();
+    format!("[{}]", literals.join(", "))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `args` (the inside of a function call) contains exactly one argument.
+fn is_single_argument(args: &str) -> bool {
+    split_top_level(args).is_some_and(|args| args.len() == 1)
+}
+
+/// Split the inside of a function call into its arguments, or `None` if the brackets and quotes
+/// are unbalanced.
+fn split_top_level(args: &str) -> Option<Vec<&str>> {
+    let mut split = Vec::new();
+    let mut depth = 0_i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth < 0 {
+                    return None;
+                }
+            }
+            ',' if depth == 0 => {
+                split.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 || in_string {
+        return None;
+    }
+    split.push(&args[start..]);
+    Some(split)
+}
+
+/// Wrap a predicate in parentheses if it contains an `or`, so that it can be joined with `and`.
+fn parenthesize_or(predicate: &str) -> String {
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut word = String::new();
+    let mut has_or = false;
+    for c in predicate.chars().chain(std::iter::once(' ')) {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if is_ident_char(c) {
+            word.push(c);
+            continue;
+        } else if c == '"' {
+            in_string = true;
+        }
+        has_or |= word == "or";
+        word.clear();
+    }
+    if has_or {
+        format!("({})", predicate)
+    } else {
+        predicate.to_string()
+    }
+}
+
+impl PartialEq for Query {
+    fn eq(&self, other: &Self) -> bool {
+        (self.imports == other.imports
+            && self.options == other.options
+            && self.lines == other.lines
+            && self.statement_starts == other.statement_starts)
+            || self.semantic_form() == other.semantic_form()
+    }
+}
+
+impl Eq for Query {}
+
+impl Hash for Query {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.semantic_form().hash(state);
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut preamble = self.preamble().peekable();
+        if preamble.peek().is_some() {
+            for statement in preamble {
+                writeln!(f, "{}", statement)?;
+            }
+            writeln!(f)?;
+        }
+        let statements: Vec<_> = self
+            .pipelines()
+            .map(|pipeline| pipeline.join("\n |> "))
+            .collect();
+        write!(f, "{}", statements.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(stages: &[&str]) -> Query {
+        let mut stages = stages.iter();
+        let mut query = Query::new(*stages.next().unwrap());
+        for stage in stages {
+            query = query.then(*stage);
+        }
+        query
+    }
+
+    #[test]
+    fn semantic_equality() {
+        use std::collections::HashSet;
+
+        let a = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "range( start: -1h )",
+            r#"filter(fn: (r) => r._measurement == "cpu")"#,
+            r#"filter(fn: (r) => r._field == "usage")"#,
+            "mean()",
+        ]);
+        let b = Query::raw(
+            r#"from(bucket:  "b")
+            |> range(start: -1h)
+            |> filter(fn: (r) => r._field == "usage")
+            |> filter(fn: (r) =>   r._measurement == "cpu")
+            |> mean()"#,
+        );
+        let c = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "range(start: -1h)",
+            r#"filter(fn: (r) => r._measurement == "cpu")"#,
+            "mean()",
+            r#"filter(fn: (r) => r._field == "usage")"#,
+        ]);
+        let d = pipeline(&[r#"from(bucket: "b  ")"#, "range(start: -1h)"]);
+        let e = pipeline(&[r#"from(bucket: "b ")"#, "range(start: -1h)"]);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(d, e);
+        let set: HashSet<_> = vec![a, b, c].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn optimize_moves_pushdown_filters_after_range() {
+        let query = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "range(start: -1h)",
+            "aggregateWindow(every: 1m, fn: mean)",
+            r#"filter(fn: (r) => r["_measurement"] == "cpu")"#,
+            r#"filter(fn: (r) => r._value > 10.0)"#,
+            r#"filter(fn: (r) => r._field == "usage" or r._field == "idle")"#,
+        ]);
+        assert_eq!(
+            query.clone().to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h)
+ |> aggregateWindow(every: 1m, fn: mean)
+ |> filter(fn: (r) => r["_measurement"] == "cpu")
+ |> filter(fn: (r) => r._value > 10.0)
+ |> filter(fn: (r) => r._field == "usage" or r._field == "idle")"#
+        );
+        assert_eq!(
+            query.optimize().to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h)
+ |> filter(fn: (r) => r["_measurement"] == "cpu" and (r._field == "usage" or r._field == "idle"))
+ |> aggregateWindow(every: 1m, fn: mean)
+ |> filter(fn: (r) => r._value > 10.0)"#
+        );
+    }
+
+    #[test]
+    fn optimize_does_not_move_filters_past_unsafe_stages() {
+        let query = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "range(start: -1h)",
+            r#"filter(fn: (r) => r.host == "a")"#,
+            r#"map(fn: (r) => ({r with _field: "renamed"}))"#,
+            r#"filter(fn: (r) => r._field == "renamed")"#,
+        ]);
+        assert_eq!(
+            query.optimize().to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h)
+ |> filter(fn: (r) => r.host == "a")
+ |> map(fn: (r) => ({r with _field: "renamed"}))
+ |> filter(fn: (r) => r._field == "renamed")"#
+        );
+    }
+
+    #[test]
+    fn optimize_merges_adjacent_filters() {
+        let query = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "range(start: -1h)",
+            r#"filter(fn: (r) => r._measurement == "cpu")"#,
+            r#"filter(fn: (r) => r.host == "a")"#,
+            r#"filter(fn: (row) => row.region == "eu")"#,
+            r#"filter(fn: (r) => r.cpu == "cpu0", onEmpty: "keep")"#,
+        ]);
+        assert_eq!(
+            query.optimize().to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h)
+ |> filter(fn: (r) => r._measurement == "cpu" and r.host == "a")
+ |> filter(fn: (row) => row.region == "eu")
+ |> filter(fn: (r) => r.cpu == "cpu0", onEmpty: "keep")"#
+        );
+    }
+
+    #[test]
+    fn optimize_ignores_record_references_in_strings() {
+        let query = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "range(start: -1h)",
+            "limit(n: 10)",
+            r#"filter(fn: (r) => r._measurement == "r.host")"#,
+        ]);
+        assert_eq!(query.clone().optimize(), query);
+
+        let query = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "range(start: -1h)",
+            "sort()",
+            r#"filter(fn: (r) => r._measurement == "r.host")"#,
+        ]);
+        assert_eq!(
+            query.optimize().to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h)
+ |> filter(fn: (r) => r._measurement == "r.host")
+ |> sort()"#
+        );
+    }
+
+    #[test]
+    fn imports_and_options() {
+        let query = Query::new(r#"from(bucket: "b")"#)
+            .option("now", "() => 2021-06-01T00:00:00Z")
+            .import("strings")
+            .then("range(start: -1h)")
+            .import("experimental/aggregate")
+            .import("strings");
+        assert_eq!(
+            query.to_string(),
+            r#"import "strings"
+import "experimental/aggregate"
+option now = () => 2021-06-01T00:00:00Z
+
+from(bucket: "b")
+ |> range(start: -1h)"#
+        );
+
+        let reordered = Query::new(r#"from(bucket: "b")"#)
+            .then("range(start: -1h)")
+            .import("experimental/aggregate")
+            .import("strings")
+            .option("now", "()  =>  2021-06-01T00:00:00Z");
+        assert_eq!(query, reordered);
+        assert_ne!(query, reordered.clone().option("location", "timezone.utc"));
+        assert_eq!(
+            reordered
+                .option("now", "() => 2021-06-01T00:00:00Z")
+                .to_string()
+                .matches("option now")
+                .count(),
+            1
+        );
+        assert_ne!(
+            query,
+            Query::new(r#"from(bucket: "b")"#).then("range(start: -1h)")
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let query = Query::new(r#"a = from(bucket: "b")"#)
+            .then("range(start: -1h)")
+            .then("aggregateWindow(every: 1m, fn: mean)")
+            .then(r#"filter(fn: (r) => r._field == "usage")"#)
+            .statement(r#"filter(fn: (r) => r._measurement == "cpu")"#)
+            .statement("a")
+            .then("range(start: -1h)")
+            .then(r#"filter(fn: (r) => r.host == "x")"#)
+            .then(r#"filter(fn: (r) => r.cpu == "y")"#);
+        let optimized = query.clone().optimize();
+        assert_eq!(
+            optimized.to_string(),
+            r#"a = from(bucket: "b")
+ |> range(start: -1h)
+ |> filter(fn: (r) => r._field == "usage")
+ |> aggregateWindow(every: 1m, fn: mean)
+filter(fn: (r) => r._measurement == "cpu")
+a
+ |> range(start: -1h)
+ |> filter(fn: (r) => r.host == "x" and r.cpu == "y")"#
+        );
+
+        let swapped = Query::new(r#"a = from(bucket: "b")"#)
+            .then("range(start: -1h)")
+            .then("aggregateWindow(every: 1m, fn: mean)")
+            .then(r#"filter(fn: (r) => r._field == "usage")"#)
+            .statement(r#"filter(fn: (r) => r._measurement == "cpu")"#)
+            .statement("a")
+            .then("range(start: -1h)")
+            .then(r#"filter(fn: (r) => r.cpu == "y")"#)
+            .then(r#"filter(fn: (r) => r.host == "x")"#);
+        assert_eq!(query, swapped);
+        let piped = Query::new(r#"a = from(bucket: "b")"#)
+            .then("range(start: -1h)")
+            .then("aggregateWindow(every: 1m, fn: mean)")
+            .then(r#"filter(fn: (r) => r._field == "usage")"#)
+            .then(r#"filter(fn: (r) => r._measurement == "cpu")"#)
+            .statement("a")
+            .then("range(start: -1h)")
+            .then(r#"filter(fn: (r) => r.host == "x")"#)
+            .then(r#"filter(fn: (r) => r.cpu == "y")"#);
+        assert_ne!(query, piped);
+    }
+
+    #[test]
+    fn combined_queries() {
+        let a = Query::new(r#"from(bucket: "a")"#).import("strings");
+        let b = Query::new(r#"from(bucket: "b")"#)
+            .import("strings")
+            .import("math")
+            .option("location", "utc");
+        let c = Query::new(r#"from(bucket: "c")"#);
+        let query = a.union(b).join_with(c, "(left, right) => left");
+        assert_eq!(
+            query.to_string(),
+            r#"import "strings"
+import "math"
+import "experimental"
+option location = utc
+
+table0 = from(bucket: "a")
+table1 = from(bucket: "b")
+table2 = union(tables: [table0, table1])
+table3 = from(bucket: "c")
+experimental.join(left: table2, right: table3, fn: (left, right) => left)"#
+        );
+
+        let nested = Query::new(r#"from(bucket: "a")"#)
+            .union(Query::new(r#"from(bucket: "b")"#).union(Query::new(r#"from(bucket: "c")"#)));
+        assert_eq!(
+            nested.to_string(),
+            r#"table2 = from(bucket: "a")
+table0 = from(bucket: "b")
+table1 = from(bucket: "c")
+table3 = union(tables: [table0, table1])
+union(tables: [table2, table3])"#
+        );
+
+        let both_nested = Query::new("w")
+            .union(Query::new("x"))
+            .union(Query::new("y").union(Query::new("z")));
+        assert_eq!(
+            both_nested.to_string(),
+            r#"table0 = w
+table1 = x
+table2 = union(tables: [table0, table1])
+table3 = y
+table4 = z
+table5 = union(tables: [table3, table4])
+union(tables: [table2, table5])"#
+        );
+
+        let assigned = Query::new(r#"data = from(bucket: "a")"#)
+            .then("range(start: -1h)")
+            .union(
+                Query::new(r#"data = from(bucket: "b")"#)
+                    .statement(r#"data |> filter(fn: (r) => r.data == "data")"#),
+            );
+        assert_eq!(
+            assigned.to_string(),
+            r#"data = from(bucket: "a")
+ |> range(start: -1h)
+table0 = from(bucket: "b")
+table1 = table0 |> filter(fn: (r) => r.data == "data")
+union(tables: [data, table1])"#
+        );
+
+        let joined = Query::new("x").join("my table", Query::new("y"), "b", ["_time"]);
+        assert_eq!(
+            joined.to_string(),
+            r#"table0 = x
+table1 = y
+join(tables: {"my table": table0, b: table1}, on: ["_time"])"#
+        );
+    }
+
+    #[test]
+    fn raw_statements() {
+        let query = Query::raw(
+            r#"
+            data = from(bucket: "b")
+                |> range(start: -1h)
+
+            // the mean
+            data |> mean()"#,
+        );
+        assert_eq!(
+            query.lines(),
+            [
+                QueryLine::Statement(r#"data = from(bucket: "b")"#.to_string()),
+                QueryLine::Piped("range(start: -1h)".to_string()),
+                QueryLine::Statement("// the mean".to_string()),
+                QueryLine::Statement("data |> mean()".to_string()),
+            ]
+        );
+        assert_eq!(
+            query.to_string(),
+            r#"data = from(bucket: "b")
+ |> range(start: -1h)
+// the mean
+data |> mean()"#
+        );
+    }
+
+    #[test]
+    fn range_bounds() {
+        let query = Query::new(r#"from(bucket: "b")"#).range(
+            RangeBound::Time(-1),
+            RangeBound::from_now(Duration::from_millis(1500)),
+        );
+        assert_eq!(
+            query.range_arguments(),
+            Some(("1969-12-31T23:59:59.999999999Z", Some("1s500ms")))
+        );
+        let query = Query::new(r#"from(bucket: "b")"#).range_from(RangeBound::ago(Duration::ZERO));
+        assert_eq!(query.range_arguments(), Some(("0s", None)));
+
+        assert_eq!("now()".parse::<RangeBound>().unwrap(), RangeBound::Now);
+        assert_eq!(
+            "1970-01-01T00:00:01Z".parse::<RangeBound>().unwrap(),
+            RangeBound::Time(1_000_000_000)
+        );
+        assert!(matches!(
+            "1622493322".parse::<RangeBound>(),
+            Err(InfluxError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn range_arguments_and_replacement() {
+        let query = Query::new(r#"from(bucket: "b")"#)
+            .then(r#"range(stop: now(), start: time(v: "2021-06-01T00:00:00Z"))"#)
+            .then("range(start: -1h)")
+            .then("count()");
+        assert_eq!(
+            query.range_arguments(),
+            Some((r#"time(v: "2021-06-01T00:00:00Z")"#, Some("now()")))
+        );
+        assert_eq!(
+            query.with_range("-2h", "-1h"),
+            Query::new(r#"from(bucket: "b")"#)
+                .then("range(start: -2h, stop: -1h)")
+                .then("range(start: -1h)")
+                .then("count()")
+        );
+
+        let no_start = Query::new(r#"from(bucket: "b")"#).then("range(stop: now())");
+        assert_eq!(no_start.range_arguments(), None);
+        let no_range = Query::new(r#"from(bucket: "b")"#).then("count()");
+        assert_eq!(no_range.range_arguments(), None);
+        assert_eq!(no_range.clone().with_range("-2h", "-1h"), no_range);
+    }
+
+    #[test]
+    fn transformations() {
+        let query = Query::new(r#"from(bucket: "b")"#)
+            .range_from(RangeBound::ago(Duration::from_secs(86400)))
+            .filter_measurement("cpu")
+            .aggregate_window(Duration::from_millis(1500), "last", true)
+            .group(Vec::<String>::new())
+            .sort(["_value", "host \"a\""], false)
+            .limit(1);
+        assert_eq!(
+            query.clone().optimize(),
+            pipeline(&[
+                r#"from(bucket: "b")"#,
+                "range(start: -24h)",
+                r#"filter(fn: (r) => r._measurement == "cpu")"#,
+                "aggregateWindow(every: 1s500ms, fn: last, createEmpty: true)",
+                "group(columns: [])",
+                r#"sort(columns: ["_value", "host \"a\""], desc: false)"#,
+                "limit(n: 1)",
+            ])
+        );
+    }
+
+    #[test]
+    fn column_transformations() {
+        let query = Query::new(r#"from(bucket: "b")"#)
+            .then("range(start: -1h)")
+            .drop_columns(["_start", "_stop"])
+            .difference()
+            .rename([("_value", "delta"), ("host name", "host \"a\"")])
+            .rename(Vec::<(&str, &str)>::new());
+        assert_eq!(
+            query.to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h)
+ |> drop(columns: ["_start", "_stop"])
+ |> difference()
+ |> rename(columns: {_value: "delta", "host name": "host \"a\""})
+ |> rename(columns: {})"#
+        );
+    }
+
+    #[test]
+    fn filter_helpers_escape() {
+        let query = Query::new(r#"from(bucket: "b")"#)
+            .then("range(start: -1h)")
+            .filter_eq("_value", "x")
+            .filter_tag("host name", "a\\\" or true or \"${token}\n")
+            .filter_measurement("cpu")
+            .optimize();
+        assert_eq!(
+            query.to_string(),
+            r#"from(bucket: "b")
+ |> range(start: -1h)
+ |> filter(fn: (r) => r._measurement == "cpu" and r._value == "x" and r["host name"] == "a\\\" or true or \"\${token}\n")"#
+        );
+    }
+
+    #[test]
+    fn optimize_without_range_is_unchanged() {
+        let query = pipeline(&[
+            r#"from(bucket: "b")"#,
+            "mean()",
+            r#"filter(fn: (r) => r._measurement == "cpu")"#,
+        ]);
+        assert_eq!(query.clone().optimize(), query);
+    }
+}
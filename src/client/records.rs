@@ -0,0 +1,377 @@
+//! Helpers for working with the records returned by `InfluxClient::query`.
+
+use super::json::JsonError;
+use crate::datetime;
+use serde::{
+    de::{self, value::MapDeserializer, IntoDeserializer},
+    forward_to_deserialize_any,
+};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+/// Sorting and reshaping helpers for query results.
+///
+/// Records are returned in the order the server sends its tables, so records from different
+/// series interleave in ways that are rarely useful. All sorts are stable, and records missing a
+/// column sort before records that have it.
+///
+/// ## Example
+/// ```rust,no_run
+/// # use influxrs::{InfluxClient, Query, RecordsExt};
+/// # async fn example(client: InfluxClient) {
+/// let mut records = client
+///     .query(Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -1h)"))
+///     .await
+///     .unwrap();
+/// records.sort_by_time();
+/// # }
+/// ```
+pub trait RecordsExt {
+    /// Sort records by their `_time` column, comparing the timestamps rather than the strings.
+    fn sort_by_time(&mut self);
+
+    /// Sort records by the values of the given columns, in order of priority.
+    fn sort_by_columns(&mut self, columns: &[&str]);
+
+    /// The values of each column in the order of the records, with `None` where a record is
+    /// missing the column or its value is empty, such as for building a data frame.
+    ///
+    /// This crate has no polars integration, so there is no conversion to a polars `DataFrame`.
+    /// Each vector can be turned into a polars `Series`, and `FluxTable::column_values` gives
+    /// typed columns instead.
+    fn to_columns(&self) -> BTreeMap<&str, Vec<Option<&str>>>;
+}
+
+impl RecordsExt for [HashMap<String, String>] {
+    fn sort_by_time(&mut self) {
+        self.sort_by_cached_key(|record| {
+            record
+                .get("_time")
+                .map(|time| (datetime::parse_rfc3339(time), time.clone()))
+        });
+    }
+
+    fn sort_by_columns(&mut self, columns: &[&str]) {
+        self.sort_by(|a, b| {
+            columns
+                .iter()
+                .map(|column| a.get(*column).cmp(&b.get(*column)))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    fn to_columns(&self) -> BTreeMap<&str, Vec<Option<&str>>> {
+        let mut columns = BTreeMap::new();
+        for record in self.iter() {
+            for column in record.keys() {
+                columns.entry(column.as_str()).or_insert_with(Vec::new);
+            }
+        }
+        for (column, values) in &mut columns {
+            *values = self
+                .iter()
+                .map(|record| record.get(*column).map(String::as_str))
+                .map(|value| value.filter(|v| !v.is_empty()))
+                .collect();
+        }
+        columns
+    }
+}
+
+/// Turn records with a `_field` and `_value` column each into one record per row, with a column
+/// per field, like `Query::pivot` does on the server.
+///
+/// Records are rows of the same record when the values of all their other columns, except
+/// `table`, are the same, which is usually the `_time` and the tags. Rows are in the order of
+/// their first record. Records without a `_field` column are kept as they are.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::pivot_records;
+/// # use std::collections::HashMap;
+/// let record = |field: &str, value: &str| -> HashMap<String, String> {
+///     [("_time", "2021-06-01T11:16:05Z"), ("host", "a"), ("_field", field), ("_value", value)]
+///         .iter()
+///         .map(|(k, v)| (k.to_string(), v.to_string()))
+///         .collect()
+/// };
+/// let rows = pivot_records(vec![record("usage", "0.5"), record("temperature", "71")]);
+/// assert_eq!(rows.len(), 1);
+/// assert_eq!(rows[0]["usage"], "0.5");
+/// assert_eq!(rows[0]["temperature"], "71");
+/// assert_eq!(rows[0].get("_field"), None);
+/// ```
+pub fn pivot_records(records: Vec<HashMap<String, String>>) -> Vec<HashMap<String, String>> {
+    let mut rows: Vec<HashMap<String, String>> = Vec::new();
+    let mut row_indices: HashMap<Vec<(String, String)>, usize> = HashMap::new();
+    for mut record in records {
+        let field = match record.remove("_field") {
+            Some(field) => field,
+            None => {
+                rows.push(record);
+                continue;
+            }
+        };
+        let value = record.remove("_value").unwrap_or_default();
+        record.remove("table");
+        let mut key: Vec<(String, String)> = record
+            .iter()
+            .map(|(column, value)| (column.clone(), value.clone()))
+            .collect();
+        key.sort();
+        let index = *row_indices.entry(key).or_insert_with(|| {
+            rows.push(record);
+            rows.len() - 1
+        });
+        rows[index].insert(field, value);
+    }
+    rows
+}
+
+/// Deserialize a record into `T`, parsing the values of its columns into the types of the
+/// corresponding fields. Empty values are null, so they deserialize as `None`.
+pub(crate) fn from_record<T: de::DeserializeOwned>(
+    record: HashMap<String, String>,
+) -> Result<T, JsonError> {
+    T::deserialize(MapDeserializer::new(
+        record
+            .into_iter()
+            .map(|(column, value)| (column, ValueDeserializer(value))),
+    ))
+}
+
+/// Deserializes the value of a column, which is always a string in the CSV response.
+struct ValueDeserializer(String);
+
+impl ValueDeserializer {
+    fn parse<T: FromStr>(&self) -> Result<T, JsonError> {
+        self.0.parse().map_err(|_| {
+            de::Error::custom(format!(
+                "invalid value '{}', expected {}",
+                self.0,
+                std::any::type_name::<T>()
+            ))
+        })
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsonError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = JsonError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsonError> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, JsonError> {
+        if self.0.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, JsonError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, JsonError> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, JsonError> for ValueDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A record with the given columns, as returned by `InfluxClient::query`.
+    pub(crate) fn record(columns: &[(&str, &str)]) -> HashMap<String, String> {
+        columns
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn pivot_fields() {
+        let records = vec![
+            record(&[
+                ("table", "0"),
+                ("_time", "1"),
+                ("host", "a"),
+                ("_field", "x"),
+                ("_value", "1"),
+            ]),
+            record(&[
+                ("table", "0"),
+                ("_time", "2"),
+                ("host", "a"),
+                ("_field", "x"),
+                ("_value", "2"),
+            ]),
+            record(&[
+                ("table", "1"),
+                ("_time", "1"),
+                ("host", "a"),
+                ("_field", "y"),
+                ("_value", "3"),
+            ]),
+            record(&[
+                ("table", "2"),
+                ("_time", "1"),
+                ("host", "b"),
+                ("_field", "x"),
+                ("_value", "4"),
+            ]),
+            record(&[("_time", "1"), ("count", "5")]),
+        ];
+        assert_eq!(
+            pivot_records(records),
+            [
+                record(&[("_time", "1"), ("host", "a"), ("x", "1"), ("y", "3")]),
+                record(&[("_time", "2"), ("host", "a"), ("x", "2")]),
+                record(&[("_time", "1"), ("host", "b"), ("x", "4")]),
+                record(&[("_time", "1"), ("count", "5")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn records_to_columns() {
+        let records = [
+            record(&[("host", "a"), ("_value", "1")]),
+            record(&[("host", "b"), ("_value", ""), ("region", "eu")]),
+        ];
+        let columns = records.to_columns();
+        assert_eq!(
+            columns.keys().copied().collect::<Vec<_>>(),
+            ["_value", "host", "region"]
+        );
+        assert_eq!(columns["_value"], [Some("1"), None]);
+        assert_eq!(columns["host"], [Some("a"), Some("b")]);
+        assert_eq!(columns["region"], [None, Some("eu")]);
+    }
+
+    #[test]
+    fn deserialize_record() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Row {
+            host: String,
+            #[serde(rename = "_value")]
+            value: f64,
+            count: Option<u32>,
+            up: bool,
+            region: Option<String>,
+        }
+
+        let row: Row = from_record(record(&[
+            ("result", "_result"),
+            ("host", "a"),
+            ("_value", "1.5"),
+            ("count", ""),
+            ("up", "true"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            row,
+            Row {
+                host: "a".to_string(),
+                value: 1.5,
+                count: None,
+                up: true,
+                region: None,
+            }
+        );
+
+        let error = from_record::<Row>(record(&[("host", "a"), ("_value", "x"), ("up", "true")]))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "invalid value 'x', expected f64");
+    }
+
+    #[test]
+    fn sort_records() {
+        let mut records = vec![
+            record(&[("_time", "2021-06-01T11:16:05.1Z"), ("host", "b")]),
+            record(&[("_time", "2021-06-01T11:16:05Z"), ("host", "a")]),
+            record(&[("_time", "2021-06-01T11:16:05.09Z"), ("host", "b")]),
+        ];
+
+        records.sort_by_time();
+        let times = |records: &[HashMap<String, String>]| {
+            records
+                .iter()
+                .map(|r| r["_time"].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            times(&records),
+            vec![
+                "2021-06-01T11:16:05Z",
+                "2021-06-01T11:16:05.09Z",
+                "2021-06-01T11:16:05.1Z"
+            ]
+        );
+
+        records.sort_by_columns(&["host"]);
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| r["host"].as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "b"]
+        );
+        assert_eq!(records[1]["_time"], "2021-06-01T11:16:05.09Z");
+    }
+}
@@ -0,0 +1,139 @@
+//! Learning the schema of everything written through a client, see
+//! `InfluxClientBuilder::learn_schema`.
+
+use super::json::{self, JsonError};
+use crate::Measurement;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The measurements, tags and fields observed in writes, see `InfluxClient::learned_schema`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WriteSchema {
+    measurements: BTreeMap<String, MeasurementSchema>,
+}
+
+/// The tags and fields observed for a single measurement name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MeasurementSchema {
+    tags: BTreeSet<String>,
+    /// Field names and the types they have been written with.
+    fields: BTreeMap<String, BTreeSet<&'static str>>,
+}
+
+impl WriteSchema {
+    /// Record the schema of a measurement.
+    pub(crate) fn observe(&mut self, measurement: &Measurement) {
+        let schema = self
+            .measurements
+            .entry(measurement.measurement_name.clone())
+            .or_default();
+        for name in measurement.tags.keys() {
+            if !schema.tags.contains(name.as_ref()) {
+                schema.tags.insert(name.to_string());
+            }
+        }
+        for (name, value) in measurement.fields.iter() {
+            schema
+                .fields
+                .entry(name.to_string())
+                .or_default()
+                .insert(value.data_type());
+        }
+    }
+
+    /// Record a field of a measurement with the given tags, for schemas not learned from writes.
+    pub(crate) fn observe_field<'a>(
+        &mut self,
+        measurement: &str,
+        tags: impl Iterator<Item = &'a str>,
+        field: &str,
+        data_type: &'static str,
+    ) {
+        let schema = self
+            .measurements
+            .entry(measurement.to_string())
+            .or_default();
+        for name in tags {
+            if !schema.tags.contains(name) {
+                schema.tags.insert(name.to_string());
+            }
+        }
+        schema
+            .fields
+            .entry(field.to_string())
+            .or_default()
+            .insert(data_type);
+    }
+
+    /// The observed measurements, sorted by name.
+    pub fn measurements(&self) -> impl Iterator<Item = (&str, &MeasurementSchema)> {
+        self.measurements.iter().map(|(name, s)| (name.as_str(), s))
+    }
+
+    /// The schema of the measurement with the given name, if it has been observed.
+    pub fn measurement(&self, name: &str) -> Option<&MeasurementSchema> {
+        self.measurements.get(name)
+    }
+
+    /// Export the schema as a JSON document.
+    ///
+    /// ## Example
+    /// ```json
+    /// {"measurements":{"m1":{"tags":["tag1"],"fields":{"field1":["string"]}}}}
+    /// ```
+    pub fn to_json(&self) -> Result<String, JsonError> {
+        json::to_string(self)
+    }
+}
+
+impl MeasurementSchema {
+    /// The observed tag keys, sorted.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(|t| t.as_str())
+    }
+
+    /// The observed field keys, sorted, and the types they have been written with (`float`,
+    /// `string`, `boolean`, `integer` or `unsigned`).
+    pub fn fields(&self) -> impl Iterator<Item = (&str, Vec<&'static str>)> {
+        self.fields
+            .iter()
+            .map(|(name, types)| (name.as_str(), types.iter().copied().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learn_schema() {
+        let mut schema = WriteSchema::default();
+        let m1 = Measurement::builder("m1")
+            .tag("tag1", "a")
+            .field("field1", "string_value")
+            .field("field2", 1.5)
+            .build()
+            .unwrap();
+        let m2 = Measurement::builder("m1")
+            .tag("tag2", "b")
+            .field("field2", 1)
+            .build()
+            .unwrap();
+        schema.observe(&m1);
+        schema.observe(&m2);
+
+        let m1_schema = schema.measurement("m1").unwrap();
+        assert_eq!(m1_schema.tags().collect::<Vec<_>>(), vec!["tag1", "tag2"]);
+        assert_eq!(
+            m1_schema.fields().collect::<Vec<_>>(),
+            vec![
+                ("field1", vec!["string"]),
+                ("field2", vec!["float", "integer"])
+            ]
+        );
+        assert_eq!(
+            schema.to_json().unwrap(),
+            r#"{"measurements":{"m1":{"tags":["tag1","tag2"],"fields":{"field1":["string"],"field2":["float","integer"]}}}}"#
+        );
+    }
+}
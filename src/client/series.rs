@@ -0,0 +1,263 @@
+//! Client-side filtering of query results by series, see `SeriesFilter`.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// Columns of a query result that vary within a series, and so don't identify it.
+const NON_SERIES_COLUMNS: &[&str] = &["", "result", "table", "_start", "_stop", "_time", "_value"];
+
+/// Identifies a series within a `SeriesFilter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SeriesId(usize);
+
+/// The columns that identify a series, such as `_measurement`, `_field` and the tags.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SeriesKey(Vec<(String, String)>);
+
+impl SeriesKey {
+    fn of(record: &HashMap<String, String>) -> Self {
+        let mut columns: Vec<_> = record
+            .iter()
+            .filter(|(column, _)| !NON_SERIES_COLUMNS.contains(&column.as_str()))
+            .map(|(column, value)| (column.clone(), value.clone()))
+            .collect();
+        columns.sort_unstable();
+        Self(columns)
+    }
+
+    /// The columns and values of the key, sorted by column.
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(c, v)| (c.as_str(), v.as_str()))
+    }
+
+    /// The value of a column of the key.
+    pub fn get(&self, column: &str) -> Option<&str> {
+        self.0
+            .binary_search_by(|(c, _)| c.as_str().cmp(column))
+            .ok()
+            .map(|i| self.0[i].1.as_str())
+    }
+}
+
+/// An index of the series in a query result, for toggling the visibility of series without
+/// reparsing or rehashing the records.
+///
+/// The series of every record is computed once, when the filter is built. After that, toggling
+/// a series is a single bit flip, and filtering is a single pass over the records that doesn't
+/// look at their contents.
+///
+/// ## Example
+/// ```rust,no_run
+/// # use influxrs::{InfluxClient, Query, SeriesFilter};
+/// # async fn example(client: InfluxClient) {
+/// let records = client
+///     .query(Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -1h)"))
+///     .await
+///     .unwrap();
+/// let mut filter = SeriesFilter::new(&records);
+/// filter.set_all_visible(false);
+/// filter.set_visible_where(|key| key.get("host") == Some("web-1"), true);
+/// let visible: Vec<_> = filter.filter(&records).collect();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeriesFilter {
+    keys: Vec<SeriesKey>,
+    /// Ids of the series with a key hashing to a value, usually just one
+    by_hash: HashMap<u64, Vec<SeriesId>>,
+    /// The series of every record
+    record_series: Vec<SeriesId>,
+    /// One bit per series
+    visible: Vec<u64>,
+}
+
+impl SeriesFilter {
+    /// Index the series of query results, with every series visible.
+    pub fn new(records: &[HashMap<String, String>]) -> Self {
+        let mut filter = Self {
+            keys: Vec::new(),
+            by_hash: HashMap::new(),
+            record_series: Vec::with_capacity(records.len()),
+            visible: Vec::new(),
+        };
+        for record in records {
+            let key = SeriesKey::of(record);
+            let id = match filter.lookup(&key) {
+                Some(id) => id,
+                None => {
+                    let id = SeriesId(filter.keys.len());
+                    filter.by_hash.entry(hash(&key)).or_default().push(id);
+                    filter.keys.push(key);
+                    id
+                }
+            };
+            filter.record_series.push(id);
+        }
+        filter.visible = vec![u64::MAX; filter.keys.len().div_ceil(64)];
+        filter
+    }
+
+    /// The number of distinct series.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether there are no series, because there were no records.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// All series and their keys, in the order they first appear in the records.
+    pub fn series(&self) -> impl Iterator<Item = (SeriesId, &SeriesKey)> {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (SeriesId(i), key))
+    }
+
+    /// The key of a series.
+    pub fn key(&self, id: SeriesId) -> &SeriesKey {
+        &self.keys[id.0]
+    }
+
+    /// The series of the record at an index of the indexed records.
+    pub fn series_of(&self, record_index: usize) -> Option<SeriesId> {
+        self.record_series.get(record_index).copied()
+    }
+
+    /// The series of a record, which doesn't need to be one of the indexed records.
+    pub fn series_of_record(&self, record: &HashMap<String, String>) -> Option<SeriesId> {
+        self.lookup(&SeriesKey::of(record))
+    }
+
+    /// The series with keys matching a predicate.
+    pub fn find<'a>(
+        &'a self,
+        mut predicate: impl FnMut(&SeriesKey) -> bool + 'a,
+    ) -> impl Iterator<Item = SeriesId> + 'a {
+        self.series()
+            .filter(move |(_, key)| predicate(key))
+            .map(|(id, _)| id)
+    }
+
+    pub fn is_visible(&self, id: SeriesId) -> bool {
+        self.visible[id.0 / 64] & (1 << (id.0 % 64)) != 0
+    }
+
+    pub fn set_visible(&mut self, id: SeriesId, visible: bool) {
+        if visible {
+            self.visible[id.0 / 64] |= 1 << (id.0 % 64);
+        } else {
+            self.visible[id.0 / 64] &= !(1 << (id.0 % 64));
+        }
+    }
+
+    /// Show or hide every series with a key matching a predicate.
+    pub fn set_visible_where(
+        &mut self,
+        mut predicate: impl FnMut(&SeriesKey) -> bool,
+        visible: bool,
+    ) {
+        let ids: Vec<_> = self.find(&mut predicate).collect();
+        for id in ids {
+            self.set_visible(id, visible);
+        }
+    }
+
+    /// Show or hide every series.
+    pub fn set_all_visible(&mut self, visible: bool) {
+        let bits = if visible { u64::MAX } else { 0 };
+        self.visible.iter_mut().for_each(|word| *word = bits);
+    }
+
+    /// The indexes of the records belonging to visible series.
+    pub fn visible_indexes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.record_series
+            .iter()
+            .enumerate()
+            .filter(move |(_, id)| self.is_visible(**id))
+            .map(|(i, _)| i)
+    }
+
+    /// The records belonging to visible series. `records` must be the records the filter was
+    /// built from.
+    pub fn filter<'a>(
+        &'a self,
+        records: &'a [HashMap<String, String>],
+    ) -> impl Iterator<Item = &'a HashMap<String, String>> + 'a {
+        debug_assert_eq!(records.len(), self.record_series.len());
+        self.visible_indexes().map(move |i| &records[i])
+    }
+
+    fn lookup(&self, key: &SeriesKey) -> Option<SeriesId> {
+        self.by_hash
+            .get(&hash(key))?
+            .iter()
+            .copied()
+            .find(|id| self.keys[id.0] == *key)
+    }
+}
+
+fn hash(key: &SeriesKey) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(host: &str, time: &str) -> HashMap<String, String> {
+        [
+            ("", ""),
+            ("result", "_result"),
+            ("table", "0"),
+            ("_time", time),
+            ("_value", "1"),
+            ("_measurement", "cpu"),
+            ("host", host),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn toggle_series() {
+        let records = vec![
+            record("a", "1"),
+            record("b", "1"),
+            record("a", "2"),
+            record("c", "1"),
+        ];
+        let mut filter = SeriesFilter::new(&records);
+        assert_eq!(filter.len(), 3);
+        assert_eq!(filter.series_of(2), filter.series_of(0));
+        assert_eq!(
+            filter
+                .key(filter.series_of(1).unwrap())
+                .columns()
+                .collect::<Vec<_>>(),
+            vec![("_measurement", "cpu"), ("host", "b")]
+        );
+        assert_eq!(filter.filter(&records).count(), 4);
+
+        let a = filter.series_of_record(&record("a", "3")).unwrap();
+        filter.set_visible(a, false);
+        assert_eq!(filter.visible_indexes().collect::<Vec<_>>(), vec![1, 3]);
+
+        filter.set_all_visible(false);
+        filter.set_visible_where(|key| key.get("host") != Some("b"), true);
+        assert_eq!(
+            filter
+                .filter(&records)
+                .map(|r| r["host"].as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "a", "c"]
+        );
+        assert_eq!(filter.series_of_record(&record("d", "1")), None);
+    }
+}
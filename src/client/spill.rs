@@ -0,0 +1,241 @@
+//! Spilling large query results to disk, see `InfluxClient::query_spilled`.
+
+use super::{dialect::QueryOptions, parse_query_records, query::Query, InfluxClient, InfluxError};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+type Record = HashMap<String, String>;
+
+/// Distinguishes the spill files of a process.
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl InfluxClient {
+    /// Run a query, keeping at most `max_rows_in_memory` records in memory and spilling the rest
+    /// to a temporary file, which is removed when the returned `SpilledRows` is dropped.
+    ///
+    /// The response body is still read into memory once, but parsed records (which take up
+    /// several times the space of their CSV) are written to disk as they are parsed. The query
+    /// cache is not used.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let rows = client
+    ///     .query_spilled(Query::new(r#"from(bucket: "b") |> range(start: -30d)"#), 100_000)
+    ///     .await?;
+    /// for row in rows {
+    ///     println!("{:?}", row?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_spilled(
+        &self,
+        query: Query,
+        max_rows_in_memory: usize,
+    ) -> Result<SpilledRows, InfluxError> {
+        let body = self.query_body(&query).await?;
+        SpilledRows::from_body(&body, &self.query_options, max_rows_in_memory)
+    }
+}
+
+/// The records of a query result, of which those beyond a limit are stored in a temporary file,
+/// see `InfluxClient::query_spilled`.
+///
+/// Iterating yields the records in the order they were returned by the server, reading spilled
+/// records back from disk as it goes.
+#[derive(Debug)]
+pub struct SpilledRows {
+    memory: std::vec::IntoIter<Record>,
+    spilled: Option<SpillFile>,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct SpillFile {
+    reader: csv::Reader<BufReader<File>>,
+    /// Declared after `reader`, so that the file is closed before it's removed, which Windows
+    /// requires.
+    _path: SpillPath,
+}
+
+/// The path of a spill file, which is removed when this is dropped, also when spilling fails.
+#[derive(Debug)]
+struct SpillPath(PathBuf);
+
+impl Drop for SpillPath {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            log::warn!("failed to remove spill file {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+impl SpilledRows {
+    fn from_body(
+        body: &str,
+        dialect: &QueryOptions,
+        max_rows_in_memory: usize,
+    ) -> Result<Self, InfluxError> {
+        let mut memory = Vec::new();
+        let mut writer: Option<(SpillPath, csv::Writer<BufWriter<File>>)> = None;
+        let mut len = 0;
+        parse_query_records(body, dialect, |record| {
+            len += 1;
+            if memory.len() < max_rows_in_memory {
+                memory.push(record);
+                return Ok(());
+            }
+            let (_, writer) = match &mut writer {
+                Some(writer) => writer,
+                None => writer.insert(create_spill_file()?),
+            };
+            // Records of different tables have different columns, so every row holds
+            // alternating column names and values.
+            writer.write_record(record.iter().flat_map(|(k, v)| [k, v]))?;
+            Ok(())
+        })?;
+
+        let spilled = match writer {
+            Some((path, writer)) => {
+                writer
+                    .into_inner()
+                    .map_err(|e| InfluxError::IoError(e.into_error()))?
+                    .into_inner()
+                    .map_err(|e| InfluxError::IoError(e.into_error()))?
+                    .sync_all()?;
+                let reader = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .flexible(true)
+                    .from_reader(BufReader::new(File::open(&path.0)?));
+                Some(SpillFile {
+                    reader,
+                    _path: path,
+                })
+            }
+            None => None,
+        };
+        Ok(Self {
+            memory: memory.into_iter(),
+            spilled,
+            len,
+        })
+    }
+
+    /// The total number of records, in memory and on disk.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether any records were spilled to disk.
+    pub fn is_spilled(&self) -> bool {
+        self.spilled.is_some()
+    }
+}
+
+fn create_spill_file() -> Result<(SpillPath, csv::Writer<BufWriter<File>>), InfluxError> {
+    let path = std::env::temp_dir().join(format!(
+        "influxrs-spill-{}-{}.csv",
+        std::process::id(),
+        SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut options = File::options();
+    options.write(true).create_new(true);
+    // The temporary directory is shared, so other users must not be able to read the results.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let file = options.open(&path)?;
+    let writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(BufWriter::new(file));
+    Ok((SpillPath(path), writer))
+}
+
+impl Iterator for SpilledRows {
+    type Item = Result<Record, InfluxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.memory.next() {
+            return Some(Ok(record));
+        }
+        let spilled = self.spilled.as_mut()?;
+        let mut row = csv::StringRecord::new();
+        match spilled.reader.read_record(&mut row) {
+            Ok(true) => {
+                let mut fields = row.iter();
+                let mut record = HashMap::with_capacity(row.len() / 2);
+                while let (Some(k), Some(v)) = (fields.next(), fields.next()) {
+                    record.insert(k.to_string(), v.to_string());
+                }
+                Some(Ok(record))
+            }
+            Ok(false) => {
+                self.spilled = None;
+                None
+            }
+            Err(e) => {
+                self.spilled = None;
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = ",result,table,_value,host
+,_result,0,1,a
+,_result,0,2,\"b,c\"
+
+,result,table,_value
+,_result,1,3
+,_result,1,4
+";
+
+    #[test]
+    fn spill_rows() {
+        let rows = SpilledRows::from_body(BODY, &QueryOptions::default(), 1).unwrap();
+        assert_eq!(rows.len(), 4);
+        assert!(rows.is_spilled());
+        let path = rows.spilled.as_ref().unwrap()._path.0.clone();
+        assert!(path.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let records: Vec<Record> = rows.collect::<Result<_, _>>().unwrap();
+        assert!(!path.exists(), "spill file is removed when exhausted");
+        let values: Vec<_> = records.iter().map(|r| r["_value"].as_str()).collect();
+        assert_eq!(values, vec!["1", "2", "3", "4"]);
+        assert_eq!(records[1]["host"], "b,c");
+        assert!(!records[2].contains_key("host"));
+
+        let rows = SpilledRows::from_body(BODY, &QueryOptions::default(), 10).unwrap();
+        assert!(!rows.is_spilled());
+        assert_eq!(rows.count(), 4);
+
+        let failing = format!("{}\n,error,reference\n,timeout,\n", BODY);
+        assert!(SpilledRows::from_body(&failing, &QueryOptions::default(), 1).is_err());
+        let prefix = format!("influxrs-spill-{}-", std::process::id());
+        let leftover = fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&prefix));
+        assert!(!leftover, "spill file is removed when spilling fails");
+    }
+}
@@ -0,0 +1,78 @@
+//! Support for SQL queries through the `/api/v3/query_sql` endpoint of InfluxDB 3.x.
+
+use super::{json, names::BucketName, InfluxClient, InfluxError, InfluxQueryResponse};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+impl InfluxClient {
+    /// Run a SQL query against the database (bucket) `bucket` of an InfluxDB 3.x server, which
+    /// doesn't support Flux.
+    ///
+    /// Records are returned like the records of `InfluxClient::query`, by column name, with
+    /// columns that are null in a row left out.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::InfluxClient;
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let records = client
+    ///     .query_sql("telegraf", "SELECT host, usage_idle FROM cpu WHERE time > now() - INTERVAL '1 hour'")
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_sql(
+        &self,
+        bucket: &str,
+        sql: &str,
+    ) -> Result<InfluxQueryResponse, InfluxError> {
+        BucketName::try_from(bucket)?;
+        let mut payload = BTreeMap::new();
+        payload.insert("db", bucket);
+        payload.insert("q", sql);
+        payload.insert("format", "csv");
+
+        let request = self
+            .request("POST", format!("{}/api/v3/query_sql", self.url))
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/csv")
+            .body(json::to_string(&payload)?)?;
+        let body = self.send(request).await?;
+        parse_sql_response(&body)
+    }
+}
+
+/// Parse the CSV body of a SQL query response, with a header row followed by the rows.
+fn parse_sql_response(body: &str) -> Result<InfluxQueryResponse, InfluxError> {
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let record = headers
+            .iter()
+            .zip(row?.iter())
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(column, value)| (column.to_string(), value.to_string()))
+            .collect();
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_csv_rows() {
+        let records = parse_sql_response(
+            "host,time,usage_idle\na,2021-06-01T00:00:00,99.5\n\"b, c\",2021-06-01T00:01:00,\n",
+        )
+        .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["usage_idle"], "99.5");
+        assert_eq!(records[1]["host"], "b, c");
+        assert!(!records[1].contains_key("usage_idle"));
+
+        assert!(parse_sql_response("").unwrap().is_empty());
+    }
+}
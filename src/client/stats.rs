@@ -0,0 +1,98 @@
+//! Counting of written and rejected points, see `InfluxClient::stats`.
+
+use crate::Measurement;
+use std::collections::BTreeMap;
+
+/// Point counts of everything written through a client, per measurement name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteStats {
+    measurements: BTreeMap<String, MeasurementStats>,
+}
+
+/// Point counts for a single measurement name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MeasurementStats {
+    /// Points accepted by the server
+    pub written: u64,
+    /// Points that were rejected, either by the server or before sending them
+    pub rejected: u64,
+}
+
+impl WriteStats {
+    pub(crate) fn record<'a>(
+        &mut self,
+        measurements: impl IntoIterator<Item = &'a Measurement>,
+        written: bool,
+    ) {
+        for measurement in measurements {
+            let stats = match self.measurements.get_mut(measurement.name()) {
+                Some(stats) => stats,
+                None => self
+                    .measurements
+                    .entry(measurement.name().to_string())
+                    .or_default(),
+            };
+            if written {
+                stats.written += 1;
+            } else {
+                stats.rejected += 1;
+            }
+        }
+    }
+
+    /// The counts of every measurement name written so far, sorted by name.
+    pub fn measurements(&self) -> impl Iterator<Item = (&str, &MeasurementStats)> {
+        self.measurements.iter().map(|(name, s)| (name.as_str(), s))
+    }
+
+    /// The counts of a single measurement name.
+    pub fn measurement(&self, name: &str) -> MeasurementStats {
+        self.measurements.get(name).copied().unwrap_or_default()
+    }
+
+    /// The total counts over all measurement names.
+    pub fn total(&self) -> MeasurementStats {
+        self.measurements
+            .values()
+            .fold(MeasurementStats::default(), |total, s| MeasurementStats {
+                written: total.written + s.written,
+                rejected: total.rejected + s.rejected,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_points() {
+        let m = |name: &str| Measurement::builder(name).field("f", 1).build().unwrap();
+        let mut stats = WriteStats::default();
+        stats.record(&[m("a"), m("b"), m("a")], true);
+        stats.record(&[m("b")], false);
+
+        assert_eq!(
+            stats.measurement("a"),
+            MeasurementStats {
+                written: 2,
+                rejected: 0
+            }
+        );
+        assert_eq!(
+            stats.measurement("b"),
+            MeasurementStats {
+                written: 1,
+                rejected: 1
+            }
+        );
+        assert_eq!(stats.measurement("c"), MeasurementStats::default());
+        assert_eq!(
+            stats.total(),
+            MeasurementStats {
+                written: 3,
+                rejected: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Flux function signatures through the `/api/v2/query/suggestions` API, for editor tooling,
+//! see `InfluxClient::flux_suggestions`.
+
+use super::{json, InfluxClient, InfluxError};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize)]
+struct Suggestions {
+    #[serde(default)]
+    funcs: Vec<FluxSuggestion>,
+}
+
+/// The signature of a Flux function, as returned by `InfluxClient::flux_suggestions`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FluxSuggestion {
+    pub name: String,
+    /// The type of each parameter, by parameter name
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+impl InfluxClient {
+    /// The signatures of all Flux functions the server knows about, such as for completions in
+    /// a Flux editor.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::InfluxClient;
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// for suggestion in client.flux_suggestions().await? {
+    ///     let params: Vec<_> = suggestion.params.keys().map(String::as_str).collect();
+    ///     println!("{}({})", suggestion.name, params.join(", "));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn flux_suggestions(&self) -> Result<Vec<FluxSuggestion>, InfluxError> {
+        let body = self
+            .api_request("GET", "/api/v2/query/suggestions", None)
+            .await?;
+        let suggestions: Suggestions = json::from_str(&body)?;
+        Ok(suggestions.funcs)
+    }
+
+    /// The signature of the Flux function `name`.
+    pub async fn flux_suggestion(&self, name: &str) -> Result<FluxSuggestion, InfluxError> {
+        let path = format!(
+            "/api/v2/query/suggestions/{}",
+            form_urlencoded::byte_serialize(name.as_bytes()).collect::<String>()
+        );
+        Ok(json::from_str(
+            &self.api_request("GET", &path, None).await?,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_suggestions() {
+        let suggestions: Suggestions = json::from_str(
+            r#"{"funcs": [
+                {"name": "range", "params": {"start": "invalid", "stop": "invalid"}},
+                {"name": "now"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(suggestions.funcs.len(), 2);
+        assert_eq!(
+            suggestions.funcs[0].params.keys().collect::<Vec<_>>(),
+            ["start", "stop"]
+        );
+        assert!(suggestions.funcs[1].params.is_empty());
+    }
+}
@@ -0,0 +1,266 @@
+//! Polling of a query for new records, see `InfluxClient::tail`.
+
+use super::InfluxQueryResponse;
+use crate::datetime;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, OnceLock, PoisonError, Weak},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+type Record = HashMap<String, String>;
+
+/// Keeps track of which records have already been yielded by a tail.
+pub(crate) struct TailState {
+    /// Start of the next time window to query, in nanoseconds since the Unix epoch.
+    start: i128,
+    /// Records at exactly `start` that have already been yielded.
+    seen_at_start: HashSet<Vec<(String, String)>>,
+    pub(crate) pending: VecDeque<Record>,
+}
+
+impl TailState {
+    pub(crate) fn new(start: i128) -> Self {
+        Self {
+            start,
+            seen_at_start: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The start of the next time window to query, as an RFC3339 timestamp.
+    pub(crate) fn start(&self) -> String {
+        datetime::format_rfc3339(self.start)
+    }
+
+    /// Queue the records of a response that haven't been yielded yet, and move the start of the
+    /// next window to the latest `_time` seen.
+    ///
+    /// Records without a parsable `_time` can't be deduplicated and are always queued.
+    pub(crate) fn push_response(&mut self, records: InfluxQueryResponse) {
+        let mut latest = self.start;
+        let mut seen_at_latest = HashSet::new();
+        for record in records {
+            let time = match record.get("_time").and_then(|t| datetime::parse_rfc3339(t)) {
+                Some(time) => time,
+                None => {
+                    self.pending.push_back(record);
+                    continue;
+                }
+            };
+            if time < self.start {
+                continue;
+            }
+            let key = record_key(&record);
+            if time == self.start && self.seen_at_start.contains(&key) {
+                continue;
+            }
+            if time > latest {
+                latest = time;
+                seen_at_latest.clear();
+            }
+            if time == latest {
+                seen_at_latest.insert(key);
+            }
+            self.pending.push_back(record);
+        }
+
+        if latest == self.start {
+            self.seen_at_start.extend(seen_at_latest);
+        } else {
+            self.start = latest;
+            self.seen_at_start = seen_at_latest;
+        }
+    }
+}
+
+/// The columns that identify a record across polls. Flux numbers tables anew in every response
+/// (so `table` changes when a new series shows up), and `_start` and `_stop` are the bounds of
+/// the window queried, so those are left out.
+fn record_key(record: &Record) -> Vec<(String, String)> {
+    const PER_RESPONSE: [&str; 4] = ["result", "table", "_start", "_stop"];
+    let mut key: Vec<_> = record
+        .iter()
+        .filter(|(k, _)| !PER_RESPONSE.contains(&k.as_str()))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    key.sort();
+    key
+}
+
+/// A future that completes after a duration, independent of any async runtime.
+///
+/// All delays are timed by a single timer thread, started by the first one, so polling doesn't
+/// cost a thread per tick. A dropped delay is forgotten by the timer once it would have
+/// completed.
+pub(crate) struct Delay {
+    state: Arc<Mutex<DelayState>>,
+}
+
+struct DelayState {
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// The pending delays of the timer thread, by deadline and a sequence number that tells apart
+/// delays with the same deadline.
+#[derive(Default)]
+struct Timers {
+    pending: BTreeMap<(Instant, u64), Weak<Mutex<DelayState>>>,
+    next_id: u64,
+}
+
+static TIMERS: OnceLock<(Mutex<Timers>, Condvar)> = OnceLock::new();
+
+impl Delay {
+    pub(crate) fn new(duration: Duration) -> Self {
+        let state = Arc::new(Mutex::new(DelayState {
+            done: false,
+            waker: None,
+        }));
+        let (timers, wakeup) = TIMERS.get_or_init(|| {
+            thread::spawn(run_timers);
+            Default::default()
+        });
+        let mut timers = timers.lock().unwrap_or_else(PoisonError::into_inner);
+        let id = timers.next_id;
+        timers.next_id += 1;
+        timers
+            .pending
+            .insert((Instant::now() + duration, id), Arc::downgrade(&state));
+        wakeup.notify_one();
+        Self { state }
+    }
+}
+
+/// Complete the pending delays as their deadlines pass, sleeping until the next one.
+fn run_timers() {
+    let (timers, wakeup) = TIMERS.get_or_init(Default::default);
+    let mut timers = timers.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        let next = timers.pending.keys().next().copied();
+        let now = Instant::now();
+        match next {
+            Some(key) if key.0 <= now => {
+                if let Some(state) = timers.pending.remove(&key).and_then(|s| s.upgrade()) {
+                    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                    state.done = true;
+                    if let Some(waker) = state.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+            Some((deadline, _)) => {
+                timers = wakeup
+                    .wait_timeout(timers, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+            }
+            None => timers = wakeup.wait(timers).unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.done {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(time: &str, value: &str) -> Record {
+        vec![("_time", time), ("_value", value)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn values(state: &mut TailState) -> Vec<String> {
+        state
+            .pending
+            .drain(..)
+            .map(|r| r["_value"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn tail_state_deduplicates_overlapping_windows() {
+        let mut state = TailState::new(0);
+        state.push_response(vec![
+            record("1970-01-01T00:00:01Z", "a"),
+            record("1970-01-01T00:00:02Z", "b"),
+        ]);
+        assert_eq!(values(&mut state), vec!["a", "b"]);
+        assert_eq!(state.start(), "1970-01-01T00:00:02Z");
+
+        state.push_response(vec![
+            record("1970-01-01T00:00:02Z", "b"),
+            record("1970-01-01T00:00:02Z", "c"),
+        ]);
+        assert_eq!(values(&mut state), vec!["c"]);
+        assert_eq!(state.start(), "1970-01-01T00:00:02Z");
+
+        state.push_response(vec![
+            record("1970-01-01T00:00:02Z", "b"),
+            record("1970-01-01T00:00:02Z", "c"),
+            record("1970-01-01T00:00:03Z", "d"),
+        ]);
+        assert_eq!(values(&mut state), vec!["d"]);
+        assert_eq!(state.start(), "1970-01-01T00:00:03Z");
+    }
+
+    #[test]
+    fn tail_state_ignores_renumbered_tables() {
+        let in_table = |table: &str, start: &str, host: &str, value: &str| {
+            let mut record = record("1970-01-01T00:00:02Z", value);
+            record.insert("table".to_string(), table.to_string());
+            record.insert("_start".to_string(), start.to_string());
+            record.insert("host".to_string(), host.to_string());
+            record
+        };
+        let mut state = TailState::new(0);
+        state.push_response(vec![in_table("0", "1970-01-01T00:00:00Z", "b", "1")]);
+        assert_eq!(values(&mut state), vec!["1"]);
+
+        // A new series sorts before the known one, which moves to table 1.
+        state.push_response(vec![
+            in_table("0", "1970-01-01T00:00:02Z", "a", "2"),
+            in_table("1", "1970-01-01T00:00:02Z", "b", "1"),
+        ]);
+        assert_eq!(values(&mut state), vec!["2"]);
+    }
+    #[test]
+    fn delays_complete_in_order() {
+        let started = Instant::now();
+        let (long, short) = futures_lite::future::block_on(futures_lite::future::zip(
+            async {
+                Delay::new(Duration::from_millis(50)).await;
+                Instant::now()
+            },
+            async {
+                Delay::new(Duration::from_millis(10)).await;
+                Instant::now()
+            },
+        ));
+        assert!(short < long);
+        assert!(long - started >= Duration::from_millis(50));
+        // A dropped delay doesn't keep the timer from completing later ones.
+        drop(Delay::new(Duration::from_millis(1)));
+        futures_lite::future::block_on(Delay::new(Duration::from_millis(5)));
+    }
+}
@@ -0,0 +1,244 @@
+//! Monitoring of task runs through the `/api/v2/tasks` API, see `InfluxClient::watch_task`.
+
+use super::{api_path, json, tail::Delay, InfluxClient, InfluxError};
+use crate::datetime;
+use futures_lite::{stream, Stream};
+use serde::Deserialize;
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Duration,
+};
+
+#[derive(Deserialize)]
+struct Runs {
+    #[serde(default)]
+    runs: Vec<TaskRun>,
+}
+
+/// A run of a task, as returned by the tasks API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRun {
+    pub id: String,
+    #[serde(rename = "taskID")]
+    pub task_id: String,
+    /// One of `scheduled`, `started`, `success`, `failed` and `canceled`
+    pub status: String,
+    /// RFC3339 time the run was scheduled for
+    pub scheduled_for: Option<String>,
+    /// RFC3339 time the run started
+    pub started_at: Option<String>,
+    /// RFC3339 time the run finished
+    pub finished_at: Option<String>,
+}
+
+impl TaskRun {
+    /// Whether the run has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "success" | "failed" | "canceled")
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.status == "failed"
+    }
+
+    /// How long after its scheduled time the run started.
+    pub fn lag(&self) -> Option<Duration> {
+        let scheduled = datetime::parse_rfc3339(self.scheduled_for.as_deref()?)?;
+        let started = datetime::parse_rfc3339(self.started_at.as_deref()?)?;
+        Some(Duration::from_nanos((started - scheduled).max(0) as u64))
+    }
+
+    fn scheduled_nanos(&self) -> Option<i128> {
+        datetime::parse_rfc3339(self.scheduled_for.as_deref()?)
+    }
+}
+
+/// A problem with a task run, passed to the hook of `InfluxClient::watch_task`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskAlert<'a> {
+    /// The run failed.
+    Failed(&'a TaskRun),
+    /// The run started the contained duration after its scheduled time, which is more than the
+    /// allowed lag.
+    Lagging(&'a TaskRun, Duration),
+}
+
+/// Keeps track of which runs have already been yielded by `watch_task`.
+struct WatchState {
+    /// Only runs scheduled after this time (in nanoseconds since the Unix epoch) are requested.
+    after: i128,
+    seen: HashSet<String>,
+    pending: VecDeque<TaskRun>,
+}
+
+impl WatchState {
+    fn new(after: i128) -> Self {
+        Self {
+            after,
+            seen: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Queue the finished runs that haven't been yielded yet, in order of their scheduled time,
+    /// and move the start of the next request past runs that won't change anymore.
+    fn push_runs(&mut self, mut runs: Vec<TaskRun>) {
+        runs.sort_by_key(|run| run.scheduled_nanos());
+        let earliest_unfinished = runs
+            .iter()
+            .filter(|run| !run.is_finished())
+            .filter_map(TaskRun::scheduled_nanos)
+            .min();
+        let latest = runs.iter().filter_map(TaskRun::scheduled_nanos).max();
+
+        for run in runs {
+            if run.is_finished() && self.seen.insert(run.id.clone()) {
+                self.pending.push_back(run);
+            }
+        }
+
+        let after = match (earliest_unfinished, latest) {
+            // The request is exclusive, so stay just before the earliest unfinished run.
+            (Some(unfinished), _) => unfinished - 1,
+            (None, Some(latest)) => latest,
+            (None, None) => self.after,
+        };
+        if after > self.after {
+            self.after = after;
+        }
+    }
+}
+
+impl InfluxClient {
+    /// Poll the runs of a task every `interval`, yielding every run that finishes from now on.
+    ///
+    /// `on_alert` is called for every yielded run that failed or started more than `max_lag`
+    /// after its scheduled time, which makes it easy to hook supervision of tasks (such as
+    /// downsampling jobs) into logging or alerting.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, TaskAlert};
+    /// # use futures_lite::StreamExt;
+    /// # use std::time::Duration;
+    /// # async fn example(client: InfluxClient) {
+    /// let runs = client.watch_task(
+    ///     "0a1b2c3d4e5f6789",
+    ///     Duration::from_secs(60),
+    ///     Duration::from_secs(30),
+    ///     |alert| match alert {
+    ///         TaskAlert::Failed(run) => eprintln!("run {} failed", run.id),
+    ///         TaskAlert::Lagging(run, lag) => eprintln!("run {} lagged {:?}", run.id, lag),
+    ///     },
+    /// );
+    /// futures_lite::pin!(runs);
+    /// while let Some(run) = runs.next().await {
+    ///     println!("{:?}", run);
+    /// }
+    /// # }
+    /// ```
+    pub fn watch_task<'a, F>(
+        &'a self,
+        task_id: &'a str,
+        interval: Duration,
+        max_lag: Duration,
+        on_alert: F,
+    ) -> impl Stream<Item = Result<TaskRun, InfluxError>> + 'a
+    where
+        F: FnMut(TaskAlert<'_>) + 'a,
+    {
+        let state = (WatchState::new(datetime::now_nanos()), on_alert, true);
+        stream::unfold(state, move |(mut state, mut on_alert, first)| async move {
+            if !first && state.pending.is_empty() {
+                Delay::new(interval).await;
+            }
+            loop {
+                if let Some(run) = state.pending.pop_front() {
+                    if run.is_failed() {
+                        on_alert(TaskAlert::Failed(&run));
+                    }
+                    if let Some(lag) = run.lag().filter(|lag| *lag > max_lag) {
+                        on_alert(TaskAlert::Lagging(&run, lag));
+                    }
+                    return Some((Ok(run), (state, on_alert, false)));
+                }
+                match self.task_runs(task_id, state.after).await {
+                    Ok(runs) => state.push_runs(runs),
+                    Err(e) => return Some((Err(e), (state, on_alert, false))),
+                }
+                if state.pending.is_empty() {
+                    Delay::new(interval).await;
+                }
+            }
+        })
+    }
+
+    async fn task_runs(&self, task_id: &str, after: i128) -> Result<Vec<TaskRun>, InfluxError> {
+        let path = api_path(
+            &format!(
+                "/api/v2/tasks/{}/runs",
+                form_urlencoded::byte_serialize(task_id.as_bytes()).collect::<String>()
+            ),
+            &[
+                ("afterTime", &datetime::format_rfc3339(after)),
+                ("limit", "500"),
+            ],
+        );
+        let runs: Runs = json::from_str(&self.api_request("GET", &path, None).await?)?;
+        Ok(runs.runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(id: &str, status: &str, scheduled_for: &str) -> TaskRun {
+        TaskRun {
+            id: id.to_string(),
+            task_id: "task".to_string(),
+            status: status.to_string(),
+            scheduled_for: Some(scheduled_for.to_string()),
+            started_at: Some("2021-06-01T00:00:05Z".to_string()),
+            finished_at: None,
+        }
+    }
+
+    #[test]
+    fn watch_state() {
+        let runs: Runs = json::from_str(
+            r#"{"runs": [{"id": "b", "taskID": "task", "status": "failed",
+                "scheduledFor": "2021-06-01T00:00:00Z", "startedAt": "2021-06-01T00:00:05Z",
+                "finishedAt": "2021-06-01T00:00:06Z", "log": []}]}"#,
+        )
+        .unwrap();
+        assert_eq!(runs.runs[0].lag(), Some(Duration::from_secs(5)));
+        assert!(runs.runs[0].is_failed());
+
+        let mut state = WatchState::new(0);
+        state.push_runs(vec![
+            run("c", "started", "2021-06-01T00:02:00Z"),
+            run("b", "success", "2021-06-01T00:01:00Z"),
+            run("a", "failed", "2021-06-01T00:00:00Z"),
+        ]);
+        let ids = |state: &mut WatchState| {
+            state
+                .pending
+                .drain(..)
+                .map(|run| run.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&mut state), vec!["a", "b"]);
+        assert_eq!(
+            datetime::format_rfc3339(state.after + 1),
+            "2021-06-01T00:02:00Z"
+        );
+
+        state.push_runs(vec![
+            run("c", "success", "2021-06-01T00:02:00Z"),
+            run("d", "scheduled", "2021-06-01T00:03:00Z"),
+        ]);
+        assert_eq!(ids(&mut state), vec!["c"]);
+    }
+}
@@ -0,0 +1,843 @@
+//! Query results with typed values, parsed from annotated CSV, see `InfluxClient::query_typed`.
+
+use super::{
+    csv_rows,
+    dialect::{Annotation, QueryOptions},
+    is_error_table, json, parse_csv_row,
+    query::Query,
+    query_request_body, InfluxClient, InfluxError,
+};
+use crate::datetime;
+use futures_lite::{io::BufReader, stream, AsyncBufReadExt, Stream};
+use isahc::AsyncBody;
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt::Write as _,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A typed value of a query result, see `FluxRecord`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Double(f64),
+    Long(i64),
+    UnsignedLong(u64),
+    Bool(bool),
+    /// A time in nanoseconds since the Unix epoch
+    Time(i128),
+    /// A duration in nanoseconds. Durations with calendar units such as `1mo` have no fixed
+    /// length, and are `String`s instead
+    Duration(i128),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value as a float, if it's a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Double(v) => Some(*v),
+            Value::Long(v) => Some(*v as f64),
+            Value::UnsignedLong(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The value in nanoseconds since the Unix epoch, if it's a time.
+    pub fn as_time(&self) -> Option<i128> {
+        match self {
+            Value::Time(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The value as a `SystemTime`, if it's a time.
+    pub fn as_system_time(&self) -> Option<SystemTime> {
+        let nanos = self.as_time()?;
+        let since_epoch = Duration::from_nanos(u64::try_from(nanos.unsigned_abs()).ok()?);
+        if nanos < 0 {
+            UNIX_EPOCH.checked_sub(since_epoch)
+        } else {
+            UNIX_EPOCH.checked_add(since_epoch)
+        }
+    }
+}
+
+/// A record of a query result with typed values, see `InfluxClient::query_typed`.
+///
+/// Null values are left out, so they read the same as missing columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluxRecord {
+    result: String,
+    table: u64,
+    group_key: Vec<String>,
+    values: HashMap<String, Value>,
+}
+
+impl FluxRecord {
+    /// The name of the result the record belongs to, `_result` unless named with `yield`.
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    /// The index of the table the record belongs to.
+    pub fn table(&self) -> u64 {
+        self.table
+    }
+
+    /// The columns of the group key of the table, in the order of the response.
+    pub fn group_key(&self) -> &[String] {
+        &self.group_key
+    }
+
+    /// The value of `column`, other than `result` and `table`.
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.values.get(column)
+    }
+
+    /// The values of all columns, other than `result` and `table`.
+    pub fn values(&self) -> &HashMap<String, Value> {
+        &self.values
+    }
+
+    /// The `_time` column, in nanoseconds since the Unix epoch.
+    pub fn time(&self) -> Option<i128> {
+        self.values.get("_time")?.as_time()
+    }
+
+    /// The `_time` column as a `SystemTime`.
+    pub fn system_time(&self) -> Option<SystemTime> {
+        self.values.get("_time")?.as_system_time()
+    }
+
+    /// The `_value` column.
+    pub fn value(&self) -> Option<&Value> {
+        self.values.get("_value")
+    }
+
+    /// The `_measurement` column.
+    pub fn measurement(&self) -> Option<&str> {
+        self.values.get("_measurement")?.as_str()
+    }
+
+    /// The `_field` column.
+    pub fn field(&self) -> Option<&str> {
+        self.values.get("_field")?.as_str()
+    }
+
+    /// The record as a JSON object of its columns, including `result` and `table`, in
+    /// alphabetical order. Times are RFC3339 strings, durations Flux duration literals and
+    /// non-finite numbers `null`.
+    pub fn to_json(&self) -> String {
+        let mut object = String::from("{");
+        json::write_string(&mut object, "result");
+        object.push(':');
+        json::write_string(&mut object, &self.result);
+        let _ = write!(object, ",\"table\":{}", self.table);
+        let values: BTreeMap<_, _> = self.values.iter().collect();
+        for (column, value) in values {
+            object.push(',');
+            json::write_string(&mut object, column);
+            object.push(':');
+            match value {
+                Value::String(v) => json::write_string(&mut object, v),
+                Value::Double(v) if v.is_finite() => {
+                    let _ = write!(object, "{}", v);
+                }
+                Value::Double(_) => object.push_str("null"),
+                Value::Long(v) => {
+                    let _ = write!(object, "{}", v);
+                }
+                Value::UnsignedLong(v) => {
+                    let _ = write!(object, "{}", v);
+                }
+                Value::Bool(v) => {
+                    let _ = write!(object, "{}", v);
+                }
+                Value::Time(v) => json::write_string(&mut object, &datetime::format_rfc3339(*v)),
+                Value::Duration(v) => {
+                    json::write_string(&mut object, &datetime::format_duration(*v))
+                }
+            }
+        }
+        object.push('}');
+        object
+    }
+}
+
+/// A column of a `FluxTable`, as described by the annotations of the response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FluxColumn {
+    pub name: String,
+    /// The annotated type of the column, such as `double` or `dateTime:RFC3339`
+    pub datatype: String,
+    /// Whether the column is part of the group key of the table
+    pub group: bool,
+    /// The value of empty cells of the column
+    pub default: Option<String>,
+}
+
+/// A table of a query result, see `InfluxClient::query_tables`.
+///
+/// Each table is a series: its records share the values of the columns of the group key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluxTable {
+    result: String,
+    table: u64,
+    columns: Vec<FluxColumn>,
+    records: Vec<FluxRecord>,
+}
+
+impl FluxTable {
+    /// The name of the result the table belongs to, `_result` unless named with `yield`.
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    /// The index of the table.
+    pub fn table(&self) -> u64 {
+        self.table
+    }
+
+    /// The columns of the table, other than `result` and `table`.
+    pub fn columns(&self) -> &[FluxColumn] {
+        &self.columns
+    }
+
+    /// The names of the columns of the group key of the table.
+    pub fn group_key(&self) -> Vec<&str> {
+        self.columns
+            .iter()
+            .filter(|column| column.group)
+            .map(|column| column.name.as_str())
+            .collect()
+    }
+
+    pub fn records(&self) -> &[FluxRecord] {
+        &self.records
+    }
+
+    /// The values of the column `name` in the order of the records, with `None` for null
+    /// values, for building columnar data such as Arrow arrays. Returns `None` if the table has
+    /// no such column.
+    pub fn column_values(&self, name: &str) -> Option<Vec<Option<&Value>>> {
+        self.columns.iter().find(|column| column.name == name)?;
+        Some(
+            self.records
+                .iter()
+                .map(|record| record.values.get(name))
+                .collect(),
+        )
+    }
+
+    pub fn into_records(self) -> Vec<FluxRecord> {
+        self.records
+    }
+}
+
+impl InfluxClient {
+    /// Like `query`, but with the values of the records parsed into their types, as described
+    /// by the annotations of the response, which are always requested.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_field("usage");
+    /// for record in client.query_typed(query).await? {
+    ///     println!("{:?}: {:?}", record.time(), record.value().and_then(|v| v.as_f64()));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_typed(&self, query: Query) -> Result<Vec<FluxRecord>, InfluxError> {
+        let options = typed_options(&self.query_options);
+        let payload = query_request_body(&query, &HashMap::new(), &HashMap::new(), &options)?;
+        let body = self.query_request(payload, "application/json").await?;
+        let mut records = parse_annotated_response(&body, &options)?;
+        records.retain(|record| record.result != "_profiler");
+        Ok(records)
+    }
+
+    /// Like `query_typed`, but with the records as a JSON array of objects, see
+    /// `FluxRecord::to_json`. The server only responds in CSV, so the records are converted from
+    /// it.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -1h)");
+    /// let json = client.query_json(query).await?;
+    /// assert!(json.starts_with('['));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_json(&self, query: Query) -> Result<String, InfluxError> {
+        let records = self.query_typed(query).await?;
+        Ok(records_json(&records))
+    }
+
+    /// Like `query_typed`, but with the records in the tables of the response, to tell series
+    /// apart by their group keys.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: -1h)")
+    ///     .filter_field("usage");
+    /// for table in client.query_tables(query).await? {
+    ///     let host = table.records()[0].get("host");
+    ///     println!("{:?}: {} points", host, table.records().len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_tables(&self, query: Query) -> Result<Vec<FluxTable>, InfluxError> {
+        let options = typed_options(&self.query_options);
+        let payload = query_request_body(&query, &HashMap::new(), &HashMap::new(), &options)?;
+        let body = self.query_request(payload, "application/json").await?;
+        let mut tables = parse_annotated_tables(&body, &options)?;
+        tables.retain(|table| table.result != "_profiler");
+        Ok(tables)
+    }
+
+    /// Like `query_typed`, but the records are parsed as the response arrives, instead of after
+    /// reading the whole response, so large results don't have to fit in memory.
+    ///
+    /// The stream ends after the first error.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query};
+    /// # use futures_lite::StreamExt;
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#).then("range(start: -30d)");
+    /// let mut records = Box::pin(client.query_stream(query));
+    /// while let Some(record) = records.next().await {
+    ///     println!("{:?}", record?.value());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query_stream(
+        &self,
+        query: Query,
+    ) -> impl Stream<Item = Result<FluxRecord, InfluxError>> + '_ {
+        stream::unfold(StreamState::Start(query), move |state| async move {
+            let (mut body, mut parser) = match state {
+                StreamState::Start(query) => match self.query_stream_response(&query).await {
+                    Ok(started) => started,
+                    Err(e) => return Some((Err(e), StreamState::Done)),
+                },
+                StreamState::Reading(body, parser) => (body, parser),
+                StreamState::Done => return None,
+            };
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match body.read_until(b'\n', &mut line).await {
+                    Ok(0) => {
+                        return match parser.finish() {
+                            Ok(()) => None,
+                            Err(e) => Some((Err(e), StreamState::Done)),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Some((Err(e.into()), StreamState::Done)),
+                }
+                match parser.push_line(&String::from_utf8_lossy(&line)) {
+                    Ok(Some(record)) if record.result != "_profiler" => {
+                        return Some((Ok(record), StreamState::Reading(body, parser)))
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Some((Err(e), StreamState::Done)),
+                }
+            }
+        })
+    }
+
+    async fn query_stream_response(
+        &self,
+        query: &Query,
+    ) -> Result<(BufReader<AsyncBody>, AnnotatedParser), InfluxError> {
+        let options = typed_options(&self.query_options);
+        let payload = query_request_body(query, &HashMap::new(), &HashMap::new(), &options)?;
+        let request = self.query_http_request(payload, "application/json", "application/csv")?;
+        let response = self.send_streaming(request).await?;
+        Ok((
+            BufReader::new(response.into_body()),
+            AnnotatedParser::new(&options),
+        ))
+    }
+}
+
+fn records_json(records: &[FluxRecord]) -> String {
+    let objects: Vec<String> = records.iter().map(FluxRecord::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+/// `options` with the annotations needed to parse typed records.
+fn typed_options(options: &QueryOptions) -> QueryOptions {
+    options
+        .clone()
+        .annotations(&[Annotation::Datatype, Annotation::Group, Annotation::Default])
+}
+
+enum StreamState {
+    Start(Query),
+    Reading(BufReader<AsyncBody>, AnnotatedParser),
+    Done,
+}
+
+/// The annotations of a table, by column.
+#[derive(Default)]
+struct Annotations {
+    datatypes: Vec<String>,
+    groups: Vec<bool>,
+    defaults: Vec<String>,
+}
+
+/// Parse a query response in annotated CSV into typed records.
+fn parse_annotated_response(
+    body: &str,
+    dialect: &QueryOptions,
+) -> Result<Vec<FluxRecord>, InfluxError> {
+    let mut parser = AnnotatedParser::new(dialect);
+    let mut records = Vec::new();
+    for row in csv_rows(body) {
+        records.extend(parser.push_row(row)?);
+    }
+    parser.finish()?;
+    Ok(records)
+}
+
+/// Parse a query response in annotated CSV into tables of typed records.
+fn parse_annotated_tables(
+    body: &str,
+    dialect: &QueryOptions,
+) -> Result<Vec<FluxTable>, InfluxError> {
+    let mut parser = AnnotatedParser::new(dialect);
+    let mut tables: Vec<FluxTable> = Vec::new();
+    let mut last_block = 0;
+    for row in csv_rows(body) {
+        let record = match parser.push_row(row)? {
+            Some(record) => record,
+            None => continue,
+        };
+        match tables.last_mut() {
+            Some(table)
+                if parser.blocks == last_block
+                    && table.result == record.result
+                    && table.table == record.table =>
+            {
+                table.records.push(record)
+            }
+            _ => {
+                last_block = parser.blocks;
+                tables.push(FluxTable {
+                    result: record.result.clone(),
+                    table: record.table,
+                    columns: parser.columns(),
+                    records: vec![record],
+                });
+            }
+        }
+    }
+    parser.finish()?;
+    Ok(tables)
+}
+
+/// Parses annotated CSV into typed records one line at a time.
+struct AnnotatedParser {
+    delimiter: u8,
+    comment_prefix: String,
+    annotations: Annotations,
+    headers: Option<Vec<String>>,
+    error_headers: Option<csv::StringRecord>,
+    /// The number of header rows so far
+    blocks: usize,
+    /// The start of a row with a quoted value that spans several lines
+    pending: String,
+}
+
+impl AnnotatedParser {
+    fn new(dialect: &QueryOptions) -> Self {
+        Self {
+            delimiter: dialect.delimiter_byte(),
+            comment_prefix: dialect.comment_prefix_str().to_string(),
+            annotations: Annotations::default(),
+            headers: None,
+            error_headers: None,
+            blocks: 0,
+            pending: String::new(),
+        }
+    }
+
+    /// Parse the next line of the response, including its line break, returning the record it
+    /// completes, if any.
+    fn push_line(&mut self, line: &str) -> Result<Option<FluxRecord>, InfluxError> {
+        if self.pending.is_empty() {
+            self.pending.push_str(line.trim_start_matches('\u{feff}'));
+        } else {
+            self.pending.push_str(line);
+        }
+        if self.pending.matches('"').count() % 2 == 1 {
+            // The line break is part of a quoted value.
+            return Ok(None);
+        }
+        let row = std::mem::take(&mut self.pending);
+        let row = row.strip_suffix('\n').unwrap_or(&row);
+        self.push_row(row.strip_suffix('\r').unwrap_or(row))
+    }
+
+    /// Parse the next row of the response, as split by `csv_rows`.
+    fn push_row(&mut self, row: &str) -> Result<Option<FluxRecord>, InfluxError> {
+        if row.is_empty() {
+            // Tables are separated by empty rows.
+            self.annotations = Annotations::default();
+            self.headers = None;
+            return Ok(None);
+        }
+        let row = parse_csv_row(row, self.delimiter)?;
+
+        if let Some(error_headers) = self.error_headers.take() {
+            return Err(query_error(&error_headers, &row));
+        }
+        let first = row.get(0).unwrap_or_default();
+        let cells = || row.iter().skip(1).map(str::to_string);
+        let prefix = self.comment_prefix.as_str();
+        if !prefix.is_empty() && first.starts_with(prefix) {
+            if self.headers.take().is_some() {
+                self.annotations = Annotations::default();
+            }
+            match &first[prefix.len()..] {
+                "datatype" => self.annotations.datatypes = cells().collect(),
+                "group" => self.annotations.groups = cells().map(|g| g == "true").collect(),
+                "default" => self.annotations.defaults = cells().collect(),
+                _ => {}
+            }
+            return Ok(None);
+        }
+        match &self.headers {
+            Some(headers) => Ok(Some(parse_record(headers, &self.annotations, &row)?)),
+            None if is_error_table(&row) => {
+                self.error_headers = Some(row);
+                Ok(None)
+            }
+            None => {
+                self.headers = Some(cells().collect());
+                self.blocks += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    /// The columns of the current table, other than `result` and `table`.
+    fn columns(&self) -> Vec<FluxColumn> {
+        let headers = self.headers.as_deref().unwrap_or_default();
+        headers
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| *name != "result" && *name != "table")
+            .map(|(i, name)| FluxColumn {
+                name: name.clone(),
+                datatype: self
+                    .annotations
+                    .datatypes
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| "string".to_string()),
+                group: self.annotations.groups.get(i) == Some(&true),
+                default: self
+                    .annotations
+                    .defaults
+                    .get(i)
+                    .filter(|d| !d.is_empty())
+                    .cloned(),
+            })
+            .collect()
+    }
+
+    /// Check the end of the response for an error table without a row.
+    fn finish(&mut self) -> Result<(), InfluxError> {
+        match self.error_headers.take() {
+            Some(error_headers) => Err(query_error(&error_headers, &csv::StringRecord::new())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Errors that occur after the response status was sent are reported as a table with `error`
+/// and `reference` columns.
+fn query_error(headers: &csv::StringRecord, row: &csv::StringRecord) -> InfluxError {
+    let column = |name| {
+        let index = headers.iter().position(|h| h == name)?;
+        row.get(index).map(str::to_string)
+    };
+    let message = column("error").unwrap_or_default();
+    let reference = column("reference").filter(|r| !r.is_empty());
+    InfluxError::QueryError(message, reference)
+}
+
+fn parse_record(
+    headers: &[String],
+    annotations: &Annotations,
+    row: &csv::StringRecord,
+) -> Result<FluxRecord, InfluxError> {
+    let mut record = FluxRecord {
+        result: "_result".to_string(),
+        table: 0,
+        group_key: Vec::new(),
+        values: HashMap::new(),
+    };
+    for (i, column) in headers.iter().enumerate() {
+        if annotations.groups.get(i) == Some(&true) {
+            record.group_key.push(column.clone());
+        }
+        let mut raw = row.get(i + 1).unwrap_or_default();
+        if raw.is_empty() {
+            raw = annotations.defaults.get(i).map_or("", String::as_str);
+        }
+        if raw.is_empty() {
+            continue;
+        }
+        match column.as_str() {
+            "result" => record.result = raw.to_string(),
+            "table" => record.table = raw.parse().unwrap_or_default(),
+            _ => {
+                let datatype = annotations
+                    .datatypes
+                    .get(i)
+                    .map_or("string", String::as_str);
+                let value = parse_value(datatype, raw).ok_or_else(|| {
+                    InfluxError::InvalidResponse(format!(
+                        "invalid {} value '{}' in column '{}'",
+                        datatype, raw, column
+                    ))
+                })?;
+                record.values.insert(column.clone(), value);
+            }
+        }
+    }
+    Ok(record)
+}
+
+/// Parse `raw` as a value of the annotated type `datatype`. Durations that aren't a fixed number
+/// of nanoseconds, such as `1mo`, are kept as strings.
+fn parse_value(datatype: &str, raw: &str) -> Option<Value> {
+    Some(match datatype {
+        "double" => Value::Double(match raw {
+            "+Inf" => f64::INFINITY,
+            "-Inf" => f64::NEG_INFINITY,
+            _ => raw.parse().ok()?,
+        }),
+        "long" => Value::Long(raw.parse().ok()?),
+        "unsignedLong" => Value::UnsignedLong(raw.parse().ok()?),
+        "boolean" => Value::Bool(raw.parse().ok()?),
+        "dateTime:RFC3339" | "dateTime:RFC3339Nano" => Value::Time(datetime::parse_rfc3339(raw)?),
+        "duration" => match raw.parse().ok().or_else(|| datetime::parse_duration(raw)) {
+            Some(nanos) => Value::Duration(nanos),
+            None => Value::String(raw.to_string()),
+        },
+        _ => Value::String(raw.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_typed_records() {
+        let body = "#datatype,string,long,dateTime:RFC3339,double,string,boolean\r\n\
+            #group,false,false,false,false,true,false\r\n\
+            #default,_result,,,,,\r\n\
+            ,result,table,_time,_value,host,up\r\n\
+            ,,0,2021-06-01T11:16:05Z,1.5,a,true\r\n\
+            ,,0,2021-06-01T11:16:06Z,,a,\r\n\
+            \r\n\
+            #datatype,string,long,unsignedLong,duration\r\n\
+            #group,false,false,false,false\r\n\
+            #default,max,,,\r\n\
+            ,result,table,_value,lag\r\n\
+            ,,1,18446744073709551615,1h30m\r\n";
+        let records = parse_annotated_response(body, &QueryOptions::new()).unwrap();
+        assert_eq!(records.len(), 3);
+
+        assert_eq!(records[0].result(), "_result");
+        assert_eq!(records[0].group_key(), ["host"]);
+        assert_eq!(records[0].time(), Some(1_622_546_165_000_000_000));
+        assert_eq!(records[0].value(), Some(&Value::Double(1.5)));
+        assert_eq!(records[0].get("up"), Some(&Value::Bool(true)));
+        assert_eq!(records[1].value(), None);
+        assert_eq!(records[1].get("host").and_then(Value::as_str), Some("a"));
+
+        assert_eq!(records[2].result(), "max");
+        assert_eq!(records[2].table(), 1);
+        assert_eq!(records[2].value(), Some(&Value::UnsignedLong(u64::MAX)));
+        assert_eq!(
+            records[2].get("lag"),
+            Some(&Value::Duration(5_400_000_000_000))
+        );
+
+        let error = "#datatype,string,string\r\n#group,true,true\r\n#default,,\r\n\
+            ,error,reference\r\n,failed to parse,897\r\n";
+        assert!(matches!(
+            parse_annotated_response(error, &QueryOptions::new()),
+            Err(InfluxError::QueryError(message, Some(reference)))
+                if message == "failed to parse" && reference == "897"
+        ));
+
+        let invalid = "#datatype,string,long,double\r\n,result,table,_value\r\n,,0,x\r\n";
+        assert!(matches!(
+            parse_annotated_response(invalid, &QueryOptions::new()),
+            Err(InfluxError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn records_as_json() {
+        let body =
+            "#datatype,string,long,dateTime:RFC3339,double,string,long,duration,boolean,double\r\n\
+            ,result,table,_time,_value,host,count,lag,up,ratio\r\n\
+            ,_result,3,2021-06-01T11:16:05Z,1.5,\"a \"\"b\"\"\",-2,1h30m,true,NaN\r\n\
+            ,_result,3,2021-06-01T11:16:06Z,,,,,,\r\n";
+        let records = parse_annotated_response(body, &QueryOptions::new()).unwrap();
+        assert_eq!(
+            records_json(&records),
+            r#"[{"result":"_result","table":3,"_time":"2021-06-01T11:16:05Z","_value":1.5,"count":-2,"host":"a \"b\"","lag":"1h30m","ratio":null,"up":true},{"result":"_result","table":3,"_time":"2021-06-01T11:16:06Z"}]"#
+        );
+        assert_eq!(records_json(&[]), "[]");
+    }
+
+    #[test]
+    fn parse_times() {
+        let nano = parse_value("dateTime:RFC3339Nano", "2021-06-01T11:16:05.684000001Z").unwrap();
+        assert_eq!(nano.as_time(), Some(1_622_546_165_684_000_001));
+        assert_eq!(
+            nano.as_system_time(),
+            Some(UNIX_EPOCH + Duration::from_nanos(1_622_546_165_684_000_001))
+        );
+        let before_epoch = parse_value("dateTime:RFC3339", "1969-12-31T23:59:59Z").unwrap();
+        assert_eq!(
+            before_epoch.as_system_time(),
+            Some(UNIX_EPOCH - Duration::from_secs(1))
+        );
+        assert_eq!(parse_value("dateTime:RFC3339", "yesterday"), None);
+        assert_eq!(
+            parse_value("duration", "-1h30m"),
+            Some(Value::Duration(-5_400_000_000_000))
+        );
+        assert_eq!(
+            parse_value("duration", "1y2mo"),
+            Some(Value::String("1y2mo".to_string()))
+        );
+        assert_eq!(Value::Long(1).as_system_time(), None);
+    }
+
+    #[test]
+    fn parse_tables() {
+        let body = "#datatype,string,long,dateTime:RFC3339,double,string\r\n\
+            #group,false,false,false,false,true\r\n\
+            #default,_result,,,,\r\n\
+            ,result,table,_time,_value,host\r\n\
+            ,,0,2021-06-01T11:16:05Z,1.5,a\r\n\
+            ,,0,2021-06-01T11:16:06Z,2.5,a\r\n\
+            ,,1,2021-06-01T11:16:05Z,3.5,b\r\n\
+            \r\n\
+            #datatype,string,long,dateTime:RFC3339,long,string\r\n\
+            #group,false,false,false,false,true\r\n\
+            #default,_result,,,,\r\n\
+            ,result,table,_time,_value,host\r\n\
+            ,,2,2021-06-01T11:16:05Z,4,c\r\n";
+        let tables = parse_annotated_tables(body, &QueryOptions::new()).unwrap();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0].records().len(), 2);
+        assert_eq!(tables[0].group_key(), ["host"]);
+        assert_eq!(
+            tables[0].columns()[1],
+            FluxColumn {
+                name: "_value".to_string(),
+                datatype: "double".to_string(),
+                group: false,
+                default: None,
+            }
+        );
+        assert_eq!(tables[1].table(), 1);
+        assert_eq!(
+            tables[1].records()[0].get("host").and_then(Value::as_str),
+            Some("b")
+        );
+        assert_eq!(
+            tables[0].column_values("_value"),
+            Some(vec![Some(&Value::Double(1.5)), Some(&Value::Double(2.5))])
+        );
+        assert_eq!(tables[0].column_values("_field"), None);
+        assert_eq!(tables[2].columns()[1].datatype, "long");
+        assert_eq!(tables[2].records()[0].value(), Some(&Value::Long(4)));
+    }
+
+    #[test]
+    fn parse_line_by_line() {
+        let mut parser = AnnotatedParser::new(&QueryOptions::new());
+        let lines = [
+            "\u{feff}#datatype,string,long,string\r\n",
+            ",result,table,note\r\n",
+            ",,0,\"first\r\n",
+            "second\"\r\n",
+            ",,0,plain\r\n",
+            "\r\n",
+            "#datatype,string,long,long\r\n",
+            ",result,table,_value\r\n",
+            ",,1,7\r\n",
+        ];
+        let records: Vec<FluxRecord> = lines
+            .iter()
+            .filter_map(|line| parser.push_line(line).unwrap())
+            .collect();
+        parser.finish().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0].get("note").and_then(Value::as_str),
+            Some("first\r\nsecond")
+        );
+        assert_eq!(
+            records[1].get("note").and_then(Value::as_str),
+            Some("plain")
+        );
+        assert_eq!(records[2].value(), Some(&Value::Long(7)));
+
+        let mut parser = AnnotatedParser::new(&QueryOptions::new());
+        assert!(parser.push_line(",error,reference").unwrap().is_none());
+        assert!(matches!(
+            parser.finish(),
+            Err(InfluxError::QueryError(message, None)) if message.is_empty()
+        ));
+    }
+}
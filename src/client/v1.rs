@@ -0,0 +1,388 @@
+//! Support for the InfluxDB 1.x `/query` endpoint, for legacy servers and the 1.x compatibility
+//! API of InfluxDB 2.x.
+
+use super::{influxql, json, InfluxClient, InfluxError, InfluxQueryResponse};
+use crate::datetime;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+#[derive(Deserialize)]
+pub(crate) struct V1Response {
+    #[serde(default)]
+    pub(crate) results: Vec<StatementResult>,
+    pub(crate) error: Option<String>,
+}
+
+/// The result of a single InfluxQL statement.
+#[derive(Deserialize)]
+pub(crate) struct StatementResult {
+    #[serde(default)]
+    pub(crate) series: Vec<V1Series>,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct V1Series {
+    pub(crate) name: Option<String>,
+    #[serde(default)]
+    pub(crate) tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) columns: Vec<String>,
+    #[serde(default)]
+    pub(crate) values: Vec<Vec<json::Value>>,
+}
+
+impl V1Series {
+    /// The values of one column in every row.
+    fn column<'a>(&'a self, name: &str) -> impl Iterator<Item = Option<&'a json::Value>> + 'a {
+        let index = self.columns.iter().position(|c| c == name);
+        self.values
+            .iter()
+            .map(move |row| index.and_then(|index| row.get(index)))
+    }
+}
+
+/// A series in the result of an InfluxQL query, see `InfluxClient::query_influxql`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfluxQlSeries {
+    /// Name of the measurement, if the statement has one
+    pub name: Option<String>,
+    /// Values of the tags the query groups by
+    pub tags: BTreeMap<String, String>,
+    pub columns: Vec<String>,
+    /// The rows of the series, with one value per column as written in the response (`None` for
+    /// null), and times in RFC3339
+    pub values: Vec<Vec<Option<String>>>,
+}
+
+impl InfluxQlSeries {
+    /// The rows of the series as records, with the tags of the series and the non-null values
+    /// by column name, like the records returned by `InfluxClient::query`.
+    pub fn records(&self) -> InfluxQueryResponse {
+        self.values
+            .iter()
+            .map(|row| {
+                let mut record: HashMap<_, _> = self.tags.clone().into_iter().collect();
+                for (column, value) in self.columns.iter().zip(row) {
+                    if let Some(value) = value {
+                        record.insert(column.clone(), value.clone());
+                    }
+                }
+                record
+            })
+            .collect()
+    }
+}
+
+impl From<V1Series> for InfluxQlSeries {
+    fn from(series: V1Series) -> Self {
+        let values = series
+            .values
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|value| match value {
+                        json::Value::Null => None,
+                        json::Value::Bool(v) => Some(v.to_string()),
+                        json::Value::Number(v) | json::Value::String(v) => Some(v),
+                        value => json::to_string(&value).ok(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            name: series.name,
+            tags: series.tags,
+            columns: series.columns,
+            values,
+        }
+    }
+}
+
+/// A retention policy of an InfluxDB 1.x database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    /// Name of the retention policy
+    pub name: String,
+    /// How long data is kept, or `None` if it is kept forever
+    pub duration: Option<Duration>,
+    /// The time range covered by each shard group, or `None` to let the server decide
+    pub shard_group_duration: Option<Duration>,
+    /// Number of copies of the data kept in a cluster
+    pub replication: u32,
+    /// Whether this is the default retention policy of the database
+    pub default: bool,
+}
+
+impl RetentionPolicy {
+    /// A retention policy with the given name and duration, a replication factor of 1, which
+    /// isn't the default policy of its database.
+    pub fn new(name: impl Into<String>, duration: Option<Duration>) -> Self {
+        Self {
+            name: name.into(),
+            duration,
+            shard_group_duration: None,
+            replication: 1,
+            default: false,
+        }
+    }
+
+    fn from_row(series: &V1Series, row: usize) -> Option<Self> {
+        let value = |column: &str| series.column(column).nth(row).flatten();
+        let duration = |column: &str| {
+            let nanos = datetime::parse_duration(value(column)?.as_str()?)?;
+            (nanos > 0).then(|| Duration::from_nanos(nanos as u64))
+        };
+        Some(Self {
+            name: value("name")?.as_str()?.to_string(),
+            duration: duration("duration"),
+            shard_group_duration: duration("shardGroupDuration"),
+            replication: value("replicaN").and_then(|v| v.as_u64()).unwrap_or(1) as u32,
+            default: matches!(value("default"), Some(json::Value::Bool(true))),
+        })
+    }
+}
+
+/// Quote an identifier for use in an InfluxQL statement.
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    format!(
+        "\"{}\"",
+        identifier.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Format a duration as an InfluxQL duration literal, where `None` is an infinite duration.
+///
+/// A zero duration is an error rather than `0s`, which InfluxDB takes as infinite.
+fn duration_literal(duration: Option<Duration>) -> Result<String, InfluxError> {
+    match duration {
+        None => Ok("INF".to_string()),
+        Some(duration) if duration.is_zero() => Err(InfluxError::InvalidQuery(
+            "duration must not be zero, use `None` for an infinite duration".to_string(),
+        )),
+        Some(duration) => Ok(influxql::duration_literal(duration.as_nanos() as i128)),
+    }
+}
+
+impl InfluxClient {
+    /// Run InfluxQL statements against the 1.x `/query` endpoint, returning the result of each
+    /// statement, or the first error reported by the server.
+    pub(crate) async fn influxql_request(
+        &self,
+        db: Option<&str>,
+        rp: Option<&str>,
+        statements: &str,
+    ) -> Result<Vec<StatementResult>, InfluxError> {
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("q", statements);
+        if let Some(db) = db {
+            form.append_pair("db", db);
+        }
+        if let Some(rp) = rp {
+            form.append_pair("rp", rp);
+        }
+        let body = form.finish();
+
+        let request = self
+            .request("POST", format!("{}/query", self.url))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", "application/json")
+            .body(body)?;
+        let body = self.send(request).await?;
+
+        let response: V1Response = json::from_str(&body)?;
+        if let Some(error) = response.error {
+            return Err(InfluxError::InfluxQlError(error));
+        }
+        if let Some(error) = response.results.iter().find_map(|r| r.error.clone()) {
+            return Err(InfluxError::InfluxQlError(error));
+        }
+        Ok(response.results)
+    }
+
+    /// Run an InfluxQL query against the database `db` and the retention policy `rp` (or the
+    /// default one), returning the series of all its statements.
+    ///
+    /// This uses the 1.x `/query` endpoint, which InfluxDB 2.x also serves for buckets with a
+    /// DBRP mapping, so existing InfluxQL queries keep working while migrating to Flux.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::InfluxClient;
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let series = client
+    ///     .query_influxql("telegraf", None, "SELECT mean(usage_idle) FROM cpu GROUP BY host")
+    ///     .await?;
+    /// for series in series {
+    ///     println!("{:?}: {:?}", series.tags.get("host"), series.values);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_influxql(
+        &self,
+        db: &str,
+        rp: Option<&str>,
+        query: &str,
+    ) -> Result<Vec<InfluxQlSeries>, InfluxError> {
+        let results = self.influxql_request(Some(db), rp, query).await?;
+        Ok(results
+            .into_iter()
+            .flat_map(|r| r.series)
+            .map(InfluxQlSeries::from)
+            .collect())
+    }
+
+    /// Create a database on an InfluxDB 1.x server. Does nothing if it already exists.
+    pub async fn create_database(&self, name: &str) -> Result<(), InfluxError> {
+        let statement = format!("CREATE DATABASE {}", quote_identifier(name));
+        self.influxql_request(None, None, &statement).await?;
+        Ok(())
+    }
+
+    /// The names of the databases on an InfluxDB 1.x server.
+    pub async fn show_databases(&self) -> Result<Vec<String>, InfluxError> {
+        let results = self.influxql_request(None, None, "SHOW DATABASES").await?;
+        Ok(results
+            .iter()
+            .flat_map(|r| &r.series)
+            .flat_map(|s| s.column("name"))
+            .filter_map(|name| Some(name?.as_str()?.to_string()))
+            .collect())
+    }
+
+    /// Create a retention policy in a database on an InfluxDB 1.x server.
+    ///
+    /// Returns `InfluxError::InvalidQuery` if a duration of the policy is zero, since InfluxDB
+    /// takes a zero duration as infinite.
+    pub async fn create_retention_policy(
+        &self,
+        db: &str,
+        policy: &RetentionPolicy,
+    ) -> Result<(), InfluxError> {
+        let mut statement = format!(
+            "CREATE RETENTION POLICY {} ON {} DURATION {} REPLICATION {}",
+            quote_identifier(&policy.name),
+            quote_identifier(db),
+            duration_literal(policy.duration)?,
+            policy.replication
+        );
+        if let Some(shard_group_duration) = policy.shard_group_duration {
+            statement.push_str(&format!(
+                " SHARD DURATION {}",
+                duration_literal(Some(shard_group_duration))?
+            ));
+        }
+        if policy.default {
+            statement.push_str(" DEFAULT");
+        }
+        self.influxql_request(None, None, &statement).await?;
+        Ok(())
+    }
+
+    /// The retention policies of a database on an InfluxDB 1.x server.
+    pub async fn show_retention_policies(
+        &self,
+        db: &str,
+    ) -> Result<Vec<RetentionPolicy>, InfluxError> {
+        let statement = format!("SHOW RETENTION POLICIES ON {}", quote_identifier(db));
+        let results = self.influxql_request(None, None, &statement).await?;
+        results
+            .iter()
+            .flat_map(|r| &r.series)
+            .flat_map(|s| (0..s.values.len()).map(move |row| (s, row)))
+            .map(|(series, row)| {
+                RetentionPolicy::from_row(series, row).ok_or_else(|| {
+                    InfluxError::InvalidResponse("malformed retention policy".to_string())
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_retention_policies() {
+        let response: V1Response = json::from_str(
+            r#"{"results":[{"statement_id":0,"series":[{"columns":["name","duration","shardGroupDuration","replicaN","default"],
+            "values":[["autogen","0s","168h0m0s",1,true],["week","168h0m0s","24h0m0s",2,false]]}]}]}"#,
+        )
+        .unwrap();
+        let series = &response.results[0].series[0];
+        let policies: Vec<_> = (0..2)
+            .map(|row| RetentionPolicy::from_row(series, row).unwrap())
+            .collect();
+
+        assert_eq!(
+            policies,
+            vec![
+                RetentionPolicy {
+                    name: "autogen".to_string(),
+                    duration: None,
+                    shard_group_duration: Some(Duration::from_secs(168 * 3600)),
+                    replication: 1,
+                    default: true,
+                },
+                RetentionPolicy {
+                    name: "week".to_string(),
+                    duration: Some(Duration::from_secs(168 * 3600)),
+                    shard_group_duration: Some(Duration::from_secs(24 * 3600)),
+                    replication: 2,
+                    default: false,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_series() {
+        let response: V1Response = json::from_str(
+            r#"{"results":[{"statement_id":0,"series":[{"name":"cpu","tags":{"host":"a"},"columns":["time","mean","note"],
+            "values":[["2021-06-01T00:00:00Z",99.5,null],["2021-06-01T00:01:00Z",12,"x"]]}]}]}"#,
+        )
+        .unwrap();
+        let series: Vec<_> = response
+            .results
+            .into_iter()
+            .flat_map(|r| r.series)
+            .map(InfluxQlSeries::from)
+            .collect();
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].name.as_deref(), Some("cpu"));
+        assert_eq!(
+            series[0].values[0],
+            vec![
+                Some("2021-06-01T00:00:00Z".to_string()),
+                Some("99.5".to_string()),
+                None
+            ]
+        );
+
+        let records = series[0].records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].get("host").map(String::as_str), Some("a"));
+        assert_eq!(records[0].get("note"), None);
+        assert_eq!(records[1].get("mean").map(String::as_str), Some("12"));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote_identifier(r#"my "db""#), r#""my \"db\"""#);
+        assert_eq!(duration_literal(None).unwrap(), "INF");
+        assert_eq!(
+            duration_literal(Some(Duration::from_secs(3600))).unwrap(),
+            "1h"
+        );
+        assert_eq!(
+            duration_literal(Some(Duration::from_millis(500))).unwrap(),
+            "500ms"
+        );
+        assert!(duration_literal(Some(Duration::ZERO)).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Running a query over a long time range in smaller windows, see
+//! `InfluxClient::query_windowed`.
+
+use super::{
+    lanes,
+    query::{Query, RangeBound},
+    InfluxClient, InfluxError, InfluxQueryResponse,
+};
+use crate::{datetime, flux::FluxValue};
+use std::time::Duration;
+
+impl InfluxClient {
+    /// Run `query` from `start` to `stop` as a sequence of sub-queries over `window` each, and
+    /// concatenate their records in order, for exports where a single query would run into the
+    /// memory or time limits of the server.
+    ///
+    /// The `range` stage of `query` is replaced by the range of each window, so `query` has to
+    /// have one. Up to `concurrency` windows are queried at the same time, `1` queries them one
+    /// after the other. Windows don't overlap, but aggregates over time (such as
+    /// `aggregateWindow`) are split at window boundaries unless `window` is a multiple of them.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use influxrs::{InfluxClient, Query, RangeBound};
+    /// # use std::time::Duration;
+    /// # async fn example(client: InfluxClient) -> Result<(), influxrs::InfluxError> {
+    /// let query = Query::new(r#"from(bucket: "example_bucket")"#)
+    ///     .then("range(start: 0)")
+    ///     .filter_measurement("cpu");
+    /// let day = Duration::from_secs(86400);
+    /// let records = client
+    ///     .query_windowed(query, RangeBound::ago(day * 90), RangeBound::Now, day, 4)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_windowed(
+        &self,
+        query: Query,
+        start: impl Into<RangeBound>,
+        stop: impl Into<RangeBound>,
+        window: Duration,
+        concurrency: usize,
+    ) -> Result<InfluxQueryResponse, InfluxError> {
+        if query.range_arguments().is_none() {
+            return Err(InfluxError::InvalidQuery(
+                "query has no range with a start".to_string(),
+            ));
+        }
+        let now = datetime::now_nanos();
+        let windows = split_range(start.into().resolve(now), stop.into().resolve(now), window)?;
+
+        let mut records = Vec::new();
+        for chunk in windows.chunks(concurrency.max(1)) {
+            // Windows resolved from relative bounds differ between calls, so they aren't cached.
+            let queries = chunk.iter().map(|(start, stop)| {
+                let query = query
+                    .clone()
+                    .with_range(FluxValue::Time(*start), FluxValue::Time(*stop));
+                async move { self.query_uncached(&query).await }
+            });
+            for result in lanes::join_all(queries).await {
+                records.extend(result?);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Split the range from `start` to `stop` (in nanoseconds since the Unix epoch) into windows of
+/// length `window`, where the last one may be shorter.
+fn split_range(
+    start: i128,
+    stop: i128,
+    window: Duration,
+) -> Result<Vec<(i128, i128)>, InfluxError> {
+    let window = window.as_nanos() as i128;
+    if window == 0 {
+        return Err(InfluxError::InvalidQuery(
+            "window must not be empty".to_string(),
+        ));
+    }
+    if stop <= start {
+        return Err(InfluxError::InvalidQuery(
+            "range stop must be after start".to_string(),
+        ));
+    }
+    let mut windows = Vec::new();
+    let mut window_start = start;
+    while window_start < stop {
+        let window_stop = (window_start + window).min(stop);
+        windows.push((window_start, window_stop));
+        window_start = window_stop;
+    }
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_into_windows() {
+        assert_eq!(
+            split_range(0, 25, Duration::from_nanos(10)).unwrap(),
+            vec![(0, 10), (10, 20), (20, 25)]
+        );
+        assert_eq!(
+            split_range(-5, 5, Duration::from_nanos(10)).unwrap(),
+            vec![(-5, 5)]
+        );
+        assert!(split_range(0, 25, Duration::ZERO).is_err());
+        assert!(split_range(25, 25, Duration::from_nanos(10)).is_err());
+
+        let query = Query::new(r#"from(bucket: "b")"#)
+            .then("range(start: -30d)")
+            .with_range(FluxValue::Time(0), FluxValue::Time(86_400_000_000_000));
+        assert_eq!(
+            query.to_string(),
+            r#"from(bucket: "b")
+ |> range(start: 1970-01-01T00:00:00Z, stop: 1970-01-02T00:00:00Z)"#
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Conversions between unix timestamps (nanoseconds) and the time formats used by Flux and
+//! InfluxDB responses.
+
+use std::time::SystemTime;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+const SECONDS_PER_DAY: i128 = 86_400;
+
+/// The current time as nanoseconds since the Unix epoch.
+pub(crate) fn now_nanos() -> i128 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+/// Parse an RFC3339 timestamp such as `2021-06-01T11:16:05.684Z` into nanoseconds since the Unix
+/// epoch.
+pub(crate) fn parse_rfc3339(s: &str) -> Option<i128> {
+    let s = s.trim();
+    let (date, rest) = s.split_once(['T', 't', ' '])?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i128 = parse_digits(date_parts.next()?, 4)?;
+    let month: i128 = parse_digits(date_parts.next()?, 2)?;
+    let day: i128 = parse_digits(date_parts.next()?, 2)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let offset_start = rest.find(['Z', 'z', '+', '-'])?;
+    let (time, offset) = rest.split_at(offset_start);
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (time, None),
+    };
+    let mut time_parts = time.splitn(3, ':');
+    let hour: i128 = parse_digits(time_parts.next()?, 2)?;
+    let minute: i128 = parse_digits(time_parts.next()?, 2)?;
+    let second: i128 = parse_digits(time_parts.next()?, 2)?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let nanos = match fraction {
+        Some(fraction) if !fraction.is_empty() && fraction.len() <= 9 => {
+            let digits: i128 = parse_digits(fraction, fraction.len())?;
+            digits * 10_i128.pow(9 - fraction.len() as u32)
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+
+    let offset_seconds = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let hours: i128 = parse_digits(hours, 2)?;
+            let minutes: i128 = parse_digits(minutes, 2)?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    let seconds =
+        days_from_civil(year, month, day) * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second
+            - offset_seconds;
+    Some(seconds * NANOS_PER_SECOND + nanos)
+}
+
+/// Format nanoseconds since the Unix epoch as an RFC3339 timestamp in UTC, with as many
+/// fractional digits as needed.
+pub(crate) fn format_rfc3339(timestamp_nanos: i128) -> String {
+    let seconds = timestamp_nanos.div_euclid(NANOS_PER_SECOND);
+    let nanos = timestamp_nanos.rem_euclid(NANOS_PER_SECOND);
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let time_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    let mut formatted = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60
+    );
+    if nanos != 0 {
+        let fraction = format!("{:09}", nanos);
+        formatted.push('.');
+        formatted.push_str(fraction.trim_end_matches('0'));
+    }
+    formatted.push('Z');
+    formatted
+}
+
+/// Resolve a time argument of a Flux `range` call, such as `-1h`, `now()`, `1622493322` (unix
+/// seconds) or `2021-06-01T11:16:05Z`, into nanoseconds since the Unix epoch.
+pub(crate) fn parse_flux_time(s: &str, now_nanos: i128) -> Option<i128> {
+    let s = s.trim();
+    if s == "now()" {
+        Some(now_nanos)
+    } else if let Ok(seconds) = s.parse::<i128>() {
+        seconds.checked_mul(NANOS_PER_SECOND)
+    } else if let Some(duration) = parse_duration(s) {
+        now_nanos.checked_add(duration)
+    } else {
+        parse_rfc3339(s)
+    }
+}
+
+/// Parse a Flux duration literal such as `-1h30m` into nanoseconds.
+///
+/// Calendar units (`mo` and `y`) don't have a fixed length and are not supported, and neither are
+/// durations that overflow.
+pub(crate) fn parse_duration(s: &str) -> Option<i128> {
+    let s = s.trim();
+    let (sign, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s),
+    };
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut total = 0_i128;
+    while !rest.is_empty() {
+        let digits_end = rest.find(|c: char| !c.is_ascii_digit())?;
+        if digits_end == 0 {
+            return None;
+        }
+        let magnitude: i128 = rest[..digits_end].parse().ok()?;
+        rest = &rest[digits_end..];
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_end] {
+            "ns" => 1,
+            "us" | "µs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => NANOS_PER_SECOND,
+            "m" => 60 * NANOS_PER_SECOND,
+            "h" => 3600 * NANOS_PER_SECOND,
+            "d" => SECONDS_PER_DAY * NANOS_PER_SECOND,
+            "w" => 7 * SECONDS_PER_DAY * NANOS_PER_SECOND,
+            _ => return None,
+        };
+        total = total.checked_add(magnitude.checked_mul(unit)?)?;
+        rest = &rest[unit_end..];
+    }
+    Some(sign * total)
+}
+
+/// Format nanoseconds as a Flux duration literal such as `-1h30m`.
+pub(crate) fn format_duration(nanos: i128) -> String {
+    const UNITS: &[(&str, i128)] = &[
+        ("h", 3600 * NANOS_PER_SECOND),
+        ("m", 60 * NANOS_PER_SECOND),
+        ("s", NANOS_PER_SECOND),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+    ];
+    if nanos == 0 {
+        return "0s".to_string();
+    }
+    let mut formatted = String::new();
+    if nanos < 0 {
+        formatted.push('-');
+    }
+    let mut rest = nanos.abs();
+    for (unit, length) in UNITS {
+        if rest >= *length {
+            formatted.push_str(&format!("{}{}", rest / length, unit));
+            rest %= length;
+        }
+    }
+    formatted
+}
+
+fn parse_digits(s: &str, len: usize) -> Option<i128> {
+    if s.len() != len || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Days since the Unix epoch of a proleptic Gregorian date.
+fn days_from_civil(year: i128, month: i128, day: i128) -> i128 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian date of a number of days since the Unix epoch.
+fn civil_from_days(days: i128) -> (i128, i128, i128) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc3339_round_trip() {
+        let cases = [
+            ("1970-01-01T00:00:00Z", 0),
+            ("2021-06-01T11:16:05.684Z", 1_622_546_165_684_000_000),
+            ("1969-12-31T23:59:59.999999999Z", -1),
+            ("2000-02-29T12:00:00Z", 951_825_600_000_000_000),
+        ];
+        for (formatted, nanos) in cases {
+            assert_eq!(parse_rfc3339(formatted), Some(nanos), "{}", formatted);
+            assert_eq!(format_rfc3339(nanos), formatted);
+        }
+        assert_eq!(
+            parse_rfc3339("2021-06-01T13:16:05.684+02:00"),
+            Some(1_622_546_165_684_000_000)
+        );
+        assert_eq!(parse_rfc3339("2021-06-01"), None);
+        assert_eq!(parse_rfc3339("2021-13-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn flux_times() {
+        let now = 1_622_546_165_000_000_000;
+        assert_eq!(parse_flux_time("now()", now), Some(now));
+        assert_eq!(parse_flux_time("-1m", now), Some(now - 60_000_000_000));
+        assert_eq!(
+            parse_flux_time("1622493322", now),
+            Some(1_622_493_322_000_000_000)
+        );
+        assert_eq!(
+            parse_flux_time("1970-01-01T00:00:01Z", now),
+            Some(1_000_000_000)
+        );
+        assert_eq!(parse_flux_time("v.timeRangeStart", now), None);
+        assert_eq!(
+            parse_flux_time("999999999999999999999999999999999", now),
+            None
+        );
+        assert_eq!(parse_flux_time(&format!("{}ns", i128::MAX), now), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("-1h30m"), Some(-5_400_000_000_000));
+        assert_eq!(parse_duration("250ms"), Some(250_000_000));
+        assert_eq!(parse_duration("1mo"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("99999999999999999999999999999w"), None);
+        assert_eq!(parse_duration(&format!("{}ns1ns", i128::MAX)), None);
+
+        for formatted in ["-1h30m", "250ms", "1s1ns", "49h", "0s"] {
+            assert_eq!(
+                format_duration(parse_duration(formatted).unwrap()),
+                formatted
+            );
+        }
+    }
+}
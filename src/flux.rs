@@ -0,0 +1,316 @@
+//! Helpers for working with Flux scripts.
+
+use crate::datetime;
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+/// Escape `s` for use inside a Flux string literal: quotes, backslashes, line breaks and the
+/// start of interpolations (`${`) are escaped, so that the string can't end the literal early or
+/// evaluate anything.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::flux::escape_string;
+/// let host = r#"a" or true or "${token}"#;
+/// assert_eq!(
+///     format!(r#"r.host == "{}""#, escape_string(host)),
+///     r#"r.host == "a\" or true or \"\${token}""#
+/// );
+/// ```
+pub fn escape_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '$' if chars.peek() == Some(&'{') => escaped.push_str("\\$"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A value that can be used in a Flux script, which displays as a Flux literal.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::flux::FluxValue;
+/// # use std::time::Duration;
+/// assert_eq!(FluxValue::from("say \"hi\"").to_string(), r#""say \"hi\"""#);
+/// assert_eq!(FluxValue::from(2.0).to_string(), "2.0");
+/// assert_eq!(FluxValue::from(Duration::from_secs(90)).to_string(), "1m30s");
+/// assert_eq!(FluxValue::Time(0).to_string(), "1970-01-01T00:00:00Z");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum FluxValue {
+    String(String),
+    Float(f64),
+    Int(i64),
+    /// A duration in nanoseconds, which can be negative
+    Duration(i128),
+    /// A time in nanoseconds since the Unix epoch
+    Time(i128),
+    Bool(bool),
+}
+
+impl Display for FluxValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FluxValue::String(s) => write!(f, "\"{}\"", escape_string(s)),
+            // Flux has no literals for these.
+            FluxValue::Float(v) if v.is_nan() => f.write_str(r#"float(v: "NaN")"#),
+            FluxValue::Float(v) if v.is_infinite() => {
+                let sign = if *v > 0.0 { '+' } else { '-' };
+                write!(f, r#"float(v: "{}Inf")"#, sign)
+            }
+            FluxValue::Float(v) => {
+                // Float literals need a decimal point, and never have an exponent.
+                let formatted = v.to_string();
+                if formatted.contains('.') {
+                    f.write_str(&formatted)
+                } else {
+                    write!(f, "{}.0", formatted)
+                }
+            }
+            FluxValue::Int(v) => write!(f, "{}", v),
+            FluxValue::Duration(nanos) => f.write_str(&datetime::format_duration(*nanos)),
+            FluxValue::Time(nanos) => f.write_str(&datetime::format_rfc3339(*nanos)),
+            FluxValue::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl From<&str> for FluxValue {
+    fn from(v: &str) -> Self {
+        FluxValue::String(v.to_string())
+    }
+}
+
+impl From<&String> for FluxValue {
+    fn from(v: &String) -> Self {
+        FluxValue::String(v.clone())
+    }
+}
+
+impl From<String> for FluxValue {
+    fn from(v: String) -> Self {
+        FluxValue::String(v)
+    }
+}
+
+impl From<f64> for FluxValue {
+    fn from(v: f64) -> Self {
+        FluxValue::Float(v)
+    }
+}
+
+impl From<i64> for FluxValue {
+    fn from(v: i64) -> Self {
+        FluxValue::Int(v)
+    }
+}
+
+impl From<i32> for FluxValue {
+    fn from(v: i32) -> Self {
+        FluxValue::Int(v.into())
+    }
+}
+
+impl From<bool> for FluxValue {
+    fn from(v: bool) -> Self {
+        FluxValue::Bool(v)
+    }
+}
+
+impl From<Duration> for FluxValue {
+    fn from(v: Duration) -> Self {
+        FluxValue::Duration(v.as_nanos() as i128)
+    }
+}
+
+impl From<SystemTime> for FluxValue {
+    fn from(v: SystemTime) -> Self {
+        match v.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since) => FluxValue::Time(since.as_nanos() as i128),
+            Err(e) => FluxValue::Time(-(e.duration().as_nanos() as i128)),
+        }
+    }
+}
+
+/// Format a Flux script in a normalized layout, so that generated scripts can be stored and
+/// diffed cleanly.
+///
+/// Whitespace outside of strings and comments is normalized, every pipe-forward at the top
+/// level of a statement starts a new line indented by four spaces, and runs of blank lines
+/// between statements are collapsed into one. Formatting an already formatted script doesn't
+/// change it.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::flux::format_flux;
+/// let script = r#"from(bucket:  "b")|> range( start: -1h )
+///   |>filter(fn: (r) => r._measurement == "cpu")"#;
+/// assert_eq!(
+///     format_flux(script),
+///     r#"from(bucket: "b")
+///     |> range(start: -1h)
+///     |> filter(fn: (r) => r._measurement == "cpu")"#
+/// );
+/// ```
+pub fn format_flux(script: &str) -> String {
+    let mut formatter = Formatter::default();
+    let mut chars = script.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                formatter.token("\"");
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    formatter.output.push(c);
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                let mut comment = String::from("/");
+                while let Some(c) = chars.next_if(|c| *c != '\n') {
+                    comment.push(c);
+                }
+                formatter.token(comment.trim_end());
+                // A comment always ends its line, even inside brackets.
+                chars.next();
+                formatter.newlines = 1;
+            }
+            '|' if chars.peek() == Some(&'>') => {
+                chars.next();
+                if formatter.depth == 0 {
+                    formatter.pipe();
+                } else {
+                    formatter.token("|>");
+                }
+            }
+            '(' | '[' | '{' => {
+                formatter.token(c.encode_utf8(&mut [0; 4]));
+                formatter.depth += 1;
+                formatter.after_open = true;
+            }
+            ')' | ']' | '}' => {
+                formatter.depth = formatter.depth.saturating_sub(1);
+                formatter.space = false;
+                formatter.token(c.encode_utf8(&mut [0; 4]));
+            }
+            ',' => {
+                formatter.space = false;
+                formatter.token(",");
+            }
+            '\n' if formatter.depth == 0 => formatter.newlines += 1,
+            c if c.is_whitespace() => formatter.space = true,
+            c => formatter.token(c.encode_utf8(&mut [0; 4])),
+        }
+    }
+    formatter.output
+}
+
+#[derive(Default)]
+struct Formatter {
+    output: String,
+    /// Nesting depth of brackets.
+    depth: usize,
+    /// Number of newlines seen since the last token.
+    newlines: usize,
+    /// Whether whitespace has been seen since the last token.
+    space: bool,
+    /// Whether the last token was an opening bracket, after which no space is needed.
+    after_open: bool,
+    /// Whether the last token was a pipe, after which exactly one space is needed.
+    after_pipe: bool,
+}
+
+impl Formatter {
+    fn token(&mut self, token: &str) {
+        if self.output.is_empty() {
+            // Leading whitespace is dropped.
+        } else if self.after_pipe {
+            self.output.push(' ');
+        } else if self.newlines > 0 {
+            self.output
+                .push_str(if self.newlines > 1 { "\n\n" } else { "\n" });
+        } else if self.space && !self.after_open {
+            self.output.push(' ');
+        }
+        self.output.push_str(token);
+        self.newlines = 0;
+        self.space = false;
+        self.after_open = false;
+        self.after_pipe = false;
+    }
+
+    fn pipe(&mut self) {
+        self.output.push_str("\n    |>");
+        self.newlines = 0;
+        self.space = false;
+        self.after_open = false;
+        self.after_pipe = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_is_idempotent() {
+        let script = r#"import "strings"
+
+
+option now = () => 2021-06-01T00:00:00Z
+// keep comments   as they are
+data = from(bucket: "b") |> range(start: -1h) |> filter(fn: (r) =>
+        r._measurement == "a  |>  b" and strings.hasPrefix(v: r.host, prefix: "web"))
+join(tables: {a: data |> mean(), b: data}, on: ["_time"])   "#;
+        let formatted = format_flux(script);
+        assert_eq!(
+            formatted,
+            r#"import "strings"
+
+option now = () => 2021-06-01T00:00:00Z
+// keep comments   as they are
+data = from(bucket: "b")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "a  |>  b" and strings.hasPrefix(v: r.host, prefix: "web"))
+join(tables: {a: data |> mean(), b: data}, on: ["_time"])"#
+        );
+        assert_eq!(format_flux(&formatted), formatted);
+    }
+
+    #[test]
+    fn value_literals() {
+        let cases = [
+            (FluxValue::from("a\\b\nc$d${e}"), r#""a\\b\nc$d\${e}""#),
+            (FluxValue::from(-1.5), "-1.5"),
+            (FluxValue::from(1e21), "1000000000000000000000.0"),
+            (FluxValue::from(f64::NAN), r#"float(v: "NaN")"#),
+            (FluxValue::from(f64::NEG_INFINITY), r#"float(v: "-Inf")"#),
+            (FluxValue::from(-3), "-3"),
+            (FluxValue::Duration(-5_400_000_000_000), "-1h30m"),
+            (
+                FluxValue::Time(1_622_546_165_684_000_000),
+                "2021-06-01T11:16:05.684Z",
+            ),
+            (FluxValue::from(false), "false"),
+        ];
+        for (value, literal) in cases {
+            assert_eq!(value.to_string(), literal);
+        }
+    }
+}
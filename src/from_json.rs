@@ -0,0 +1,1182 @@
+//! Flattening of JSON-like documents into `Measurement`s, see `Measurement::from_json`.
+
+use crate::{Field, Measurement, MeasurementBuilder, MeasurementBuilderError, Precision};
+use serde::{ser, Serialize};
+use std::{collections::BTreeSet, convert::TryInto, error::Error, fmt::Display};
+
+/// Options for `Measurement::from_json`.
+#[derive(Debug, Clone)]
+pub struct FromJsonOptions {
+    separator: String,
+    tags: BTreeSet<String>,
+    timestamp: Option<(String, Precision)>,
+}
+
+impl Default for FromJsonOptions {
+    fn default() -> Self {
+        Self {
+            separator: ".".to_string(),
+            tags: BTreeSet::new(),
+            timestamp: None,
+        }
+    }
+}
+
+impl FromJsonOptions {
+    /// Set the separator between the keys of nested values, defaults to `.`.
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Make the value at a (flattened) key path a tag instead of a field.
+    pub fn tag(mut self, path: impl Into<String>) -> Self {
+        self.tags.insert(path.into());
+        self
+    }
+
+    /// Make the values at several (flattened) key paths tags instead of fields.
+    pub fn tags<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.tags.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Use the integer at a (flattened) key path as the timestamp, in the given precision. If
+    /// not set, or the key is missing, the current time is used.
+    pub fn timestamp(mut self, path: impl Into<String>, precision: Precision) -> Self {
+        self.timestamp = Some((path.into(), precision));
+        self
+    }
+}
+
+/// The error returned when `Measurement::from_json` fails.
+#[derive(Debug)]
+pub enum FromJsonError {
+    /// The document was not an object.
+    NotAnObject,
+    /// An object had a key that isn't a string, number or unit enum variant.
+    InvalidKey,
+    /// The value at the timestamp path was not an integer.
+    InvalidTimestamp(String),
+    /// The value at the timestamp path is too large to be represented in nanoseconds.
+    TimestampOutOfRange(String),
+    /// The document failed to serialize, with the contained message.
+    Serialize(String),
+    /// The flattened document wasn't a valid measurement, for example because it had no fields.
+    Build(MeasurementBuilderError),
+    /// The value at the contained key can't be stored in a field, see `FieldClassifier`.
+    InvalidValue(String, FieldValueError),
+}
+
+impl Display for FromJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FromJsonError::NotAnObject => write!(f, "document is not an object"),
+            FromJsonError::InvalidKey => {
+                write!(f, "object keys must be strings, numbers or unit variants")
+            }
+            FromJsonError::InvalidTimestamp(path) => {
+                write!(f, "timestamp at '{}' is not an integer", path)
+            }
+            FromJsonError::TimestampOutOfRange(path) => {
+                write!(f, "timestamp at '{}' is out of range", path)
+            }
+            FromJsonError::Serialize(message) => write!(f, "serialization failed: '{}'", message),
+            FromJsonError::Build(e) => write!(f, "invalid measurement: '{}'", e),
+            FromJsonError::InvalidValue(key, e) => write!(f, "invalid value of '{}': {}", key, e),
+        }
+    }
+}
+
+impl Error for FromJsonError {}
+
+impl ser::Error for FromJsonError {
+    fn custom<T: Display>(msg: T) -> Self {
+        FromJsonError::Serialize(msg.to_string())
+    }
+}
+
+impl From<MeasurementBuilderError> for FromJsonError {
+    fn from(e: MeasurementBuilderError) -> Self {
+        FromJsonError::Build(e)
+    }
+}
+
+impl Measurement {
+    /// Flatten a JSON object, or anything else that serializes as a map or struct, into a
+    /// measurement.
+    ///
+    /// Nested objects and arrays are flattened into fields named by their key path, such as
+    /// `sensor.readings.0`, and nulls are skipped. Paths selected with `FromJsonOptions::tag`
+    /// become tags instead. A `serde_json::Value` can be passed directly.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::{Field, FromJsonOptions, Measurement, Precision};
+    /// #[derive(serde::Serialize)]
+    /// struct Payload {
+    ///     device: &'static str,
+    ///     time: i64,
+    ///     readings: Readings,
+    /// }
+    /// #[derive(serde::Serialize)]
+    /// struct Readings {
+    ///     temperature: f64,
+    ///     humidity: Option<f64>,
+    /// }
+    ///
+    /// let payload = Payload {
+    ///     device: "sensor-1",
+    ///     time: 1622493622,
+    ///     readings: Readings { temperature: 21.5, humidity: None },
+    /// };
+    /// let options = FromJsonOptions::default()
+    ///     .separator("_")
+    ///     .tag("device")
+    ///     .timestamp("time", Precision::Seconds);
+    /// let m = Measurement::from_json("climate", &payload, &options).unwrap();
+    /// assert_eq!(
+    ///     m.to_line_protocol(),
+    ///     "climate,device=sensor-1 readings_temperature=21.5 1622493622000000000"
+    /// );
+    /// ```
+    pub fn from_json<T: Serialize + ?Sized>(
+        measurement_name: impl Into<String>,
+        document: &T,
+        options: &FromJsonOptions,
+    ) -> Result<Measurement, FromJsonError> {
+        let mut entries = Vec::new();
+        document.serialize(Flattener {
+            path: String::new(),
+            out: &mut entries,
+            separator: &options.separator,
+        })?;
+
+        let mut builder = MeasurementBuilder::new(measurement_name);
+        for (path, field) in entries {
+            match &options.timestamp {
+                Some((timestamp_path, precision)) if *timestamp_path == path => {
+                    let timestamp: i128 = match field {
+                        Field::Integer(v) => v,
+                        Field::UInteger(v) => match v.try_into() {
+                            Ok(v) => v,
+                            Err(_) => return Err(FromJsonError::TimestampOutOfRange(path)),
+                        },
+                        _ => return Err(FromJsonError::InvalidTimestamp(path)),
+                    };
+                    let nanos = match timestamp.checked_mul(precision.nanos_per_unit()) {
+                        Some(nanos) => nanos,
+                        None => return Err(FromJsonError::TimestampOutOfRange(path)),
+                    };
+                    builder.timestamp = Some((nanos, *precision));
+                }
+                _ if options.tags.contains(&path) => builder = builder.tag(path, tag_value(field)),
+                _ => builder = builder.field(path, field),
+            }
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// The error returned when a value can't be converted into a `Field`, see
+/// `Field::try_from_value`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValueError {
+    /// The value was null (or a unit value).
+    Null,
+    /// The value was an object or array.
+    Nested,
+    /// The value is of a type that fields can't hold, such as bytes.
+    Unsupported(String),
+}
+
+impl Display for FieldValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValueError::Null => write!(f, "null can't be stored in a field"),
+            FieldValueError::Nested => write!(f, "nested values can't be stored in a field"),
+            FieldValueError::Unsupported(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for FieldValueError {}
+
+impl ser::Error for FieldValueError {
+    fn custom<T: Display>(msg: T) -> Self {
+        FieldValueError::Unsupported(msg.to_string())
+    }
+}
+
+impl Field {
+    /// Convert a scalar value, such as a `serde_json::Value` holding a number, string or bool,
+    /// into a field.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::{Field, FieldValueError};
+    /// assert_eq!(Field::try_from_value(&1.5), Ok(Field::Float(1.5)));
+    /// assert_eq!(Field::try_from_value(&Some("on")), Ok(Field::from("on")));
+    /// assert_eq!(Field::try_from_value(&None::<i32>), Err(FieldValueError::Null));
+    /// assert_eq!(Field::try_from_value(&vec![1]), Err(FieldValueError::Nested));
+    /// ```
+    pub fn try_from_value<T: Serialize + ?Sized>(value: &T) -> Result<Field, FieldValueError> {
+        value.serialize(FieldSerializer)
+    }
+}
+
+/// Splits the entries of a flat object into tags and fields: numbers and bools become fields,
+/// and strings become tags if their key is allowed and they are short enough, and fields
+/// otherwise.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::{FieldClassifier, Measurement};
+/// # use std::collections::BTreeMap;
+/// let mut payload = BTreeMap::new();
+/// payload.insert("host", "web-1".to_string());
+/// payload.insert("status", "a long status message".to_string());
+/// let classifier = FieldClassifier::new().tags(vec!["host", "status"]).max_tag_length(10);
+/// let m = classifier
+///     .classify(Measurement::builder("m"), &payload)
+///     .unwrap()
+///     .build()
+///     .unwrap();
+/// assert_eq!(m.tag("host"), Some("web-1"));
+/// assert!(m.field("status").is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FieldClassifier {
+    tag_keys: BTreeSet<String>,
+    max_tag_length: usize,
+}
+
+impl Default for FieldClassifier {
+    fn default() -> Self {
+        Self {
+            tag_keys: BTreeSet::new(),
+            max_tag_length: 64,
+        }
+    }
+}
+
+impl FieldClassifier {
+    /// A classifier without allowed tag keys, so that everything becomes a field.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow string values with this key to become tags.
+    pub fn tag(mut self, key: impl Into<String>) -> Self {
+        self.tag_keys.insert(key.into());
+        self
+    }
+
+    /// Allow string values with these keys to become tags.
+    pub fn tags<I, K>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<String>,
+    {
+        self.tag_keys.extend(keys.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set the maximum length (in bytes) of string values that become tags, defaults to 64.
+    /// Longer values become fields, to keep the series cardinality down.
+    pub fn max_tag_length(mut self, max_tag_length: usize) -> Self {
+        self.max_tag_length = max_tag_length;
+        self
+    }
+
+    /// Add the entries of a flat object, such as a `serde_json::Value` or a map, to a builder.
+    ///
+    /// Fails with `FromJsonError::InvalidValue` if an entry is null or nested.
+    pub fn classify<T: Serialize + ?Sized>(
+        &self,
+        mut builder: MeasurementBuilder,
+        document: &T,
+    ) -> Result<MeasurementBuilder, FromJsonError> {
+        for (key, field) in document.serialize(EntriesSerializer)? {
+            let field = field.map_err(|e| FromJsonError::InvalidValue(key.clone(), e))?;
+            builder = match field {
+                Field::String(value)
+                    if value.len() <= self.max_tag_length && self.tag_keys.contains(&key) =>
+                {
+                    builder.tag(key, value)
+                }
+                field => builder.field(key, field),
+            };
+        }
+        Ok(builder)
+    }
+}
+
+fn tag_value(field: Field) -> String {
+    match field {
+        Field::String(v) => v,
+        Field::Float(v) => v.to_string(),
+        Field::Bool(v) => v.to_string(),
+        Field::Integer(v) => v.to_string(),
+        Field::UInteger(v) => v.to_string(),
+    }
+}
+
+fn join(path: &str, separator: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}{}{}", path, separator, key)
+    }
+}
+
+/// Serializes a value into (key path, field) pairs.
+struct Flattener<'a> {
+    path: String,
+    out: &'a mut Vec<(String, Field)>,
+    separator: &'a str,
+}
+
+impl<'a> Flattener<'a> {
+    fn push(self, field: Field) -> Result<(), FromJsonError> {
+        if self.path.is_empty() {
+            return Err(FromJsonError::NotAnObject);
+        }
+        self.out.push((self.path, field));
+        Ok(())
+    }
+
+    fn compound(self) -> Compound<'a> {
+        Compound {
+            path: self.path,
+            out: self.out,
+            separator: self.separator,
+            index: 0,
+            key: None,
+        }
+    }
+}
+
+impl<'a> ser::Serializer for Flattener<'a> {
+    type Ok = ();
+    type Error = FromJsonError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), FromJsonError> {
+        self.push(Field::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), FromJsonError> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), FromJsonError> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), FromJsonError> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), FromJsonError> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), FromJsonError> {
+        self.push(Field::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), FromJsonError> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), FromJsonError> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), FromJsonError> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), FromJsonError> {
+        self.serialize_u128(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), FromJsonError> {
+        self.push(Field::UInteger(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), FromJsonError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), FromJsonError> {
+        self.push(Field::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), FromJsonError> {
+        self.push(Field::from(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), FromJsonError> {
+        self.push(Field::from(v))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), FromJsonError> {
+        Err(ser::Error::custom("bytes can't be stored in a field"))
+    }
+
+    fn serialize_none(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), FromJsonError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), FromJsonError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), FromJsonError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), FromJsonError> {
+        value.serialize(Flattener {
+            path: join(&self.path, self.separator, variant),
+            ..self
+        })
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, FromJsonError> {
+        if self.path.is_empty() {
+            return Err(FromJsonError::NotAnObject);
+        }
+        Ok(self.compound())
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>, FromJsonError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, FromJsonError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, FromJsonError> {
+        let path = join(&self.path, self.separator, variant);
+        Ok(Flattener { path, ..self }.compound())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, FromJsonError> {
+        Ok(self.compound())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, FromJsonError> {
+        Ok(self.compound())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, FromJsonError> {
+        let path = join(&self.path, self.separator, variant);
+        Ok(Flattener { path, ..self }.compound())
+    }
+}
+
+/// Serializes the elements of sequences and the entries of maps and structs.
+struct Compound<'a> {
+    path: String,
+    out: &'a mut Vec<(String, Field)>,
+    separator: &'a str,
+    /// Index of the next sequence element
+    index: usize,
+    /// Path of the next map value, after its key was serialized
+    key: Option<String>,
+}
+
+impl Compound<'_> {
+    fn child(&mut self, key: &str) -> Flattener<'_> {
+        Flattener {
+            path: join(&self.path, self.separator, key),
+            out: &mut *self.out,
+            separator: self.separator,
+        }
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), FromJsonError> {
+        let index = self.index.to_string();
+        self.index += 1;
+        value.serialize(self.child(&index))
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = FromJsonError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), FromJsonError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = FromJsonError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), FromJsonError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = FromJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), FromJsonError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = FromJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), FromJsonError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = FromJsonError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), FromJsonError> {
+        let mut entries = Vec::new();
+        key.serialize(Flattener {
+            path: "key".to_string(),
+            out: &mut entries,
+            separator: self.separator,
+        })?;
+        match entries.pop() {
+            Some((_, key)) if entries.is_empty() => {
+                self.key = Some(tag_value(key));
+                Ok(())
+            }
+            _ => Err(FromJsonError::InvalidKey),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), FromJsonError> {
+        let key = self.key.take().unwrap_or_default();
+        value.serialize(self.child(&key))
+    }
+
+    fn end(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = FromJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), FromJsonError> {
+        value.serialize(self.child(key))
+    }
+
+    fn end(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = FromJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), FromJsonError> {
+        value.serialize(self.child(key))
+    }
+
+    fn end(self) -> Result<(), FromJsonError> {
+        Ok(())
+    }
+}
+
+/// Serializes a scalar value into a `Field`.
+struct FieldSerializer;
+
+impl ser::Serializer for FieldSerializer {
+    type Ok = Field;
+    type Error = FieldValueError;
+    type SerializeSeq = ser::Impossible<Field, FieldValueError>;
+    type SerializeTuple = ser::Impossible<Field, FieldValueError>;
+    type SerializeTupleStruct = ser::Impossible<Field, FieldValueError>;
+    type SerializeTupleVariant = ser::Impossible<Field, FieldValueError>;
+    type SerializeMap = ser::Impossible<Field, FieldValueError>;
+    type SerializeStruct = ser::Impossible<Field, FieldValueError>;
+    type SerializeStructVariant = ser::Impossible<Field, FieldValueError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Field, FieldValueError> {
+        Ok(Field::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Field, FieldValueError> {
+        Ok(Field::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Field, FieldValueError> {
+        Ok(Field::UInteger(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Field, FieldValueError> {
+        Ok(Field::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Field, FieldValueError> {
+        Ok(Field::from(v))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Field, FieldValueError> {
+        Err(ser::Error::custom("bytes can't be stored in a field"))
+    }
+
+    fn serialize_none(self) -> Result<Field, FieldValueError> {
+        Err(FieldValueError::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Field, FieldValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Field, FieldValueError> {
+        Err(FieldValueError::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Field, FieldValueError> {
+        Err(FieldValueError::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Field, FieldValueError> {
+        Ok(Field::from(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Field, FieldValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Field, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, FieldValueError> {
+        Err(FieldValueError::Nested)
+    }
+}
+
+type Entries = Vec<(String, Result<Field, FieldValueError>)>;
+
+/// Serializes the entries of a flat object, without failing on values that aren't scalars.
+struct EntriesSerializer;
+
+impl ser::Serializer for EntriesSerializer {
+    type Ok = Entries;
+    type Error = FromJsonError;
+    type SerializeSeq = ser::Impossible<Entries, FromJsonError>;
+    type SerializeTuple = ser::Impossible<Entries, FromJsonError>;
+    type SerializeTupleStruct = ser::Impossible<Entries, FromJsonError>;
+    type SerializeTupleVariant = ser::Impossible<Entries, FromJsonError>;
+    type SerializeMap = EntriesCompound;
+    type SerializeStruct = EntriesCompound;
+    type SerializeStructVariant = ser::Impossible<Entries, FromJsonError>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_none(self) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Entries, FromJsonError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+    ) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Entries, FromJsonError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Entries, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<EntriesCompound, FromJsonError> {
+        Ok(EntriesCompound::default())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<EntriesCompound, FromJsonError> {
+        Ok(EntriesCompound::default())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, FromJsonError> {
+        Err(FromJsonError::NotAnObject)
+    }
+}
+
+#[derive(Default)]
+struct EntriesCompound {
+    entries: Entries,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for EntriesCompound {
+    type Ok = Entries;
+    type Error = FromJsonError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), FromJsonError> {
+        let key = key
+            .serialize(FieldSerializer)
+            .map_err(|_| FromJsonError::InvalidKey)?;
+        self.key = Some(tag_value(key));
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), FromJsonError> {
+        let key = self.key.take().unwrap_or_default();
+        self.entries.push((key, value.serialize(FieldSerializer)));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Entries, FromJsonError> {
+        Ok(self.entries)
+    }
+}
+
+impl ser::SerializeStruct for EntriesCompound {
+    type Ok = Entries;
+    type Error = FromJsonError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), FromJsonError> {
+        self.entries
+            .push((key.to_string(), value.serialize(FieldSerializer)));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Entries, FromJsonError> {
+        Ok(self.entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Webhook {
+        site: String,
+        ts: u64,
+        status: Status,
+        values: Vec<f64>,
+        extra: BTreeMap<u8, Option<bool>>,
+    }
+
+    #[derive(Serialize)]
+    enum Status {
+        Online,
+    }
+
+    #[test]
+    fn flatten() {
+        let document = Webhook {
+            site: "a b".to_string(),
+            ts: 1622493622000,
+            status: Status::Online,
+            values: vec![1.0, 2.5],
+            extra: vec![(1, Some(true)), (2, None)].into_iter().collect(),
+        };
+        let options = FromJsonOptions::default()
+            .tags(vec!["site", "status"])
+            .timestamp("ts", Precision::Milliseconds);
+
+        let m = Measurement::from_json("hook", &document, &options).unwrap();
+        assert_eq!(m.tag("site"), Some("a b"));
+        assert_eq!(m.tag("status"), Some("Online"));
+        assert_eq!(m.field("values.1"), Some(&Field::Float(2.5)));
+        assert_eq!(m.field("extra.1"), Some(&Field::Bool(true)));
+        assert_eq!(m.field("extra.2"), None);
+        assert_eq!(m.timestamp(), Some(1622493622000000000));
+        assert_eq!(m.precision(), Precision::Milliseconds);
+    }
+
+    #[derive(Serialize)]
+    struct Host {
+        host: &'static str,
+        region: &'static str,
+        load: f64,
+        missing: Option<i32>,
+    }
+
+    #[test]
+    fn classify() {
+        let mut document = Host {
+            host: "web-1",
+            region: "eu",
+            load: 0.5,
+            missing: Some(1),
+        };
+        let classifier = FieldClassifier::new().tags(vec!["host", "load"]);
+
+        let m = classifier
+            .classify(Measurement::builder("m"), &document)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(m.tag("host"), Some("web-1"));
+        assert_eq!(m.field("region"), Some(&Field::from("eu")));
+        assert_eq!(m.field("load"), Some(&Field::Float(0.5)));
+
+        document.missing = None;
+        assert!(matches!(
+            classifier.classify(Measurement::builder("m"), &document),
+            Err(FromJsonError::InvalidValue(key, FieldValueError::Null)) if key == "missing"
+        ));
+        assert!(matches!(
+            classifier.classify(Measurement::builder("m"), &"not an object"),
+            Err(FromJsonError::NotAnObject)
+        ));
+    }
+
+    #[test]
+    fn invalid_documents() {
+        let options = FromJsonOptions::default();
+        assert!(matches!(
+            Measurement::from_json("m", &1, &options),
+            Err(FromJsonError::NotAnObject)
+        ));
+        assert!(matches!(
+            Measurement::from_json("m", &vec![1], &options),
+            Err(FromJsonError::NotAnObject)
+        ));
+        let empty: BTreeMap<String, ()> = vec![("a".to_string(), ())].into_iter().collect();
+        assert!(matches!(
+            Measurement::from_json("m", &empty, &options),
+            Err(FromJsonError::Build(MeasurementBuilderError::EmptyFields))
+        ));
+        let nested_key: BTreeMap<Vec<u8>, i32> = vec![(vec![1, 2], 1)].into_iter().collect();
+        assert!(matches!(
+            Measurement::from_json("m", &nested_key, &options),
+            Err(FromJsonError::InvalidKey)
+        ));
+        let options = options.timestamp("ts", Precision::Seconds);
+        let timestamp: BTreeMap<&str, &str> = vec![("ts", "now")].into_iter().collect();
+        assert!(matches!(
+            Measurement::from_json("m", &timestamp, &options),
+            Err(FromJsonError::InvalidTimestamp(path)) if path == "ts"
+        ));
+        let timestamp: BTreeMap<&str, i128> = vec![("ts", i128::MAX / 10)].into_iter().collect();
+        assert!(matches!(
+            Measurement::from_json("m", &timestamp, &options),
+            Err(FromJsonError::TimestampOutOfRange(path)) if path == "ts"
+        ));
+        let timestamp: BTreeMap<&str, u128> = vec![("ts", u128::MAX)].into_iter().collect();
+        assert!(matches!(
+            Measurement::from_json("m", &timestamp, &options),
+            Err(FromJsonError::TimestampOutOfRange(path)) if path == "ts"
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+//! Geographic points in the layout of Flux's `experimental/geo` package, see
+//! `MeasurementBuilder::geo`.
+
+use crate::MeasurementBuilder;
+
+/// The deepest S2 cell level, at which cells are about a square centimeter.
+pub const MAX_S2_LEVEL: u8 = 30;
+
+/// Position in the Hilbert curve of a cell's child, by orientation and `(i, j)` bits.
+const IJ_TO_POS: [[u64; 4]; 4] = [[0, 1, 3, 2], [0, 3, 1, 2], [2, 3, 1, 0], [2, 1, 3, 0]];
+/// Orientation change of a child, by its position in the Hilbert curve.
+const POS_TO_ORIENTATION: [usize; 4] = [1, 0, 0, 3];
+
+impl MeasurementBuilder {
+    /// Add a point as the `lat` and `lon` float fields, in degrees, as expected by the functions
+    /// of Flux's `experimental/geo` package.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// let measurement = Measurement::builder("position")
+    ///     .geo(59.3293, 18.0686)
+    ///     .timestamp_s(1622493622)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(measurement.field("lat"), Some(&Field::Float(59.3293)));
+    /// ```
+    pub fn geo(self, lat: f64, lon: f64) -> Self {
+        self.field("lat", lat).field("lon", lon)
+    }
+
+    /// Like `geo`, and also add the `s2_cell_id` tag with the token of the S2 cell at `level`
+    /// (capped at `MAX_S2_LEVEL`) containing the point, which the `experimental/geo` package
+    /// uses to filter by region efficiently.
+    ///
+    /// The level should be the same for all points of a measurement, e.g. 11 (cells of about
+    /// 20 km²) for vehicles.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// let measurement = Measurement::builder("position")
+    ///     .geo_with_s2_cell(40.7128, -74.006, 11)
+    ///     .timestamp_s(1622493622)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(measurement.tag("s2_cell_id"), Some("89c25a4"));
+    /// ```
+    pub fn geo_with_s2_cell(self, lat: f64, lon: f64, level: u8) -> Self {
+        self.geo(lat, lon)
+            .tag("s2_cell_id", s2_cell_token(lat, lon, level))
+    }
+}
+
+/// The token of the S2 cell at `level` containing a point: the hex representation of its cell
+/// id, without trailing zeros.
+fn s2_cell_token(lat: f64, lon: f64, level: u8) -> String {
+    let id = s2_cell_id(lat, lon, level.min(MAX_S2_LEVEL));
+    let token = format!("{:016x}", id);
+    token.trim_end_matches('0').to_string()
+}
+
+/// The id of the S2 cell at `level` containing a point, following the reference
+/// implementation: project the point onto a face of the unit cube, and find its position on
+/// the Hilbert curve of that face.
+fn s2_cell_id(lat: f64, lon: f64, level: u8) -> u64 {
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    let (x, y, z) = (lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin());
+
+    let axis = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+        0
+    } else if y.abs() >= z.abs() {
+        1
+    } else {
+        2
+    };
+    let negative = [x, y, z][axis] < 0.0;
+    let face = axis + if negative { 3 } else { 0 };
+    let (u, v) = match face {
+        0 => (y / x, z / x),
+        1 => (-x / y, z / y),
+        2 => (-x / z, -y / z),
+        3 => (z / x, y / x),
+        4 => (z / y, -x / y),
+        _ => (-y / z, -x / z),
+    };
+
+    let (i, j) = (st_to_ij(uv_to_st(u)), st_to_ij(uv_to_st(v)));
+    let mut orientation = face & 1;
+    let mut position = face as u64;
+    for k in (0..u64::from(MAX_S2_LEVEL)).rev() {
+        let ij = (((i >> k) & 1) << 1 | ((j >> k) & 1)) as usize;
+        let pos = IJ_TO_POS[orientation][ij];
+        position = position << 2 | pos;
+        orientation ^= POS_TO_ORIENTATION[pos as usize];
+    }
+    let leaf = position << 1 | 1;
+
+    let lsb = 1_u64 << (2 * u64::from(MAX_S2_LEVEL - level));
+    (leaf & lsb.wrapping_neg()) | lsb
+}
+
+/// The quadratic projection used by S2, making cells more uniform in size.
+fn uv_to_st(u: f64) -> f64 {
+    if u >= 0.0 {
+        0.5 * (1.0 + 3.0 * u).sqrt()
+    } else {
+        1.0 - 0.5 * (1.0 - 3.0 * u).sqrt()
+    }
+}
+
+fn st_to_ij(s: f64) -> u64 {
+    let max = 1_u64 << MAX_S2_LEVEL;
+    ((max as f64 * s).floor().max(0.0) as u64).min(max - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn s2_cells() {
+        assert_eq!(s2_cell_id(0.0, 0.0, 0), 0x1000_0000_0000_0000);
+        assert_eq!(s2_cell_id(0.0, 0.0, MAX_S2_LEVEL), 0x1000_0000_0000_0001);
+        assert_eq!(s2_cell_token(90.0, 0.0, 0), "5");
+        assert!(s2_cell_token(37.7749, -122.4194, 13).starts_with("8085"));
+        assert_eq!(s2_cell_token(40.7128, -74.006, 11), "89c25a4");
+        let leaf = s2_cell_id(40.7128, -74.006, MAX_S2_LEVEL);
+        let cell = s2_cell_id(40.7128, -74.006, 11);
+        let lsb = cell & cell.wrapping_neg();
+        assert_eq!(
+            leaf & !(2 * lsb - 1),
+            cell & !(2 * lsb - 1),
+            "leaf is inside its cell"
+        );
+        assert_eq!(
+            s2_cell_token(1.0, 1.0, 40),
+            s2_cell_token(1.0, 1.0, MAX_S2_LEVEL)
+        );
+    }
+}
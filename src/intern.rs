@@ -0,0 +1,118 @@
+//! Sharing repeated tag values between measurements, see `TagPool`.
+
+use crate::{Key, MeasurementBuilder, TagValue};
+use std::{collections::HashSet, sync::Arc};
+
+/// A pool of tag values, so that measurements with the same tag values (such as host names or
+/// device ids) share one allocation per value instead of one per measurement.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::*;
+/// let mut pool = TagPool::new();
+/// let measurements: Vec<_> = (0..1000)
+///     .map(|i| {
+///         Measurement::builder("cpu")
+///             .pooled_tag("host", &format!("host{}", i % 10), &mut pool)
+///             .field("usage", 0.5)
+///             .build()
+///             .unwrap()
+///     })
+///     .collect();
+/// assert_eq!(pool.len(), 10);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TagPool {
+    values: HashSet<Arc<str>>,
+}
+
+impl TagPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shared allocation of `value`, adding it to the pool if it isn't there yet.
+    pub fn intern(&mut self, value: &str) -> Arc<str> {
+        if let Some(existing) = self.values.get(value) {
+            return Arc::clone(existing);
+        }
+        let value: Arc<str> = Arc::from(value);
+        self.values.insert(Arc::clone(&value));
+        value
+    }
+
+    /// The number of distinct values in the pool.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Remove the values that are not used by any measurement anymore.
+    pub fn shrink(&mut self) {
+        self.values.retain(|value| Arc::strong_count(value) > 1);
+    }
+}
+
+impl MeasurementBuilder {
+    /// Add a tag whose value is shared with other measurements, e.g. one from
+    /// `TagPool::intern`.
+    pub fn shared_tag(mut self, name: impl Into<Key>, value: Arc<str>) -> Self {
+        self.tags.push((name.into(), TagValue::Shared(value)));
+        self
+    }
+
+    /// Add a tag with its value taken from `pool`, see `TagPool`.
+    pub fn pooled_tag(self, name: impl Into<Key>, value: &str, pool: &mut TagPool) -> Self {
+        let value = pool.intern(value);
+        self.shared_tag(name, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Measurement;
+
+    #[test]
+    fn shared_values() {
+        let mut pool = TagPool::new();
+        let build = |pool: &mut TagPool| {
+            Measurement::builder("m")
+                .pooled_tag("host", "a", pool)
+                .field("f", 1)
+                .timestamp_s(1622493622)
+                .build()
+                .unwrap()
+        };
+        let first = build(&mut pool);
+        let second = build(&mut pool);
+        match (&first.tags["host"], &second.tags["host"]) {
+            (TagValue::Shared(a), TagValue::Shared(b)) => assert!(Arc::ptr_eq(a, b)),
+            other => panic!("expected shared values, got {:?}", other),
+        }
+        assert_eq!(
+            first,
+            Measurement::builder("m")
+                .tag("host", "a")
+                .field("f", 1)
+                .timestamp_s(1622493622)
+                .build()
+                .unwrap()
+        );
+        assert_eq!(
+            first.to_line_protocol(),
+            "m,host=a f=1i 1622493622000000000"
+        );
+
+        pool.intern("unused");
+        assert_eq!(pool.len(), 2);
+        pool.shrink();
+        assert_eq!(pool.len(), 1);
+        drop((first, second));
+        pool.shrink();
+        assert!(pool.is_empty());
+    }
+}
@@ -1,36 +1,165 @@
 use std::{
-    collections::HashMap,
-    convert::TryInto,
+    borrow::Cow,
+    collections::BTreeMap,
+    convert::{TryFrom, TryInto},
     error::Error,
-    fmt::Display,
+    fmt::{Display, Write},
+    net::IpAddr,
     num::TryFromIntError,
-    time::{SystemTime, SystemTimeError},
+    sync::Arc,
+    time::{Duration, SystemTime, SystemTimeError},
 };
 
 #[cfg(feature = "client")]
 mod client;
+// Parsing is only needed by the client.
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
+mod datetime;
+pub mod flux;
+#[cfg(feature = "serde")]
+mod from_json;
+pub mod geo;
+mod intern;
+mod line_protocol;
+pub mod lint;
+#[cfg(feature = "preserve_order")]
+mod ordered_map;
+mod template;
 #[cfg(feature = "client")]
 pub use client::{
-    query::Query, InfluxClient, InfluxClientBuilder, InfluxClientBuilderError, InfluxError,
+    analyze::FluxError,
+    dialect::{Annotation, DateTimeFormat, Profiler, QueryOptions},
+    downsample::{Aggregate, Downsample},
+    export::ExportFormat,
+    influxql::Select,
+    json::JsonError,
+    names::{BucketName, InvalidNameError, OrgName, Token},
+    prepared::PreparedQuery,
+    query::{Query, QueryLine, RangeBound},
+    records::{pivot_records, RecordsExt},
+    schema::{MeasurementSchema, WriteSchema},
+    series::{SeriesFilter, SeriesId, SeriesKey},
+    spill::SpilledRows,
+    stats::{MeasurementStats, WriteStats},
+    suggestions::FluxSuggestion,
+    tasks::{TaskAlert, TaskRun},
+    typed::{FluxColumn, FluxRecord, FluxTable, Value},
+    v1::{InfluxQlSeries, RetentionPolicy},
+    InfluxClient, InfluxClientBuilder, InfluxClientBuilderError, InfluxError, ProfiledResponse,
+    QueryEstimate, QueryOutcome, TimeSkewHook, TimeSkewPolicy,
 };
+#[cfg(feature = "serde")]
+pub use from_json::{FieldClassifier, FieldValueError, FromJsonError, FromJsonOptions};
+pub use intern::TagPool;
+pub use line_protocol::{
+    ControlCharacterError, ControlCharacters, FloatFormat, LineProtocolError, LineProtocolOptions,
+};
+pub use template::MeasurementTemplate;
 
-#[derive(Debug, Clone, PartialEq)]
-struct TagValue(String);
+/// A tag or field key, as accepted by `MeasurementBuilder` and `Measurement`.
+///
+/// Keys are usually string literals, which are borrowed instead of being allocated for every
+/// point. Keys only known at runtime can be given as a `String`.
+pub type Key = Cow<'static, str>;
+
+/// Storage for the fields of a `Measurement`, which keeps insertion order if the
+/// `preserve_order` feature is enabled.
+#[cfg(not(feature = "preserve_order"))]
+type FieldMap = std::collections::HashMap<Key, Field>;
+#[cfg(feature = "preserve_order")]
+type FieldMap = ordered_map::OrderedMap<Key, Field>;
+
+/// Storage for the tags and fields of a `MeasurementBuilder`, which is kept inline for up to 4
+/// tags and 8 fields if the `smallvec` feature is enabled.
+#[cfg(not(feature = "smallvec"))]
+type TagList = Vec<(Key, TagValue)>;
+#[cfg(not(feature = "smallvec"))]
+type FieldList = Vec<(Key, Field)>;
+#[cfg(feature = "smallvec")]
+type TagList = smallvec::SmallVec<[(Key, TagValue); 4]>;
+#[cfg(feature = "smallvec")]
+type FieldList = smallvec::SmallVec<[(Key, Field); 8]>;
+
+/// The value of a tag, stored unescaped. Values from a `TagPool` share their allocation.
+#[derive(Debug, Clone)]
+enum TagValue {
+    Owned(String),
+    Shared(Arc<str>),
+}
 
 impl TagValue {
     fn new(s: String) -> Self {
-        let s = s
-            .replace(',', "\\,")
-            .replace('=', "\\=")
-            .replace(' ', "\\ ");
-        Self(s)
+        Self::Owned(s)
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            TagValue::Owned(s) => s,
+            TagValue::Shared(s) => s,
+        }
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            TagValue::Owned(s) => s,
+            TagValue::Shared(s) => s.to_string(),
+        }
+    }
+}
+
+impl PartialEq for TagValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TagValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TagValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(TagValue::Owned)
     }
 }
 
+/// Characters escaped in measurement names.
+const NAME_SPECIAL: &[char] = &[',', ' '];
+/// Characters escaped in tag keys, tag values and field keys.
+const KEY_SPECIAL: &[char] = &[',', '=', ' '];
+/// Characters escaped in string field values.
+pub(crate) const STRING_SPECIAL: &[char] = &['"', '\\'];
+
+/// Write `s` with `special` characters escaped with a backslash, for use in line protocol.
+pub(crate) fn write_escaped<W: std::fmt::Write>(
+    out: &mut W,
+    s: &str,
+    special: &[char],
+) -> std::fmt::Result {
+    if !s.contains(special) {
+        return out.write_str(s);
+    }
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.write_char('\\')?;
+        }
+        out.write_char(c)?;
+    }
+    Ok(())
+}
+
 /// Represents various supported field values.
 ///
 /// Fields can be floats, strings, bools, signed and unsigned integers.
+///
+/// With the `serde` feature, fields serialize as externally tagged enums (e.g.
+/// `{"Integer": 1}`), so that their type survives a round trip.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Field {
     /// A float field
     Float(f64),
@@ -44,14 +173,151 @@ pub enum Field {
     UInteger(u128),
 }
 
+impl Field {
+    /// The value as a float, if it is numeric. Integers are converted, possibly losing
+    /// precision.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Field::Float(v) => Some(*v),
+            Field::Integer(v) => Some(*v as f64),
+            Field::UInteger(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    /// The value as an `i64`, if it is an integer that fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Field::Integer(v) => (*v).try_into().ok(),
+            Field::UInteger(v) => (*v).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a `u64`, if it is an integer that fits.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Field::Integer(v) => (*v).try_into().ok(),
+            Field::UInteger(v) => (*v).try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a bool, if it is a bool.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Field::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The value as a string slice, if it is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Field::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The name of the type of this field, as used by InfluxDB.
+    pub fn data_type(&self) -> &'static str {
+        match self {
+            Field::Float(_) => "float",
+            Field::String(_) => "string",
+            Field::Bool(_) => "boolean",
+            Field::Integer(_) => "integer",
+            Field::UInteger(_) => "unsigned",
+        }
+    }
+}
+
 impl Display for Field {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Field::Float(v) => write!(f, "{}", v),
-            Field::String(v) => write!(f, r#""{}""#, v),
+            Field::Float(v) => f.write_str(format_float(&mut ryu::Buffer::new(), *v)),
+            Field::String(v) => {
+                f.write_char('"')?;
+                write_escaped(f, v, STRING_SPECIAL)?;
+                f.write_char('"')
+            }
             Field::Bool(v) => write!(f, "{}", v),
-            Field::Integer(v) => write!(f, "{}i", v),
-            Field::UInteger(v) => write!(f, "{}u", v),
+            Field::Integer(v) => {
+                f.write_str(itoa::Buffer::new().format(*v))?;
+                f.write_char('i')
+            }
+            Field::UInteger(v) => {
+                f.write_str(itoa::Buffer::new().format(*v))?;
+                f.write_char('u')
+            }
+        }
+    }
+}
+
+/// Format a float in its shortest representation that parses back to the same value, without a
+/// trailing `.0` for whole numbers.
+fn format_float(buffer: &mut ryu::Buffer, v: f64) -> &str {
+    let formatted = buffer.format(v);
+    formatted.strip_suffix(".0").unwrap_or(formatted)
+}
+
+/// The error returned when converting a `Field` into a type it doesn't hold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldTypeError {
+    field: Field,
+    expected: &'static str,
+}
+
+impl FieldTypeError {
+    /// The field that couldn't be converted.
+    pub fn into_field(self) -> Field {
+        self.field
+    }
+}
+
+impl Display for FieldTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "expected a field convertible to {}, got {} field",
+            self.expected,
+            self.field.data_type()
+        )
+    }
+}
+
+impl Error for FieldTypeError {}
+
+macro_rules! impl_try_from_field {
+    ($to_type:ty, $expected:literal, $convert:expr) => {
+        impl TryFrom<Field> for $to_type {
+            type Error = FieldTypeError;
+
+            fn try_from(field: Field) -> Result<Self, Self::Error> {
+                let convert: fn(&Field) -> Option<$to_type> = $convert;
+                convert(&field).ok_or(FieldTypeError {
+                    field,
+                    expected: $expected,
+                })
+            }
+        }
+    };
+}
+
+impl_try_from_field!(f64, "f64", Field::as_f64);
+impl_try_from_field!(i64, "i64", Field::as_i64);
+impl_try_from_field!(u64, "u64", Field::as_u64);
+impl_try_from_field!(bool, "bool", Field::as_bool);
+
+impl TryFrom<Field> for String {
+    type Error = FieldTypeError;
+
+    fn try_from(field: Field) -> Result<Self, Self::Error> {
+        match field {
+            Field::String(v) => Ok(v),
+            field => Err(FieldTypeError {
+                field,
+                expected: "String",
+            }),
         }
     }
 }
@@ -119,6 +385,96 @@ impl From<&str> for Field {
     }
 }
 
+impl From<&String> for Field {
+    fn from(v: &String) -> Self {
+        Field::String(v.clone())
+    }
+}
+
+impl From<Cow<'_, str>> for Field {
+    fn from(v: Cow<'_, str>) -> Self {
+        Field::String(v.into_owned())
+    }
+}
+
+impl From<char> for Field {
+    fn from(v: char) -> Self {
+        Field::String(v.to_string())
+    }
+}
+
+/// IP addresses are stored as strings.
+impl From<IpAddr> for Field {
+    fn from(v: IpAddr) -> Self {
+        Field::String(v.to_string())
+    }
+}
+
+/// Durations are stored as integers, in nanoseconds.
+impl From<Duration> for Field {
+    fn from(v: Duration) -> Self {
+        Field::Integer(v.as_nanos() as i128)
+    }
+}
+
+/// The precision of a timestamp, which is also the precision it is written to InfluxDB with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Precision {
+    /// Seconds since the Unix epoch
+    Seconds,
+    /// Milliseconds since the Unix epoch
+    Milliseconds,
+    /// Microseconds since the Unix epoch
+    Microseconds,
+    /// Nanoseconds since the Unix epoch
+    Nanoseconds,
+}
+
+impl Precision {
+    /// The value of the `precision` parameter of the InfluxDB write API for this precision.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Precision::Seconds => "s",
+            Precision::Milliseconds => "ms",
+            Precision::Microseconds => "us",
+            Precision::Nanoseconds => "ns",
+        }
+    }
+
+    /// The number of nanoseconds in one unit of this precision.
+    pub fn nanos_per_unit(&self) -> i128 {
+        match self {
+            Precision::Seconds => 1_000_000_000,
+            Precision::Milliseconds => 1_000_000,
+            Precision::Microseconds => 1_000,
+            Precision::Nanoseconds => 1,
+        }
+    }
+}
+
+impl Display for Precision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Types that can be used as the timestamp of a `Measurement`, see `MeasurementBuilder::timestamp`.
+pub trait IntoTimestamp {
+    /// The timestamp as nanoseconds since the Unix epoch, and the precision it was given with.
+    fn into_timestamp(self) -> (i128, Precision);
+}
+
+impl IntoTimestamp for SystemTime {
+    fn into_timestamp(self) -> (i128, Precision) {
+        let nanos = match self.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since) => since.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128),
+        };
+        (nanos, Precision::Nanoseconds)
+    }
+}
+
 /// Represents a point of measurement in Influx
 ///
 /// ## Example
@@ -134,28 +490,42 @@ impl From<&str> for Field {
 ///     .build()
 ///     .unwrap(); // building can fail if no fields are specified
 /// ```
+///
+/// Timestamps are signed, so points from before the Unix epoch can be created by passing negative
+/// timestamps to the builder. A measurement built with `MeasurementBuilder::server_timestamp`
+/// has no timestamp at all, and is assigned the time it is received by the server.
+///
+/// A `Measurement` displays as its line protocol, and with the `serde` feature it can be
+/// serialized and deserialized, e.g. to persist it in a queue before writing it.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Measurement {
     /// Name of measurement
+    #[cfg_attr(feature = "serde", serde(rename = "name"))]
     measurement_name: String,
-    /// Timestamp of measurement as a Unix Epoch (nanoseconds)
-    timestamp_nanos: i128,
-    /// Tags of measurement
-    tags: HashMap<String, TagValue>,
+    /// Timestamp of measurement as a Unix Epoch (nanoseconds), `None` if the server assigns it
+    #[cfg_attr(feature = "serde", serde(rename = "timestamp"))]
+    timestamp_nanos: Option<i128>,
+    /// Precision the timestamp was given with, and is written with
+    precision: Precision,
+    /// Tags of measurement, sorted by key as recommended by InfluxDB
+    tags: BTreeMap<Key, TagValue>,
     /// Fields of measurement
-    fields: HashMap<String, Field>,
+    fields: FieldMap,
 }
 
 impl Measurement {
     fn new(
         measurement_name: String,
-        timestamp_nanos: i128,
-        tags: HashMap<String, TagValue>,
-        fields: HashMap<String, Field>,
+        timestamp_nanos: Option<i128>,
+        precision: Precision,
+        tags: BTreeMap<Key, TagValue>,
+        fields: FieldMap,
     ) -> Self {
         Self {
             measurement_name,
             timestamp_nanos,
+            precision,
             tags,
             fields,
         }
@@ -165,124 +535,793 @@ impl Measurement {
         MeasurementBuilder::new(measurement_name)
     }
 
-    /// Add a field to the measurement.
-    pub fn add_field(&mut self, name: impl Into<String>, value: impl Into<Field>) {
-        self.fields.insert(name.into(), value.into());
+    /// The name of the measurement.
+    pub fn name(&self) -> &str {
+        &self.measurement_name
     }
 
-    /// Add a tag to the measurement.
-    pub fn add_tag(&mut self, name: impl Into<String>, value: impl Into<String>) {
-        self.tags.insert(name.into(), TagValue::new(value.into()));
+    /// The timestamp of the measurement, in nanoseconds since the Unix epoch, or `None` if it is
+    /// assigned by the server.
+    pub fn timestamp(&self) -> Option<i128> {
+        self.timestamp_nanos
     }
 
-    fn measurement_part(&self) -> &str {
-        &self.measurement_name
-    }
-
-    fn tags_part(&self) -> String {
+    /// The tags of the measurement and their (unescaped) values, sorted by key.
+    pub fn tags(&self) -> impl Iterator<Item = (&str, &str)> {
         self.tags
             .iter()
-            .map(|(name, value)| format!("{}={}", name, value.0))
-            .collect::<Vec<_>>()
-            .join(",")
+            .map(|(name, value)| (name.as_ref(), value.as_str()))
     }
 
-    fn fields_part(&self) -> String {
+    /// The fields of the measurement.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Field)> {
         self.fields
             .iter()
-            .map(|(name, value)| format!("{}={}", name, value))
-            .collect::<Vec<_>>()
-            .join(",")
+            .map(|(name, value)| (name.as_ref(), value))
+    }
+
+    /// The (unescaped) value of the tag with the given name.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.get(name).map(|value| value.as_str())
+    }
+
+    /// The value of the field with the given name.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.get(name)
+    }
+
+    /// Remove a tag from the measurement, returning its value if it was present.
+    pub fn remove_tag(&mut self, name: &str) -> Option<String> {
+        self.tags.remove(name).map(TagValue::into_string)
+    }
+
+    /// Remove a field from the measurement, returning its value if it was present.
+    ///
+    /// Note that InfluxDB rejects measurements without fields.
+    pub fn remove_field(&mut self, name: &str) -> Option<Field> {
+        self.fields.remove(name)
+    }
+
+    /// Add a field to the measurement.
+    pub fn add_field(&mut self, name: impl Into<Key>, value: impl Into<Field>) {
+        self.fields.insert(name.into(), value.into());
+    }
+
+    /// Add a tag to the measurement. Tags with an empty key or value are dropped, as with
+    /// `EmptyTagPolicy::Drop`, since InfluxDB rejects them.
+    pub fn add_tag(&mut self, name: impl Into<Key>, value: impl Into<String>) {
+        let (name, value) = (name.into(), value.into());
+        if name.is_empty() || value.is_empty() {
+            return;
+        }
+        self.tags.insert(name, TagValue::new(value));
+    }
+
+    /// Add multiple fields to the measurement.
+    pub fn add_fields<K, F>(&mut self, fields: impl IntoIterator<Item = (K, F)>)
+    where
+        K: Into<Key>,
+        F: Into<Field>,
+    {
+        for (name, value) in fields {
+            self.add_field(name, value);
+        }
+    }
+
+    /// Add multiple tags to the measurement, dropping those with an empty key or value like
+    /// `add_tag`.
+    pub fn add_tags<K, V>(&mut self, tags: impl IntoIterator<Item = (K, V)>)
+    where
+        K: Into<Key>,
+        V: Into<String>,
+    {
+        for (name, value) in tags {
+            self.add_tag(name, value);
+        }
+    }
+
+    /// The precision the timestamp of this measurement was given with, e.g.
+    /// `Precision::Milliseconds` if it was built with `timestamp_ms`.
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Merge the tags and fields of `other` into this measurement, for points that are built
+    /// incrementally from several sources. Tags and fields present in both are resolved with
+    /// `policy`.
+    ///
+    /// Fails with `MergeError::NameMismatch` or `MergeError::TimestampMismatch` if the
+    /// measurements don't describe the same point, and with `MergeError::Conflict` for a key with
+    /// different values under `MergePolicy::Reject`, leaving this measurement unchanged.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// let build = |field: &'static str, value: f64| {
+    ///     Measurement::builder("sensor")
+    ///         .field(field, value)
+    ///         .timestamp_s(1622493622)
+    ///         .build()
+    ///         .unwrap()
+    /// };
+    /// let mut measurement = build("temperature", 21.5);
+    /// measurement
+    ///     .merge(build("humidity", 0.4), MergePolicy::Reject)
+    ///     .unwrap();
+    /// assert_eq!(measurement.fields().count(), 2);
+    /// ```
+    pub fn merge(&mut self, other: Measurement, policy: MergePolicy) -> Result<(), MergeError> {
+        if self.measurement_name != other.measurement_name {
+            return Err(MergeError::NameMismatch(other.measurement_name));
+        }
+        if self.timestamp_nanos != other.timestamp_nanos {
+            return Err(MergeError::TimestampMismatch(
+                self.timestamp_nanos,
+                other.timestamp_nanos,
+            ));
+        }
+        if policy == MergePolicy::Reject {
+            let tag_conflict = other
+                .tags
+                .iter()
+                .find(|(name, value)| self.tags.get(*name).is_some_and(|v| v != *value));
+            if let Some((name, _)) = tag_conflict {
+                return Err(MergeError::Conflict(name.to_string()));
+            }
+            let field_conflict = other
+                .fields
+                .iter()
+                .find(|(name, value)| self.fields.get(name.as_ref()).is_some_and(|v| v != *value));
+            if let Some((name, _)) = field_conflict {
+                return Err(MergeError::Conflict(name.to_string()));
+            }
+        }
+
+        let keep_existing = policy == MergePolicy::KeepExisting;
+        for (name, value) in other.tags {
+            if !(keep_existing && self.tags.contains_key(&name)) {
+                self.tags.insert(name, value);
+            }
+        }
+        for (name, value) in other.fields {
+            if !(keep_existing && self.fields.contains_key(name.as_ref())) {
+                self.fields.insert(name, value);
+            }
+        }
+        if other.precision.nanos_per_unit() < self.precision.nanos_per_unit() {
+            self.precision = other.precision;
+        }
+        Ok(())
+    }
+
+    /// Truncate the timestamp to a whole number of `precision` units (rounding towards the past),
+    /// e.g. to deduplicate points within the same second, or to match the resolution of a
+    /// downsampled bucket.
+    ///
+    /// If `precision` is coarser than the precision of the measurement, the measurement is
+    /// written with `precision` from then on.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// let mut measurement = Measurement::builder("m")
+    ///     .field("field1", 1)
+    ///     .timestamp_ms(1622493622999)
+    ///     .build()
+    ///     .unwrap();
+    /// measurement.truncate_timestamp(Precision::Seconds);
+    /// assert_eq!(measurement.to_line_protocol(), "m field1=1i 1622493622000000000");
+    /// assert_eq!(measurement.precision(), Precision::Seconds);
+    /// ```
+    pub fn truncate_timestamp(&mut self, precision: Precision) {
+        let unit = precision.nanos_per_unit();
+        if let Some(timestamp_nanos) = &mut self.timestamp_nanos {
+            *timestamp_nanos = timestamp_nanos.div_euclid(unit) * unit;
+        }
+        if unit > self.precision.nanos_per_unit() {
+            self.precision = precision;
+        }
     }
 
-    /// Convert this `Measurement` to Influx line protocol.
+    /// Convert this `Measurement` to Influx line protocol, with the timestamp in nanoseconds.
     pub fn to_line_protocol(&self) -> String {
-        if self.tags.is_empty() {
-            format!(
-                "{} {} {}",
-                self.measurement_part(),
-                self.fields_part(),
-                self.timestamp_nanos
-            )
-        } else {
-            format!(
-                "{},{} {} {}",
-                self.measurement_part(),
-                self.tags_part(),
-                self.fields_part(),
-                self.timestamp_nanos
-            )
+        self.to_line_protocol_with_precision(Precision::Nanoseconds)
+    }
+
+    /// Convert this `Measurement` to Influx line protocol, with the timestamp in the given
+    /// precision. Timestamps more precise than `precision` are truncated.
+    ///
+    /// The result must be written with the same `precision` parameter.
+    pub fn to_line_protocol_with_precision(&self, precision: Precision) -> String {
+        let mut line = String::new();
+        self.write_line_protocol_with_precision(&mut line, precision);
+        line
+    }
+
+    /// Append the line protocol of this `Measurement`, with the timestamp in nanoseconds, to
+    /// `out`, without a trailing newline.
+    ///
+    /// Unlike `to_line_protocol`, this doesn't allocate when `out` has enough capacity, so a
+    /// buffer can be reused for many measurements.
+    pub fn write_line_protocol(&self, out: &mut String) {
+        self.write_line_protocol_with_precision(out, Precision::Nanoseconds)
+    }
+
+    /// Like `write_line_protocol`, but with the timestamp in the given precision, see
+    /// `to_line_protocol_with_precision`.
+    pub fn write_line_protocol_with_precision(&self, out: &mut String, precision: Precision) {
+        // Writing to a `String` never fails.
+        let _ = self.write_line(out, precision, "", &LineProtocolOptions::default());
+    }
+
+    /// Like `to_line_protocol_with_precision`, with the formatting controlled by `options`.
+    /// Control characters that `options` reject are escaped, see
+    /// `try_to_line_protocol_with_options` to fail instead.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// let measurement = Measurement::builder("m")
+    ///     .field("up", true)
+    ///     .timestamp_s(1622493622)
+    ///     .build()
+    ///     .unwrap();
+    /// let options = LineProtocolOptions::new().compact_booleans(true);
+    /// assert_eq!(
+    ///     measurement.to_line_protocol_with_options(Precision::Seconds, &options),
+    ///     "m up=t 1622493622"
+    /// );
+    /// ```
+    pub fn to_line_protocol_with_options(
+        &self,
+        precision: Precision,
+        options: &LineProtocolOptions,
+    ) -> String {
+        let mut line = String::new();
+        // Writing to a `String` never fails.
+        let _ = self.write_line(&mut line, precision, "", options);
+        line
+    }
+
+    /// Like `to_line_protocol_with_options`, but fails if a string field has control characters
+    /// that `options` reject, with `ControlCharacters::Reject`.
+    pub fn try_to_line_protocol_with_options(
+        &self,
+        precision: Precision,
+        options: &LineProtocolOptions,
+    ) -> Result<String, ControlCharacterError> {
+        let mut line = String::new();
+        self.write_prefixed_line_protocol(&mut line, precision, "", options)?;
+        Ok(line)
+    }
+
+    /// Like `write_line_protocol_with_precision`, with `prefix` prepended to the measurement name.
+    /// Fails if a string field has control characters that `options` reject.
+    pub(crate) fn write_prefixed_line_protocol(
+        &self,
+        out: &mut String,
+        precision: Precision,
+        prefix: &str,
+        options: &LineProtocolOptions,
+    ) -> Result<(), ControlCharacterError> {
+        if options.control_characters == ControlCharacters::Reject {
+            let rejected = self.fields().find(
+                |(_, value)| matches!(value, Field::String(s) if s.contains(char::is_control)),
+            );
+            if let Some((field, _)) = rejected {
+                return Err(ControlCharacterError::new(&self.measurement_name, field));
+            }
+        }
+        // Writing to a `String` never fails.
+        let _ = self.write_line(out, precision, prefix, options);
+        Ok(())
+    }
+
+    fn write_line<W: std::fmt::Write>(
+        &self,
+        out: &mut W,
+        precision: Precision,
+        prefix: &str,
+        options: &LineProtocolOptions,
+    ) -> std::fmt::Result {
+        write_escaped(out, prefix, NAME_SPECIAL)?;
+        write_escaped(out, &self.measurement_name, NAME_SPECIAL)?;
+        for (name, value) in &self.tags {
+            out.write_char(',')?;
+            write_escaped(out, name, KEY_SPECIAL)?;
+            out.write_char('=')?;
+            write_escaped(out, value.as_str(), KEY_SPECIAL)?;
         }
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            out.write_char(if i == 0 { ' ' } else { ',' })?;
+            write_escaped(out, name, KEY_SPECIAL)?;
+            out.write_char('=')?;
+            match value {
+                Field::Bool(v) if options.compact_booleans => {
+                    out.write_char(if *v { 't' } else { 'f' })?
+                }
+                Field::Float(v) => {
+                    let v = options.float_format_of(name).round(*v);
+                    out.write_str(format_float(&mut ryu::Buffer::new(), v))?
+                }
+                Field::String(v) => {
+                    out.write_char('"')?;
+                    options.control_characters.write_string(out, v)?;
+                    out.write_char('"')?
+                }
+                _ => write!(out, "{}", value)?,
+            }
+        }
+        if let Some(timestamp_nanos) = self.timestamp_nanos {
+            out.write_char(' ')?;
+            out.write_str(
+                itoa::Buffer::new().format(timestamp_nanos.div_euclid(precision.nanos_per_unit())),
+            )?;
+        }
+        Ok(())
     }
 }
 
+impl Display for Measurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_line(
+            f,
+            Precision::Nanoseconds,
+            "",
+            &LineProtocolOptions::default(),
+        )
+    }
+}
+
+/// Extending a `Measurement` adds fields to it.
+impl<K, F> Extend<(K, F)> for Measurement
+where
+    K: Into<Key>,
+    F: Into<Field>,
+{
+    fn extend<T: IntoIterator<Item = (K, F)>>(&mut self, iter: T) {
+        self.add_fields(iter);
+    }
+}
+
+/// What to do with tags and fields present in both measurements of `Measurement::merge`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Keep the value of the measurement that is merged into.
+    KeepExisting,
+    /// Use the value of the measurement that is merged in.
+    #[default]
+    Overwrite,
+    /// Fail with `MergeError::Conflict` if the values differ.
+    Reject,
+}
+
+/// The error returned when merging two measurements fails, see `Measurement::merge`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeError {
+    /// The measurement merged in has the contained, different name.
+    NameMismatch(String),
+    /// The timestamps of the measurements (in nanoseconds) differ.
+    TimestampMismatch(Option<i128>, Option<i128>),
+    /// The tag or field with the contained key has different values.
+    Conflict(String),
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::NameMismatch(name) => {
+                write!(f, "cannot merge a measurement named '{}'", name)
+            }
+            MergeError::TimestampMismatch(a, b) => {
+                write!(f, "cannot merge timestamps {:?} and {:?}", a, b)
+            }
+            MergeError::Conflict(key) => write!(f, "conflicting values for key '{}'", key),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+/// What to do with tags that have an empty key or value when building a `Measurement`.
+///
+/// InfluxDB rejects lines containing such tags, so by default building fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyTagPolicy {
+    /// Fail with `MeasurementBuilderError::EmptyTagKey` or `MeasurementBuilderError::EmptyTagValue`.
+    #[default]
+    Error,
+    /// Silently drop the tag from the measurement.
+    Drop,
+}
+
+/// What to do with measurement, tag and field names starting with `_`, which are reserved by
+/// InfluxDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReservedNamePolicy {
+    /// Don't check for reserved names.
+    #[default]
+    Allow,
+    /// Log a warning for each reserved name.
+    Warn,
+    /// Fail with `MeasurementBuilderError::ReservedName`.
+    Reject,
+}
+
+/// What to do with tag or field keys that are added to a `MeasurementBuilder` more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeyPolicy {
+    /// Keep the value that was added last.
+    #[default]
+    Overwrite,
+    /// Keep the value that was added last, and log a warning for each duplicate key.
+    Warn,
+    /// Fail with `MeasurementBuilderError::DuplicateKey`.
+    Reject,
+}
+
+#[derive(Debug, Clone)]
 pub struct MeasurementBuilder {
     name: String,
-    tags: Vec<(String, TagValue)>,
-    fields: Vec<(String, Field)>,
-    timestamp: Option<i128>,
+    tags: TagList,
+    fields: FieldList,
+    timestamp: Option<(i128, Precision)>,
+    server_timestamp: bool,
+    empty_tag_policy: EmptyTagPolicy,
+    reserved_name_policy: ReservedNamePolicy,
+    duplicate_key_policy: DuplicateKeyPolicy,
 }
 
 impl MeasurementBuilder {
     fn new(measurement_name: impl Into<String>) -> Self {
         MeasurementBuilder {
             name: measurement_name.into(),
-            tags: Vec::new(),
-            fields: Vec::new(),
+            tags: TagList::new(),
+            fields: FieldList::new(),
             timestamp: None,
+            server_timestamp: false,
+            empty_tag_policy: EmptyTagPolicy::default(),
+            reserved_name_policy: ReservedNamePolicy::default(),
+            duplicate_key_policy: DuplicateKeyPolicy::default(),
         }
     }
 
     /// Add a tag to the measurement.
-    pub fn tag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+    pub fn tag(mut self, name: impl Into<Key>, value: impl Into<String>) -> Self {
         self.tags.push((name.into(), TagValue::new(value.into())));
         self
     }
 
     /// Add a field to the measurement.
-    pub fn field(mut self, name: impl Into<String>, value: impl Into<Field>) -> Self {
+    pub fn field(mut self, name: impl Into<Key>, value: impl Into<Field>) -> Self {
         self.fields.push((name.into(), value.into()));
         self
     }
 
+    /// Add multiple tags to the measurement.
+    pub fn tags<K, V>(mut self, tags: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<Key>,
+        V: Into<String>,
+    {
+        self.tags.extend(
+            tags.into_iter()
+                .map(|(name, value)| (name.into(), TagValue::new(value.into()))),
+        );
+        self
+    }
+
+    /// Add multiple fields to the measurement.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// # use std::collections::HashMap;
+    /// let readings: HashMap<&str, f64> = vec![("temperature", 21.5)].into_iter().collect();
+    /// let measurement = Measurement::builder("sensor")
+    ///     .fields(readings)
+    ///     .timestamp_ms(1622493622000)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(measurement.to_line_protocol(), "sensor temperature=21.5 1622493622000000000");
+    /// ```
+    pub fn fields<K, F>(mut self, fields: impl IntoIterator<Item = (K, F)>) -> Self
+    where
+        K: Into<Key>,
+        F: Into<Field>,
+    {
+        self.fields.extend(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into())),
+        );
+        self
+    }
+
+    /// Add a tag to the measurement if `value` is `Some`.
+    pub fn tag_opt(self, name: impl Into<Key>, value: Option<impl Into<String>>) -> Self {
+        match value {
+            Some(value) => self.tag(name, value),
+            None => self,
+        }
+    }
+
+    /// Add a field to the measurement if `value` is `Some`.
+    pub fn field_opt(self, name: impl Into<Key>, value: Option<impl Into<Field>>) -> Self {
+        match value {
+            Some(value) => self.field(name, value),
+            None => self,
+        }
+    }
+
+    /// Set the timestamp of the measurement from a timestamp type, such as `SystemTime`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// # use std::time::{Duration, SystemTime};
+    /// let measurement = Measurement::builder("m1")
+    ///     .field("field1", 1)
+    ///     .timestamp(SystemTime::UNIX_EPOCH + Duration::from_millis(1622493622000))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(measurement.to_line_protocol(), "m1 field1=1i 1622493622000000000");
+    /// ```
+    pub fn timestamp(mut self, timestamp: impl IntoTimestamp) -> Self {
+        self.timestamp = Some(timestamp.into_timestamp());
+        self
+    }
+
     /// Set the timestamp of the measurement. Expects a unix timestamp in seconds.
     pub fn timestamp_s(mut self, timestamp_s: i128) -> Self {
-        self.timestamp = Some(timestamp_s * 1_000_000_000);
+        self.timestamp = Some((timestamp_s * 1_000_000_000, Precision::Seconds));
         self
     }
 
     /// Set the timestamp of the measurement. Expects a unix timestamp in milliseconds.
     pub fn timestamp_ms(mut self, timestamp_ms: i128) -> Self {
-        self.timestamp = Some(timestamp_ms * 1_000_000);
+        self.timestamp = Some((timestamp_ms * 1_000_000, Precision::Milliseconds));
+        self
+    }
+
+    /// Set the timestamp of the measurement. Expects a unix timestamp in microseconds.
+    pub fn timestamp_us(mut self, timestamp_us: i128) -> Self {
+        self.timestamp = Some((timestamp_us * 1_000, Precision::Microseconds));
         self
     }
 
     /// Set the timestamp of the measurement. Expects a unix timestamp in nanoseconds.
-    pub fn timestamp_nanos(mut self, timestamp_nanos: i128) -> Self {
-        self.timestamp = Some(timestamp_nanos);
+    pub fn timestamp_ns(mut self, timestamp_ns: i128) -> Self {
+        self.timestamp = Some((timestamp_ns, Precision::Nanoseconds));
+        self
+    }
+
+    /// Set the timestamp of the measurement. Expects a unix timestamp in nanoseconds.
+    ///
+    /// Same as `timestamp_ns`.
+    pub fn timestamp_nanos(self, timestamp_nanos: i128) -> Self {
+        self.timestamp_ns(timestamp_nanos)
+    }
+
+    /// Leave the timestamp out of the line protocol, so that the server assigns the time it
+    /// receives the measurement. Overrides any timestamp set on the builder.
+    pub fn server_timestamp(mut self) -> Self {
+        self.server_timestamp = true;
+        self
+    }
+
+    /// Set what to do with tags that have an empty key or value, defaults to `EmptyTagPolicy::Error`.
+    pub fn empty_tag_policy(mut self, policy: EmptyTagPolicy) -> Self {
+        self.empty_tag_policy = policy;
+        self
+    }
+
+    /// Set what to do with names starting with `_`, defaults to `ReservedNamePolicy::Allow`.
+    pub fn reserved_name_policy(mut self, policy: ReservedNamePolicy) -> Self {
+        self.reserved_name_policy = policy;
+        self
+    }
+
+    /// Set what to do with tag or field keys that are added more than once, defaults to
+    /// `DuplicateKeyPolicy::Overwrite`. A field with the same key as a tag counts as a duplicate.
+    pub fn duplicate_key_policy(mut self, policy: DuplicateKeyPolicy) -> Self {
+        self.duplicate_key_policy = policy;
         self
     }
 
-    pub fn build(self) -> Result<Measurement, MeasurementBuilderError> {
+    /// The measurement name, tag keys and field keys.
+    fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str())
+            .chain(self.tags.iter().map(|(name, _)| name.as_ref()))
+            .chain(self.fields.iter().map(|(name, _)| name.as_ref()))
+    }
+
+    fn check_reserved_names(&self) -> Result<(), MeasurementBuilderError> {
+        if self.reserved_name_policy == ReservedNamePolicy::Allow {
+            return Ok(());
+        }
+        for name in self.names().filter(|name| name.starts_with('_')) {
+            match self.reserved_name_policy {
+                ReservedNamePolicy::Reject => {
+                    return Err(MeasurementBuilderError::ReservedName(name.to_string()))
+                }
+                _ => log::warn!(
+                    "name '{}' in measurement '{}' is reserved by InfluxDB",
+                    name,
+                    self.name
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Tag keys that were added more than once, and field keys that were added more than once or
+    /// are also tag keys, once for every repetition.
+    fn duplicate_keys(&self) -> impl Iterator<Item = &str> {
+        let tags =
+            self.tags.iter().enumerate().filter(move |(i, (name, _))| {
+                self.tags[..*i].iter().any(|(other, _)| other == name)
+            });
+        let fields = self
+            .fields
+            .iter()
+            .enumerate()
+            .filter(move |(i, (name, _))| {
+                self.fields[..*i].iter().any(|(other, _)| other == name)
+                    || self.tags.iter().any(|(other, _)| other == name)
+            });
+        tags.map(|(_, (name, _))| name.as_ref())
+            .chain(fields.map(|(_, (name, _))| name.as_ref()))
+    }
+
+    fn check_duplicate_keys(&self) -> Result<(), MeasurementBuilderError> {
+        if self.duplicate_key_policy == DuplicateKeyPolicy::Overwrite {
+            return Ok(());
+        }
+        for name in self.duplicate_keys() {
+            match self.duplicate_key_policy {
+                DuplicateKeyPolicy::Reject => {
+                    return Err(MeasurementBuilderError::DuplicateKey(name.to_string()))
+                }
+                _ => log::warn!(
+                    "key '{}' is used more than once in measurement '{}'",
+                    name,
+                    self.name
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build(mut self) -> Result<Measurement, MeasurementBuilderError> {
+        if self.name.is_empty() {
+            return Err(MeasurementBuilderError::EmptyMeasurementName);
+        }
+        if self.fields.iter().any(|(name, _)| name.is_empty()) {
+            return Err(MeasurementBuilderError::EmptyFieldKey);
+        }
+        self.check_reserved_names()?;
+        self.check_duplicate_keys()?;
+        match self.empty_tag_policy {
+            EmptyTagPolicy::Error => {
+                for (name, value) in &self.tags {
+                    if name.is_empty() {
+                        return Err(MeasurementBuilderError::EmptyTagKey);
+                    }
+                    if value.as_str().is_empty() {
+                        return Err(MeasurementBuilderError::EmptyTagValue(name.to_string()));
+                    }
+                }
+            }
+            EmptyTagPolicy::Drop => self
+                .tags
+                .retain(|(name, value)| !name.is_empty() && !value.as_str().is_empty()),
+        }
+
         if self.fields.is_empty() {
             Err(MeasurementBuilderError::EmptyFields)
         } else {
-            let timestamp_nanos = if let Some(timestamp_nanos) = self.timestamp {
-                timestamp_nanos
+            let (timestamp_nanos, precision) = if self.server_timestamp {
+                (None, Precision::Nanoseconds)
+            } else if let Some((timestamp_nanos, precision)) = self.timestamp {
+                (Some(timestamp_nanos), precision)
             } else {
-                SystemTime::now()
+                let now = SystemTime::now()
                     .duration_since(SystemTime::UNIX_EPOCH)?
                     .as_nanos()
-                    .try_into()?
+                    .try_into()?;
+                (Some(now), Precision::Nanoseconds)
             };
             Ok(Measurement::new(
                 self.name,
                 timestamp_nanos,
+                precision,
                 self.tags.into_iter().collect(),
                 self.fields.into_iter().collect(),
             ))
         }
     }
+
+    /// Build the measurement after validating everything InfluxDB would reject, collecting every
+    /// problem instead of stopping at the first one.
+    ///
+    /// On top of the checks of `build`, this rejects non-finite floats, names that contain
+    /// newlines or end with a backslash (which can't be escaped in line protocol), and tag or
+    /// field keys that are used more than once.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::*;
+    /// let errors = Measurement::builder("m")
+    ///     .tag("host", "")
+    ///     .field("value", f64::NAN)
+    ///     .field("value", 1)
+    ///     .try_build_strict()
+    ///     .unwrap_err();
+    /// assert_eq!(errors.errors().len(), 3);
+    /// ```
+    pub fn try_build_strict(mut self) -> Result<Measurement, ValidationErrors> {
+        if self.empty_tag_policy == EmptyTagPolicy::Drop {
+            self.tags
+                .retain(|(name, value)| !name.is_empty() && !value.as_str().is_empty());
+        }
+
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push(MeasurementBuilderError::EmptyMeasurementName);
+        }
+        if self.fields.is_empty() {
+            errors.push(MeasurementBuilderError::EmptyFields);
+        }
+        if self.reserved_name_policy == ReservedNamePolicy::Reject {
+            errors.extend(
+                self.names()
+                    .filter(|name| name.starts_with('_'))
+                    .map(|name| MeasurementBuilderError::ReservedName(name.to_string())),
+            );
+        }
+
+        let names = std::iter::once(self.name.as_str())
+            .chain(
+                self.tags
+                    .iter()
+                    .flat_map(|(name, value)| [name.as_ref(), value.as_str()]),
+            )
+            .chain(self.fields.iter().map(|(name, _)| name.as_ref()));
+        errors.extend(
+            names
+                .filter(|name| name.contains(['\n', '\r']) || name.ends_with('\\'))
+                .map(|name| MeasurementBuilderError::UnescapableName(name.to_string())),
+        );
+
+        for (name, value) in &self.tags {
+            if name.is_empty() {
+                errors.push(MeasurementBuilderError::EmptyTagKey);
+            } else if value.as_str().is_empty() {
+                errors.push(MeasurementBuilderError::EmptyTagValue(name.to_string()));
+            }
+        }
+        for (name, value) in &self.fields {
+            if name.is_empty() {
+                errors.push(MeasurementBuilderError::EmptyFieldKey);
+            }
+            if matches!(value, Field::Float(v) if !v.is_finite()) {
+                errors.push(MeasurementBuilderError::NonFiniteFloat(name.to_string()));
+            }
+        }
+        errors.extend(
+            self.duplicate_keys()
+                .map(|name| MeasurementBuilderError::DuplicateKey(name.to_string())),
+        );
+
+        if !errors.is_empty() {
+            return Err(ValidationErrors(errors));
+        }
+        // Everything `build` checks has passed, so only getting the current time can fail.
+        self.reserved_name_policy = ReservedNamePolicy::Allow;
+        self.duplicate_key_policy = DuplicateKeyPolicy::Overwrite;
+        self.build().map_err(|e| ValidationErrors(vec![e]))
+    }
 }
 
 /// The error type returned when building a `Measurement` fails.
@@ -290,6 +1329,23 @@ impl MeasurementBuilder {
 pub enum MeasurementBuilderError {
     /// No fields were provided in the measurement.
     EmptyFields,
+    /// The measurement name is empty.
+    EmptyMeasurementName,
+    /// A field was added with an empty key.
+    EmptyFieldKey,
+    /// A tag was added with an empty key.
+    EmptyTagKey,
+    /// The tag with the contained key has an empty value.
+    EmptyTagValue(String),
+    /// The contained measurement, tag or field name starts with `_`, which is reserved.
+    ReservedName(String),
+    /// The contained measurement name, key or tag value contains a newline or ends with a
+    /// backslash, which can't be escaped in line protocol.
+    UnescapableName(String),
+    /// The field with the contained key is `NaN` or infinite, which InfluxDB rejects.
+    NonFiniteFloat(String),
+    /// The contained key is used by more than one tag or field.
+    DuplicateKey(String),
     /// Getting the current unix timestamp (because one wasn't provided) failed.
     SystemTimeError(SystemTimeError),
     /// Converting the current unix timestamp from `u128` to `i128` failed.
@@ -300,6 +1356,27 @@ impl Display for MeasurementBuilderError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let output = match self {
             MeasurementBuilderError::EmptyFields => "fields cannot be empty".to_string(),
+            MeasurementBuilderError::EmptyMeasurementName => {
+                "measurement name cannot be empty".to_string()
+            }
+            MeasurementBuilderError::EmptyFieldKey => "field keys cannot be empty".to_string(),
+            MeasurementBuilderError::EmptyTagKey => "tag keys cannot be empty".to_string(),
+            MeasurementBuilderError::EmptyTagValue(name) => {
+                format!("value of tag '{}' cannot be empty", name)
+            }
+            MeasurementBuilderError::ReservedName(name) => {
+                format!("name '{}' is reserved, names cannot start with '_'", name)
+            }
+            MeasurementBuilderError::UnescapableName(name) => format!(
+                "name '{}' cannot contain newlines or end with a backslash",
+                name.escape_debug()
+            ),
+            MeasurementBuilderError::NonFiniteFloat(name) => {
+                format!("value of field '{}' must be a finite number", name)
+            }
+            MeasurementBuilderError::DuplicateKey(name) => {
+                format!("key '{}' is used more than once", name)
+            }
             MeasurementBuilderError::SystemTimeError(e) => format!("SystemTimeError: '{}'", e),
             MeasurementBuilderError::TryFromIntError(e) => format!("TryFromIntError: '{}'", e),
         };
@@ -322,6 +1399,36 @@ impl From<TryFromIntError> for MeasurementBuilderError {
 
 impl Error for MeasurementBuilderError {}
 
+/// The error type returned by `MeasurementBuilder::try_build_strict`, holding every problem found
+/// with the measurement.
+#[derive(Debug)]
+pub struct ValidationErrors(Vec<MeasurementBuilderError>);
+
+impl ValidationErrors {
+    /// The problems found, in the order they were checked.
+    pub fn errors(&self) -> &[MeasurementBuilderError] {
+        &self.0
+    }
+
+    pub fn into_errors(self) -> Vec<MeasurementBuilderError> {
+        self.0
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationErrors {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -347,6 +1454,49 @@ mod tests {
         assert_eq!(Field::from(false), Field::Bool(false));
 
         assert_eq!(Field::from("s".to_string()), Field::String("s".to_string()));
+        assert_eq!(
+            Field::from(&"s".to_string()),
+            Field::String("s".to_string())
+        );
+        assert_eq!(
+            Field::from(Cow::Borrowed("s")),
+            Field::String("s".to_string())
+        );
+        assert_eq!(Field::from('c'), Field::String("c".to_string()));
+        assert_eq!(
+            Field::from(IpAddr::from([127, 0, 0, 1])),
+            Field::String("127.0.0.1".to_string())
+        );
+        assert_eq!(
+            Field::from(Duration::from_millis(1500)),
+            Field::Integer(1_500_000_000)
+        );
+    }
+
+    #[test]
+    fn field_conversion() {
+        assert_eq!(Field::Float(1.5).as_f64(), Some(1.5));
+        assert_eq!(Field::Integer(-2).as_f64(), Some(-2.0));
+        assert_eq!(Field::Integer(-2).as_i64(), Some(-2));
+        assert_eq!(Field::Integer(-2).as_u64(), None);
+        assert_eq!(Field::UInteger(u128::MAX).as_u64(), None);
+        assert_eq!(Field::Bool(true).as_bool(), Some(true));
+        assert_eq!(Field::String("s".to_string()).as_str(), Some("s"));
+        assert_eq!(Field::String("s".to_string()).as_f64(), None);
+
+        assert_eq!(f64::try_from(Field::UInteger(3)), Ok(3.0));
+        assert_eq!(i64::try_from(Field::Integer(3)), Ok(3));
+        assert_eq!(bool::try_from(Field::Bool(false)), Ok(false));
+        assert_eq!(
+            String::try_from(Field::String("s".to_string())),
+            Ok("s".to_string())
+        );
+        let err = u64::try_from(Field::Float(1.0)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected a field convertible to u64, got float field"
+        );
+        assert_eq!(err.into_field(), Field::Float(1.0));
     }
 
     #[test]
@@ -371,7 +1521,7 @@ mod tests {
                 measurement_name: "example_measurement".to_string(),
                 tags: vec![("tag_1", "tag_value_1"), ("tag_2", "tag_value_2")]
                     .into_iter()
-                    .map(|(name, value)| (name.to_string(), TagValue::new(value.to_string())))
+                    .map(|(name, value)| (name.into(), TagValue::new(value.to_string())))
                     .collect(),
                 fields: vec![
                     ("bool_field", Field::Bool(true)),
@@ -381,9 +1531,10 @@ mod tests {
                     ("string_field", Field::String("string_value".to_string()))
                 ]
                 .into_iter()
-                .map(|(name, value)| (name.to_string(), value))
+                .map(|(name, value)| (name.into(), value))
                 .collect(),
-                timestamp_nanos: 1602321877560000000
+                timestamp_nanos: Some(1602321877560000000),
+                precision: Precision::Milliseconds,
             }
         );
     }
@@ -403,6 +1554,531 @@ mod tests {
         );
     }
 
+    #[test]
+    fn empty_tags() {
+        let builder = || {
+            Measurement::builder("m")
+                .tag("tag1", "value")
+                .tag("tag2", "")
+                .field("field1", 1)
+                .timestamp_ms(1622493622000)
+        };
+
+        assert!(matches!(
+            builder().build(),
+            Err(MeasurementBuilderError::EmptyTagValue(name)) if name == "tag2"
+        ));
+        assert!(matches!(
+            builder()
+                .tag("", "value")
+                .empty_tag_policy(EmptyTagPolicy::Error)
+                .build(),
+            Err(MeasurementBuilderError::EmptyTagValue(_))
+        ));
+        let empty_key = || {
+            Measurement::builder("m")
+                .tag("", "value")
+                .field("field1", 1)
+                .timestamp_ms(1622493622000)
+        };
+        assert!(matches!(
+            empty_key().build(),
+            Err(MeasurementBuilderError::EmptyTagKey)
+        ));
+        assert!(matches!(
+            empty_key()
+                .empty_tag_policy(EmptyTagPolicy::Error)
+                .try_build_strict()
+                .unwrap_err()
+                .errors(),
+            [MeasurementBuilderError::EmptyTagKey]
+        ));
+        assert!(matches!(
+            builder().try_build_strict().unwrap_err().errors(),
+            [MeasurementBuilderError::EmptyTagValue(name)] if name == "tag2"
+        ));
+        assert_eq!(
+            builder()
+                .tag("", "value")
+                .empty_tag_policy(EmptyTagPolicy::Drop)
+                .build()
+                .unwrap()
+                .to_line_protocol(),
+            "m,tag1=value field1=1i 1622493622000000000"
+        );
+        assert!(matches!(
+            Measurement::builder("").field("field1", 1).build(),
+            Err(MeasurementBuilderError::EmptyMeasurementName)
+        ));
+        assert!(matches!(
+            Measurement::builder("m").field("", 1).build(),
+            Err(MeasurementBuilderError::EmptyFieldKey)
+        ));
+    }
+
+    #[test]
+    fn reserved_names() {
+        let builder = || {
+            Measurement::builder("m")
+                .tag("_tag", "value")
+                .field("field1", 1)
+                .timestamp_ms(1622493622000)
+        };
+
+        assert!(builder().build().is_ok());
+        assert!(builder()
+            .reserved_name_policy(ReservedNamePolicy::Warn)
+            .build()
+            .is_ok());
+        assert!(matches!(
+            builder()
+                .reserved_name_policy(ReservedNamePolicy::Reject)
+                .build(),
+            Err(MeasurementBuilderError::ReservedName(name)) if name == "_tag"
+        ));
+    }
+
+    #[test]
+    fn merge() {
+        let build = |tag: &'static str, field: &'static str, value: i64| {
+            Measurement::builder("m")
+                .tag(tag, "a")
+                .field(field, value)
+                .timestamp_ms(1622493622000)
+                .build()
+                .unwrap()
+        };
+
+        let mut m = build("t1", "f1", 1);
+        m.merge(build("t2", "f2", 2), MergePolicy::Reject).unwrap();
+        m.merge(build("t1", "f1", 1), MergePolicy::Reject).unwrap();
+        assert_eq!(m.tags().count(), 2);
+        assert_eq!(m.fields().count(), 2);
+
+        assert_eq!(
+            m.merge(build("t1", "f1", 3), MergePolicy::Reject),
+            Err(MergeError::Conflict("f1".to_string()))
+        );
+        assert_eq!(m.field("f1"), Some(&Field::Integer(1)));
+        m.merge(build("t1", "f1", 3), MergePolicy::KeepExisting)
+            .unwrap();
+        assert_eq!(m.field("f1"), Some(&Field::Integer(1)));
+        m.merge(build("t1", "f1", 3), MergePolicy::Overwrite)
+            .unwrap();
+        assert_eq!(m.field("f1"), Some(&Field::Integer(3)));
+
+        let mut later = build("t1", "f1", 1);
+        later.timestamp_nanos = Some(0);
+        assert!(matches!(
+            m.merge(later, MergePolicy::Overwrite),
+            Err(MergeError::TimestampMismatch(_, Some(0)))
+        ));
+        let other = Measurement::builder("other").field("f", 1).build().unwrap();
+        assert!(matches!(
+            m.merge(other, MergePolicy::Overwrite),
+            Err(MergeError::NameMismatch(name)) if name == "other"
+        ));
+    }
+
+    #[test]
+    fn truncate_timestamp() {
+        let mut m = Measurement::builder("m")
+            .field("field1", 1)
+            .timestamp_ns(-1_500)
+            .build()
+            .unwrap();
+        m.truncate_timestamp(Precision::Microseconds);
+        assert_eq!(m.timestamp(), Some(-2_000));
+        assert_eq!(m.precision(), Precision::Microseconds);
+        m.truncate_timestamp(Precision::Nanoseconds);
+        assert_eq!(m.timestamp(), Some(-2_000));
+        assert_eq!(m.precision(), Precision::Microseconds);
+
+        let mut m = Measurement::builder("m")
+            .field("field1", 1)
+            .server_timestamp()
+            .build()
+            .unwrap();
+        m.truncate_timestamp(Precision::Seconds);
+        assert_eq!(m.timestamp(), None);
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let key = String::from("runtime");
+        let m = Measurement::builder("m")
+            .tag("host", "a")
+            .field("value", 1)
+            .field(key, 2)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+        assert!(m.tags.keys().all(|k| matches!(k, Cow::Borrowed(_))));
+        assert!(matches!(
+            m.fields.iter().find(|(k, _)| *k == "value"),
+            Some((Cow::Borrowed(_), _))
+        ));
+        assert_eq!(m.field("runtime"), Some(&Field::Integer(2)));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let builder = || {
+            Measurement::builder("m")
+                .tag("tag1", "a")
+                .field("field1", 1)
+                .field("field1", 2)
+                .timestamp_ms(1622493622000)
+        };
+
+        let m = builder().build().unwrap();
+        assert_eq!(m.field("field1"), Some(&Field::Integer(2)));
+        assert!(builder()
+            .duplicate_key_policy(DuplicateKeyPolicy::Warn)
+            .build()
+            .is_ok());
+        assert!(matches!(
+            builder()
+                .duplicate_key_policy(DuplicateKeyPolicy::Reject)
+                .build(),
+            Err(MeasurementBuilderError::DuplicateKey(name)) if name == "field1"
+        ));
+        assert!(matches!(
+            Measurement::builder("m")
+                .tag("key", "a")
+                .field("key", 1)
+                .duplicate_key_policy(DuplicateKeyPolicy::Reject)
+                .build(),
+            Err(MeasurementBuilderError::DuplicateKey(name)) if name == "key"
+        ));
+    }
+
+    #[test]
+    fn strict_validation() {
+        let errors = Measurement::builder("m\n")
+            .tag("host", "a")
+            .tag("host", "b")
+            .tag("path", "c:\\")
+            .field("host", 1)
+            .field("value", f64::INFINITY)
+            .timestamp_ms(1622493622000)
+            .try_build_strict()
+            .unwrap_err();
+        let messages: Vec<String> = errors.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "name 'm\\n' cannot contain newlines or end with a backslash",
+                "name 'c:\\\\' cannot contain newlines or end with a backslash",
+                "value of field 'value' must be a finite number",
+                "key 'host' is used more than once",
+                "key 'host' is used more than once",
+            ]
+        );
+
+        let measurement = Measurement::builder("m")
+            .tag("empty", "")
+            .empty_tag_policy(EmptyTagPolicy::Drop)
+            .field("value", 1.5)
+            .timestamp_ms(1622493622000)
+            .try_build_strict()
+            .unwrap();
+        assert_eq!(
+            measurement.to_line_protocol(),
+            "m value=1.5 1622493622000000000"
+        );
+        assert!(matches!(
+            Measurement::builder("m")
+                .try_build_strict()
+                .unwrap_err()
+                .errors(),
+            [MeasurementBuilderError::EmptyFields]
+        ));
+    }
+
+    #[test]
+    fn sorted_tags() {
+        let m = Measurement::builder("m")
+            .tag("c", "3")
+            .tag("a", "1")
+            .tag("b", "2")
+            .field("field1", 1)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            m.to_line_protocol(),
+            "m,a=1,b=2,c=3 field1=1i 1622493622000000000"
+        );
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn preserve_field_order() {
+        let mut m = Measurement::builder("m")
+            .field("c", 3)
+            .field("a", 1)
+            .field("b", 2)
+            .field("c", 4)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+        m.add_field("0", 0);
+
+        assert_eq!(
+            m.to_line_protocol(),
+            "m c=4i,a=1i,b=2i,0=0i 1622493622000000000"
+        );
+    }
+
+    #[test]
+    fn precision() {
+        let m = Measurement::builder("m")
+            .field("field1", 1)
+            .timestamp_s(1622493622)
+            .build()
+            .unwrap();
+
+        assert_eq!(m.precision(), Precision::Seconds);
+        assert_eq!(
+            m.to_line_protocol_with_precision(Precision::Seconds),
+            "m field1=1i 1622493622"
+        );
+        assert_eq!(m.to_line_protocol(), "m field1=1i 1622493622000000000");
+
+        let m = Measurement::builder("m")
+            .field("field1", 1)
+            .timestamp_ns(1622493622123456789)
+            .build()
+            .unwrap();
+
+        assert_eq!(m.precision(), Precision::Nanoseconds);
+        assert_eq!(m.to_line_protocol(), "m field1=1i 1622493622123456789");
+        assert_eq!(
+            m.to_line_protocol_with_precision(Precision::Microseconds),
+            "m field1=1i 1622493622123456"
+        );
+
+        let m = Measurement::builder("m")
+            .field("field1", 1)
+            .timestamp_us(1622493622123456)
+            .build()
+            .unwrap();
+
+        assert_eq!(m.precision(), Precision::Microseconds);
+        assert_eq!(m.to_line_protocol(), "m field1=1i 1622493622123456000");
+    }
+
+    #[test]
+    fn server_timestamp() {
+        let m = Measurement::builder("m")
+            .tag("tag1", "value")
+            .field("field1", 1)
+            .timestamp_ms(1622493622000)
+            .server_timestamp()
+            .build()
+            .unwrap();
+
+        assert_eq!(m.timestamp(), None);
+        assert_eq!(m.to_line_protocol(), "m,tag1=value field1=1i");
+        assert_eq!(
+            m.to_line_protocol_with_precision(Precision::Seconds),
+            "m,tag1=value field1=1i"
+        );
+    }
+
+    #[test]
+    fn negative_timestamps() {
+        let m = Measurement::builder("climate")
+            .field("temperature", -3.5)
+            .timestamp_ms(-1_500)
+            .build()
+            .unwrap();
+
+        assert_eq!(m.to_line_protocol(), "climate temperature=-3.5 -1500000000");
+        assert_eq!(
+            m.to_line_protocol_with_precision(Precision::Milliseconds),
+            "climate temperature=-3.5 -1500"
+        );
+        // truncation rounds towards negative infinity, like it does for positive timestamps
+        assert_eq!(
+            m.to_line_protocol_with_precision(Precision::Seconds),
+            "climate temperature=-3.5 -2"
+        );
+
+        let m = Measurement::builder("climate")
+            .field("temperature", 1.0)
+            .timestamp_s(-2_208_988_800) // 1900-01-01T00:00:00Z
+            .build()
+            .unwrap();
+        assert_eq!(
+            m.to_line_protocol_with_precision(m.precision()),
+            "climate temperature=1 -2208988800"
+        );
+    }
+
+    #[test]
+    fn system_time_timestamp() {
+        let before_epoch = SystemTime::UNIX_EPOCH - Duration::from_nanos(1_500);
+        assert_eq!(
+            before_epoch.into_timestamp(),
+            (-1_500, Precision::Nanoseconds)
+        );
+
+        let m = Measurement::builder("m")
+            .field("field1", 1)
+            .timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(1622493622))
+            .build()
+            .unwrap();
+        assert_eq!(m.to_line_protocol(), "m field1=1i 1622493622000000000");
+    }
+
+    #[test]
+    fn optional_tags_and_fields() {
+        let m = Measurement::builder("m")
+            .tag_opt("tag1", Some("a"))
+            .tag_opt("tag2", None::<String>)
+            .field_opt("field1", Some(1))
+            .field_opt("field2", None::<f64>)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            m.to_line_protocol(),
+            "m,tag1=a field1=1i 1622493622000000000"
+        );
+    }
+
+    #[test]
+    fn bulk_tags_and_fields() {
+        let mut m = Measurement::builder("m")
+            .tags(vec![("b", "2"), ("a", "1")])
+            .fields(vec![("field1", 1)])
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+        m.add_tags(vec![
+            ("c", "3".to_string()),
+            ("", "4".to_string()),
+            ("d", String::new()),
+        ]);
+        m.add_tag("", "5");
+        m.add_fields(vec![("field1", 2)]);
+        m.extend(vec![("field1", 3)]);
+
+        assert_eq!(
+            m.to_line_protocol(),
+            "m,a=1,b=2,c=3 field1=3i 1622493622000000000"
+        );
+    }
+
+    #[test]
+    fn accessors() {
+        let mut m = Measurement::builder("m")
+            .tag("agent", "KHTML, like Gecko")
+            .tag("tag2", "value")
+            .field("field1", 1)
+            .field("field2", true)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+
+        assert_eq!(m.name(), "m");
+        assert_eq!(m.timestamp(), Some(1622493622000000000));
+        assert_eq!(
+            m.tags().collect::<Vec<_>>(),
+            vec![("agent", "KHTML, like Gecko"), ("tag2", "value")]
+        );
+        assert_eq!(m.tag("agent"), Some("KHTML, like Gecko"));
+        assert_eq!(m.field("field2"), Some(&Field::Bool(true)));
+        assert_eq!(m.fields().count(), 2);
+
+        assert_eq!(m.remove_tag("tag2"), Some("value".to_string()));
+        assert_eq!(m.remove_tag("tag2"), None);
+        assert_eq!(m.remove_field("field2"), Some(Field::Bool(true)));
+        assert_eq!(
+            m.to_line_protocol(),
+            r#"m,agent=KHTML\,\ like\ Gecko field1=1i 1622493622000000000"#
+        );
+    }
+
+    #[test]
+    fn number_formatting() {
+        let line = |field: Field| {
+            Measurement::builder("m")
+                .field("f", field)
+                .timestamp_ns(-1)
+                .build()
+                .unwrap()
+                .to_line_protocol()
+        };
+        assert_eq!(line(Field::Float(1.0)), "m f=1 -1");
+        assert_eq!(line(Field::Float(-0.1)), "m f=-0.1 -1");
+        assert_eq!(line(Field::Float(1e21)), "m f=1e21 -1");
+        assert_eq!(
+            line(Field::Integer(i128::MIN)),
+            format!("m f={}i -1", i128::MIN)
+        );
+        assert_eq!(
+            line(Field::UInteger(u128::MAX)),
+            format!("m f={}u -1", u128::MAX)
+        );
+    }
+
+    #[test]
+    fn write_into_buffer() {
+        let m = Measurement::builder("m")
+            .tag("tag1", "a b")
+            .field("field1", "\"quoted\"")
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+
+        let mut buffer = String::with_capacity(128);
+        m.write_line_protocol(&mut buffer);
+        buffer.push('\n');
+        m.write_line_protocol_with_precision(&mut buffer, Precision::Seconds);
+        assert_eq!(
+            buffer,
+            "m,tag1=a\\ b field1=\"\\\"quoted\\\"\" 1622493622000000000\n\
+             m,tag1=a\\ b field1=\"\\\"quoted\\\"\" 1622493622"
+        );
+        assert_eq!(buffer.capacity(), 128);
+    }
+
+    #[test]
+    fn display() {
+        let m = Measurement::builder("m")
+            .tag("tag1", "value")
+            .field("field1", 1)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+        assert_eq!(m.to_string(), m.to_line_protocol());
+    }
+
+    #[cfg(all(feature = "serde", feature = "client"))]
+    #[test]
+    fn serde_round_trip() {
+        let m = Measurement::builder("m")
+            .tag("tag1", "value, with escapes")
+            .field("float", 1.5)
+            .field("integer", -1)
+            .field("uinteger", u64::MAX)
+            .field("string", "value")
+            .field("bool", true)
+            .timestamp_ms(1622493622000)
+            .build()
+            .unwrap();
+
+        let json = client::json::to_string(&m).unwrap();
+        assert!(json.contains(r#""uinteger":{"UInteger":18446744073709551615}"#));
+        let deserialized: Measurement = client::json::from_str(&json).unwrap();
+        assert_eq!(deserialized, m);
+    }
+
     #[test]
     fn readme_test() {
         let measurement = Measurement::builder("m1")
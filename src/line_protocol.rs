@@ -0,0 +1,595 @@
+//! Parsing of InfluxDB line protocol into `Measurement`s.
+
+use crate::{Field, Measurement, MeasurementBuilder, Precision};
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+/// The error returned when parsing line protocol fails.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineProtocolError {
+    line: usize,
+    message: String,
+}
+
+impl LineProtocolError {
+    /// The (1-based) number of the line that couldn't be parsed.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl Display for LineProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid line protocol on line {}: {}",
+            self.line, self.message
+        )
+    }
+}
+
+impl Error for LineProtocolError {}
+
+/// Options for writing line protocol, see `Measurement::to_line_protocol_with_options` and
+/// `InfluxClientBuilder::line_protocol_options`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineProtocolOptions {
+    pub(crate) compact_booleans: bool,
+    pub(crate) control_characters: ControlCharacters,
+    float_format: FloatFormat,
+    field_float_formats: HashMap<String, FloatFormat>,
+}
+
+/// How float fields are written, see `LineProtocolOptions::float_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatFormat {
+    /// The shortest representation that parses back to the same value, up to 17 significant
+    /// digits.
+    #[default]
+    Shortest,
+    /// Rounded to the given number of decimal places, e.g. `21.5` for `21.4999` with 2.
+    Decimals(u8),
+    /// Rounded to the given number of significant digits (at least 1), e.g. `0.00123` for
+    /// `0.0012345` with 3.
+    SignificantDigits(u8),
+}
+
+impl FloatFormat {
+    /// Round `v` according to this format.
+    pub(crate) fn round(self, v: f64) -> f64 {
+        if !v.is_finite() {
+            return v;
+        }
+        // Formatting rounds correctly, unlike scaling by a power of ten.
+        let rounded = match self {
+            FloatFormat::Shortest => return v,
+            FloatFormat::Decimals(decimals) => format!("{:.*}", usize::from(decimals), v),
+            FloatFormat::SignificantDigits(digits) => {
+                format!("{:.*e}", usize::from(digits.max(1) - 1), v)
+            }
+        };
+        rounded.parse().unwrap_or(v)
+    }
+}
+
+/// What to do with control characters (such as newlines) in string field values, see
+/// `LineProtocolOptions::control_characters`. Written as is, a newline ends the line early and
+/// corrupts the rest of the batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlCharacters {
+    /// Write them as Rust-style escapes, e.g. `\n` or `\u{1b}`, which InfluxDB stores as is.
+    #[default]
+    Escape,
+    /// Leave them out.
+    Strip,
+    /// Fail `Measurement::try_to_line_protocol_with_options`, and writes through the client
+    /// with `InfluxError::ControlCharacter`. Methods that can't fail, such as
+    /// `Measurement::to_line_protocol_with_options`, escape them.
+    Reject,
+}
+
+/// The error returned when a string field has control characters that `ControlCharacters::Reject`
+/// rejects, see `Measurement::try_to_line_protocol_with_options`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlCharacterError {
+    measurement: String,
+    field: String,
+}
+
+impl ControlCharacterError {
+    pub(crate) fn new(measurement: &str, field: &str) -> Self {
+        Self {
+            measurement: measurement.to_string(),
+            field: field.to_string(),
+        }
+    }
+
+    pub fn measurement(&self) -> &str {
+        &self.measurement
+    }
+
+    /// The key of the string field with control characters.
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+}
+
+impl Display for ControlCharacterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "string field '{}' of '{}' contains control characters",
+            self.field, self.measurement
+        )
+    }
+}
+
+impl Error for ControlCharacterError {}
+
+impl ControlCharacters {
+    /// Write the string field value `s`, escaped for line protocol. Values with control
+    /// characters to reject are escaped, callers that can fail check for them beforehand.
+    pub(crate) fn write_string<W: std::fmt::Write>(self, out: &mut W, s: &str) -> std::fmt::Result {
+        let mut rest = s;
+        while let Some(i) = rest.find(char::is_control) {
+            crate::write_escaped(out, &rest[..i], crate::STRING_SPECIAL)?;
+            let c = rest[i..].chars().next().unwrap_or_default();
+            match self {
+                ControlCharacters::Escape | ControlCharacters::Reject => {
+                    write!(out, "{}", c.escape_default())?
+                }
+                ControlCharacters::Strip => {}
+            }
+            rest = &rest[i + c.len_utf8()..];
+        }
+        crate::write_escaped(out, rest, crate::STRING_SPECIAL)
+    }
+}
+
+impl LineProtocolOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write booleans as `t` and `f` instead of `true` and `false`, which InfluxDB reads the
+    /// same, to save bytes in large batches of boolean fields.
+    pub fn compact_booleans(mut self, compact_booleans: bool) -> Self {
+        self.compact_booleans = compact_booleans;
+        self
+    }
+
+    /// Set what to do with control characters in string field values, defaults to
+    /// `ControlCharacters::Escape`.
+    pub fn control_characters(mut self, control_characters: ControlCharacters) -> Self {
+        self.control_characters = control_characters;
+        self
+    }
+
+    /// Set how float fields are written, defaults to `FloatFormat::Shortest`. Rounding noisy
+    /// sensor readings to the precision they are actually measured with saves a lot of bytes.
+    pub fn float_format(mut self, format: FloatFormat) -> Self {
+        self.float_format = format;
+        self
+    }
+
+    /// Set how float fields with the key `field` are written, overriding `float_format`.
+    pub fn field_float_format(mut self, field: impl Into<String>, format: FloatFormat) -> Self {
+        self.field_float_formats.insert(field.into(), format);
+        self
+    }
+
+    /// The format of float fields with the key `field`.
+    pub(crate) fn float_format_of(&self, field: &str) -> FloatFormat {
+        if self.field_float_formats.is_empty() {
+            return self.float_format;
+        }
+        self.field_float_formats
+            .get(field)
+            .copied()
+            .unwrap_or(self.float_format)
+    }
+}
+
+impl Measurement {
+    /// Parse one or more lines of line protocol with timestamps in nanoseconds, such as the output
+    /// of `to_line_protocol`.
+    ///
+    /// Empty lines and comments (lines starting with `#`) are skipped, and lines without a
+    /// timestamp are parsed as if built with `MeasurementBuilder::server_timestamp`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use influxrs::Measurement;
+    /// let measurements = Measurement::from_line_protocol(
+    ///     r#"cpu,host=web\ 1 usage=0.5,cores=4i 1622493622000000000
+    /// mem,host=web\ 1 free=1024u"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(measurements[0].tag("host"), Some("web 1"));
+    /// assert_eq!(measurements[1].timestamp(), None);
+    /// ```
+    pub fn from_line_protocol(lines: &str) -> Result<Vec<Measurement>, LineProtocolError> {
+        Self::from_line_protocol_with_precision(lines, Precision::Nanoseconds)
+    }
+
+    /// Parse one or more lines of line protocol with timestamps in the given precision, such as
+    /// the output of `to_line_protocol_with_precision`.
+    pub fn from_line_protocol_with_precision(
+        lines: &str,
+        precision: Precision,
+    ) -> Result<Vec<Measurement>, LineProtocolError> {
+        lines
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim_start();
+                !line.is_empty() && !line.starts_with('#')
+            })
+            .map(|(i, line)| {
+                parse_line(line.trim_start(), precision)
+                    .map(|parsed| parsed.measurement)
+                    .map_err(|e| LineProtocolError {
+                        line: i + 1,
+                        message: e.message,
+                    })
+            })
+            .collect()
+    }
+}
+
+const NAME_ESCAPES: &[char] = &[',', ' '];
+const KEY_ESCAPES: &[char] = &[',', '=', ' '];
+const STRING_ESCAPES: &[char] = &['"', '\\'];
+
+/// A parsed line, with the byte offsets of its parts in the line.
+pub(crate) struct ParsedLine {
+    pub(crate) measurement: Measurement,
+    /// Keys of the fields and their offsets, in the order they appear
+    pub(crate) fields: Vec<(String, usize)>,
+    /// Offset of the timestamp, if the line has one
+    pub(crate) timestamp: Option<usize>,
+    /// Offsets of backslashes that don't escape anything and are kept as is
+    pub(crate) stray_escapes: Vec<usize>,
+}
+
+/// Why a line couldn't be parsed, see `parse_line`.
+pub(crate) struct ParseLineError {
+    /// Offset in the line where parsing failed
+    pub(crate) offset: usize,
+    pub(crate) message: String,
+    /// The timestamp of the line, in units of the precision, if it can't be represented in
+    /// nanoseconds
+    pub(crate) timestamp_out_of_range: Option<i128>,
+}
+
+impl From<(usize, String)> for ParseLineError {
+    fn from((offset, message): (usize, String)) -> Self {
+        Self {
+            offset,
+            message,
+            timestamp_out_of_range: None,
+        }
+    }
+}
+
+/// Parse a line, failing with where and why parsing failed.
+pub(crate) fn parse_line(line: &str, precision: Precision) -> Result<ParsedLine, ParseLineError> {
+    let mut cursor = Cursor {
+        line,
+        rest: line,
+        stray_escapes: Vec::new(),
+    };
+
+    let name = cursor.read_until(NAME_ESCAPES, NAME_ESCAPES);
+    let mut builder = MeasurementBuilder::new(name);
+    while cursor.eat(',') {
+        let key = cursor.read_until(&['='], KEY_ESCAPES);
+        if !cursor.eat('=') {
+            return Err((cursor.offset(), format!("missing value of tag '{}'", key)).into());
+        }
+        let value = cursor.read_until(&[',', ' '], KEY_ESCAPES);
+        builder = builder.tag(key, value);
+    }
+
+    if !cursor.eat(' ') {
+        return Err((cursor.offset(), "missing fields".to_string()).into());
+    }
+    let mut fields = Vec::new();
+    loop {
+        let key_offset = cursor.offset();
+        let key = cursor.read_until(&['='], KEY_ESCAPES);
+        if !cursor.eat('=') {
+            return Err((cursor.offset(), format!("missing value of field '{}'", key)).into());
+        }
+        let value_offset = cursor.offset();
+        let value = if cursor.eat('"') {
+            let value = cursor.read_until(&['"'], STRING_ESCAPES);
+            if !cursor.eat('"') {
+                return Err((
+                    value_offset,
+                    format!("unterminated string value of field '{}'", key),
+                )
+                    .into());
+            }
+            Field::String(value)
+        } else {
+            let value = cursor.read_until(&[',', ' '], &[]);
+            parse_field(&value)
+                .ok_or_else(|| (value_offset, format!("invalid value of field '{}'", key)))?
+        };
+        fields.push((key.clone(), key_offset));
+        builder = builder.field(key, value);
+        if !cursor.eat(',') {
+            break;
+        }
+    }
+
+    let timestamp_offset = cursor.offset() + (cursor.rest.len() - cursor.rest.trim_start().len());
+    let timestamp = cursor.rest.trim();
+    let (builder, timestamp_offset) = if timestamp.is_empty() {
+        (builder.server_timestamp(), None)
+    } else {
+        let timestamp: i128 = timestamp.parse().map_err(|_| {
+            (
+                timestamp_offset,
+                format!("invalid timestamp '{}'", timestamp),
+            )
+        })?;
+        let nanos = timestamp
+            .checked_mul(precision.nanos_per_unit())
+            .ok_or_else(|| ParseLineError {
+                offset: timestamp_offset,
+                message: format!("timestamp '{}' out of range", timestamp),
+                timestamp_out_of_range: Some(timestamp),
+            })?;
+        builder.timestamp = Some((nanos, precision));
+        (builder, Some(timestamp_offset))
+    };
+    let measurement = builder
+        .build()
+        .map_err(|e| ParseLineError::from((0, e.to_string())))?;
+    Ok(ParsedLine {
+        measurement,
+        fields,
+        timestamp: timestamp_offset,
+        stray_escapes: cursor.stray_escapes,
+    })
+}
+
+fn parse_field(value: &str) -> Option<Field> {
+    let field = match value {
+        "t" | "T" | "true" | "True" | "TRUE" => Field::Bool(true),
+        "f" | "F" | "false" | "False" | "FALSE" => Field::Bool(false),
+        _ => {
+            if let Some(integer) = value.strip_suffix('i') {
+                Field::Integer(integer.parse().ok()?)
+            } else if let Some(uinteger) = value.strip_suffix('u') {
+                Field::UInteger(uinteger.parse().ok()?)
+            } else {
+                Field::Float(value.parse().ok()?)
+            }
+        }
+    };
+    Some(field)
+}
+
+struct Cursor<'a> {
+    line: &'a str,
+    rest: &'a str,
+    stray_escapes: Vec<usize>,
+}
+
+impl Cursor<'_> {
+    fn offset(&self) -> usize {
+        self.line.len() - self.rest.len()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Read up to the first unescaped character in `stops`. A backslash only escapes the
+    /// characters in `escapes`, and is kept as is before any other character.
+    fn read_until(&mut self, stops: &[char], escapes: &[char]) -> String {
+        let mut read = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if stops.contains(&c) {
+                self.rest = &self.rest[i..];
+                return read;
+            }
+            if c == '\\' {
+                match self.rest[i + 1..].chars().next() {
+                    Some(next) if escapes.contains(&next) => {
+                        read.push(next);
+                        chars.next();
+                        continue;
+                    }
+                    _ => self.stray_escapes.push(self.offset() + i),
+                }
+            }
+            read.push(c);
+        }
+        self.rest = "";
+        read
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let measurements = vec![
+            Measurement::builder("weather, daily")
+                .tag("location", "Madrid, Spain")
+                .tag("sensor id", "a=b")
+                .field("temperature", 21.5)
+                .field("raining", false)
+                .field("note", r#"said "hi" \o/"#)
+                .field("count", -3)
+                .field("total", 3_u64)
+                .timestamp_ms(1622493622000)
+                .build()
+                .unwrap(),
+            Measurement::builder("m")
+                .field("f", 1)
+                .server_timestamp()
+                .build()
+                .unwrap(),
+        ];
+        let lines = measurements
+            .iter()
+            .map(|m| m.to_line_protocol_with_precision(Precision::Milliseconds))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let parsed =
+            Measurement::from_line_protocol_with_precision(&lines, Precision::Milliseconds)
+                .unwrap();
+        assert_eq!(parsed, measurements);
+    }
+
+    #[test]
+    fn float_formats() {
+        let measurement = Measurement::builder("m")
+            .field("a", 21.456789)
+            .field("b", 0.0012345)
+            .timestamp_s(1622493622)
+            .build()
+            .unwrap();
+        let line = |options: &LineProtocolOptions| {
+            measurement.to_line_protocol_with_options(Precision::Seconds, options)
+        };
+        let fields = |line: String| -> Vec<String> {
+            line.split(' ')
+                .nth(1)
+                .unwrap()
+                .split(',')
+                .map(String::from)
+                .collect()
+        };
+
+        let mut shortest = fields(line(&LineProtocolOptions::new()));
+        shortest.sort();
+        assert_eq!(shortest, vec!["a=21.456789", "b=0.0012345"]);
+
+        let options = LineProtocolOptions::new()
+            .float_format(FloatFormat::Decimals(2))
+            .field_float_format("b", FloatFormat::SignificantDigits(3));
+        let mut rounded = fields(line(&options));
+        rounded.sort();
+        assert_eq!(rounded, vec!["a=21.46", "b=0.00123"]);
+
+        assert_eq!(FloatFormat::Decimals(0).round(2.5), 2.0);
+        assert_eq!(FloatFormat::Decimals(1).round(-0.04), -0.0);
+        assert_eq!(FloatFormat::SignificantDigits(0).round(1234.5), 1000.0);
+        assert_eq!(FloatFormat::SignificantDigits(2).round(1.0e300), 1.0e300);
+        assert!(FloatFormat::Decimals(2).round(f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn compact_booleans() {
+        let measurement = Measurement::builder("m")
+            .field("a", true)
+            .field("b", false)
+            .timestamp_s(1622493622)
+            .build()
+            .unwrap();
+        let options = LineProtocolOptions::new().compact_booleans(true);
+        let line = measurement.to_line_protocol_with_options(Precision::Seconds, &options);
+        assert!(!line.contains("true") && !line.contains("false"));
+        assert_eq!(
+            Measurement::from_line_protocol_with_precision(&line, Precision::Seconds).unwrap(),
+            vec![measurement.clone()]
+        );
+        assert_eq!(
+            measurement
+                .to_line_protocol_with_options(Precision::Seconds, &LineProtocolOptions::new()),
+            measurement.to_line_protocol_with_precision(Precision::Seconds)
+        );
+    }
+
+    #[test]
+    fn control_characters() {
+        let measurement = Measurement::builder("m")
+            .field("log", "line 1\r\n\"line\" 2\u{1b}")
+            .timestamp_s(1622493622)
+            .build()
+            .unwrap();
+        let line = |control_characters| {
+            let options = LineProtocolOptions::new().control_characters(control_characters);
+            measurement.to_line_protocol_with_options(Precision::Seconds, &options)
+        };
+        assert_eq!(
+            line(ControlCharacters::Escape),
+            r#"m log="line 1\r\n\"line\" 2\u{1b}" 1622493622"#
+        );
+        let options = LineProtocolOptions::new().control_characters(ControlCharacters::Reject);
+        let error = measurement
+            .try_to_line_protocol_with_options(Precision::Seconds, &options)
+            .unwrap_err();
+        assert_eq!(error.field(), "log");
+        assert_eq!(
+            error.to_string(),
+            "string field 'log' of 'm' contains control characters"
+        );
+        let plain = Measurement::builder("m")
+            .field("log", "ok")
+            .timestamp_s(1622493622)
+            .build()
+            .unwrap();
+        assert_eq!(
+            plain
+                .try_to_line_protocol_with_options(Precision::Seconds, &options)
+                .unwrap(),
+            r#"m log="ok" 1622493622"#
+        );
+        let stripped = line(ControlCharacters::Strip);
+        assert_eq!(stripped, r#"m log="line 1\"line\" 2" 1622493622"#);
+        assert_eq!(
+            Measurement::from_line_protocol_with_precision(&stripped, Precision::Seconds).unwrap()
+                [0]
+            .field("log"),
+            Some(&Field::String(r#"line 1"line" 2"#.to_string()))
+        );
+    }
+
+    #[test]
+    fn telegraf_output() {
+        let parsed = Measurement::from_line_protocol(
+            "# comment\n\ncpu,cpu=cpu-total,host=a\\b usage_idle=99.2,ok=T 1622493622000000000\n",
+        )
+        .unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].tag("host"), Some("a\\b"));
+        assert_eq!(parsed[0].field("ok"), Some(&Field::Bool(true)));
+        assert_eq!(parsed[0].timestamp(), Some(1622493622000000000));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let error = |lines| Measurement::from_line_protocol(lines).unwrap_err();
+        assert_eq!(error("m\nm f=1").line(), 1);
+        assert_eq!(error("m f=1\nm,t f=1").line(), 2);
+        assert_eq!(error("m f=\"1").line(), 1);
+        assert_eq!(error("m f=1x").line(), 1);
+        assert_eq!(error("m f=1 yesterday").line(), 1);
+
+        let error = Measurement::from_line_protocol_with_precision(
+            "m f=1 99999999999999999999999999999999999999",
+            Precision::Seconds,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid line protocol on line 1: timestamp '99999999999999999999999999999999999999' out of range"
+        );
+    }
+}
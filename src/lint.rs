@@ -0,0 +1,563 @@
+//! Non-fatal checks for measurements that are valid, but likely to cause trouble once written.
+
+use crate::{line_protocol, Field, Measurement, Precision};
+use std::{collections::BTreeMap, fmt::Display};
+
+/// String tag and field values longer than this many bytes are reported.
+pub const MAX_STRING_LENGTH: usize = 1024;
+
+/// The earliest timestamp (in nanoseconds) InfluxDB accepts.
+pub const MIN_TIMESTAMP_NANOS: i128 = -9_223_372_036_854_775_806;
+
+/// The latest timestamp (in nanoseconds) InfluxDB accepts.
+pub const MAX_TIMESTAMP_NANOS: i128 = 9_223_372_036_854_775_806;
+
+/// A problem found by `lint`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// Index of the offending measurement in the batch
+    pub index: usize,
+    /// Name of the offending measurement
+    pub measurement: String,
+    pub kind: WarningKind,
+}
+
+/// The kinds of problems found by `lint`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarningKind {
+    /// The value of the tag looks unique per point (such as a UUID or a long hex string), which
+    /// makes every point a new series.
+    HighCardinalityTag { tag: String, value: String },
+    /// The value of the tag or string field is longer than `MAX_STRING_LENGTH`.
+    LongString { key: String, length: usize },
+    /// The numeric field looks like it holds a timestamp, which usually belongs in the timestamp
+    /// of the measurement or in a tag.
+    TimestampField { field: String },
+    /// Two keys of measurements with the same name only differ in case, and are likely meant to
+    /// be the same key.
+    KeyCaseConflict { key: String, other: String },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "measurement '{}' (#{}): ", self.measurement, self.index)?;
+        match &self.kind {
+            WarningKind::HighCardinalityTag { tag, value } => write!(
+                f,
+                "tag '{}' has a value that looks unique ('{}')",
+                tag, value
+            ),
+            WarningKind::LongString { key, length } => {
+                write!(f, "value of '{}' is {} bytes long", key, length)
+            }
+            WarningKind::TimestampField { field } => {
+                write!(f, "field '{}' looks like a timestamp", field)
+            }
+            WarningKind::KeyCaseConflict { key, other } => {
+                write!(f, "key '{}' only differs in case from '{}'", key, other)
+            }
+        }
+    }
+}
+
+/// A field written with different types in the same batch, found by `type_conflicts`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeConflict {
+    /// Name of the measurements with the field
+    pub measurement: String,
+    pub field: String,
+    /// Index of the first measurement with the field in the batch
+    pub first_index: usize,
+    /// Type of the field in the first measurement
+    pub first_type: &'static str,
+    /// Index of the first measurement with the field in a different type
+    pub index: usize,
+    /// Type of the field in that measurement
+    pub data_type: &'static str,
+}
+
+impl Display for TypeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "field '{}' of measurement '{}' is {} (#{}), but {} (#{})",
+            self.field,
+            self.measurement,
+            self.first_type,
+            self.first_index,
+            self.data_type,
+            self.index
+        )
+    }
+}
+
+/// Find fields that are written with different types in a batch, such as `value=1i` in one
+/// measurement and `value=1.0` in another with the same name. InfluxDB rejects the points
+/// that don't match the type it sees first, and writes the rest.
+///
+/// Every other type of a field is reported once, for the first measurement with it.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::{lint, Measurement};
+/// let batch = vec![
+///     Measurement::builder("cpu").field("usage", 1).build().unwrap(),
+///     Measurement::builder("cpu").field("usage", 0.5).build().unwrap(),
+/// ];
+/// let conflicts = lint::type_conflicts(&batch);
+/// assert_eq!(
+///     conflicts[0].to_string(),
+///     "field 'usage' of measurement 'cpu' is integer (#0), but float (#1)"
+/// );
+/// ```
+pub fn type_conflicts(measurements: &[Measurement]) -> Vec<TypeConflict> {
+    let mut conflicts = Vec::new();
+    // Types of fields with the index of the first measurement with each, per measurement name
+    let mut types: BTreeMap<(&str, &str), Vec<(&'static str, usize)>> = BTreeMap::new();
+
+    for (index, measurement) in measurements.iter().enumerate() {
+        for (field, value) in measurement.fields() {
+            let data_type = value.data_type();
+            let seen = types.entry((measurement.name(), field)).or_default();
+            if seen.iter().any(|(seen_type, _)| *seen_type == data_type) {
+                continue;
+            }
+            if let Some(&(first_type, first_index)) = seen.first() {
+                conflicts.push(TypeConflict {
+                    measurement: measurement.name().to_string(),
+                    field: field.to_string(),
+                    first_index,
+                    first_type,
+                    index,
+                    data_type,
+                });
+            }
+            seen.push((data_type, index));
+        }
+    }
+    conflicts
+}
+
+/// A problem found by `lint_line_protocol`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The (1-based) number of the offending line
+    pub line: usize,
+    /// Byte offset of the problem in the input
+    pub offset: usize,
+    pub kind: DiagnosticKind,
+}
+
+/// The kinds of problems found by `lint_line_protocol`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// The line can't be parsed.
+    Syntax { message: String },
+    /// A backslash that doesn't escape anything, and is written as is. This is usually a
+    /// character that was escaped where it doesn't need to be, such as `=` in a measurement name,
+    /// or a missing escape of the backslash itself in a string field value.
+    StrayEscape,
+    /// The field has a different type than in an earlier line of the same measurement, which
+    /// InfluxDB rejects.
+    TypeConflict {
+        measurement: String,
+        field: String,
+        data_type: &'static str,
+        /// Type of the field in the earlier line
+        first_type: &'static str,
+        /// The (1-based) number of the earlier line
+        first_line: usize,
+    },
+    /// The timestamp is outside of the range InfluxDB accepts, `MIN_TIMESTAMP_NANOS` to
+    /// `MAX_TIMESTAMP_NANOS`, usually because it is in a different precision than written with.
+    TimestampOutOfRange { timestamp: i128 },
+    /// The line ends with whitespace, which InfluxDB rejects after a timestamp and otherwise
+    /// ignores.
+    TrailingWhitespace,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {} (offset {}): ", self.line, self.offset)?;
+        match &self.kind {
+            DiagnosticKind::Syntax { message } => write!(f, "{}", message),
+            DiagnosticKind::StrayEscape => write!(f, "backslash doesn't escape anything"),
+            DiagnosticKind::TypeConflict {
+                measurement,
+                field,
+                data_type,
+                first_type,
+                first_line,
+            } => write!(
+                f,
+                "field '{}' of '{}' is {}, but was {} on line {}",
+                field, measurement, data_type, first_type, first_line
+            ),
+            DiagnosticKind::TimestampOutOfRange { timestamp } => {
+                write!(f, "timestamp {} is out of range", timestamp)
+            }
+            DiagnosticKind::TrailingWhitespace => write!(f, "trailing whitespace"),
+        }
+    }
+}
+
+/// Check line protocol with timestamps in nanoseconds for mistakes, pointing out where they are:
+/// lines that don't parse, stray backslashes, fields that change type between lines, timestamps
+/// out of range and trailing whitespace.
+///
+/// Unlike `Measurement::from_line_protocol`, all lines are checked instead of stopping at the
+/// first error. Empty lines and comments are skipped.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::lint::{self, DiagnosticKind};
+/// let diagnostics = lint::lint_line_protocol("cpu usage=0.5\ncpu usage=1i \n");
+/// assert_eq!(diagnostics.len(), 2);
+/// assert!(matches!(diagnostics[0].kind, DiagnosticKind::TypeConflict { .. }));
+/// assert_eq!(diagnostics[0].offset, 18);
+/// assert_eq!(diagnostics[1].kind, DiagnosticKind::TrailingWhitespace);
+/// ```
+pub fn lint_line_protocol(lines: &str) -> Vec<Diagnostic> {
+    lint_line_protocol_with_precision(lines, Precision::Nanoseconds)
+}
+
+/// Check line protocol with timestamps in the given precision, see `lint_line_protocol`.
+pub fn lint_line_protocol_with_precision(lines: &str, precision: Precision) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // Types and line numbers of the first occurrence of fields, per measurement name
+    let mut types: BTreeMap<String, BTreeMap<String, (&'static str, usize)>> = BTreeMap::new();
+
+    let mut line_start = 0;
+    for (index, raw_line) in lines.split('\n').enumerate() {
+        let number = index + 1;
+        let start = line_start;
+        line_start += raw_line.len() + 1;
+
+        let line = raw_line.strip_suffix('\r').unwrap_or(raw_line);
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let start = start + (line.len() - trimmed.len());
+        let mut report = |offset, kind| {
+            diagnostics.push(Diagnostic {
+                line: number,
+                offset: start + offset,
+                kind,
+            })
+        };
+
+        let parsed = match line_protocol::parse_line(trimmed, precision) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let kind = match e.timestamp_out_of_range {
+                    Some(timestamp) => DiagnosticKind::TimestampOutOfRange { timestamp },
+                    None => DiagnosticKind::Syntax { message: e.message },
+                };
+                report(e.offset, kind);
+                continue;
+            }
+        };
+        for offset in parsed.stray_escapes {
+            report(offset, DiagnosticKind::StrayEscape);
+        }
+
+        let measurement = &parsed.measurement;
+        let seen = types.entry(measurement.name().to_string()).or_default();
+        for (field, offset) in parsed.fields {
+            let data_type = match measurement.field(&field) {
+                Some(value) => value.data_type(),
+                None => continue,
+            };
+            match seen.get(&field) {
+                Some(&(first_type, first_line)) if first_type != data_type => report(
+                    offset,
+                    DiagnosticKind::TypeConflict {
+                        measurement: measurement.name().to_string(),
+                        field,
+                        data_type,
+                        first_type,
+                        first_line,
+                    },
+                ),
+                Some(_) => {}
+                None => {
+                    seen.insert(field, (data_type, number));
+                }
+            }
+        }
+
+        if let (Some(offset), Some(timestamp)) = (parsed.timestamp, measurement.timestamp()) {
+            if !(MIN_TIMESTAMP_NANOS..=MAX_TIMESTAMP_NANOS).contains(&timestamp) {
+                report(
+                    offset,
+                    DiagnosticKind::TimestampOutOfRange {
+                        timestamp: timestamp / precision.nanos_per_unit(),
+                    },
+                );
+            }
+        }
+
+        let content = trimmed.trim_end();
+        if content.len() < trimmed.len() {
+            report(content.len(), DiagnosticKind::TrailingWhitespace);
+        }
+    }
+    diagnostics
+}
+
+/// Check a batch of measurements for values that InfluxDB accepts, but that tend to cause
+/// trouble later: tags that blow up the series cardinality, very long strings, timestamps
+/// stored as fields, and keys that only differ in case.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::{lint::{self, WarningKind}, Measurement};
+/// let batch = vec![Measurement::builder("requests")
+///     .tag("request_id", "5f0c5e7a-3d4b-4c1e-9b7a-2f6d8e9a1b3c")
+///     .field("duration", 0.25)
+///     .build()
+///     .unwrap()];
+/// let warnings = lint::lint(&batch);
+/// assert!(matches!(
+///     &warnings[0].kind,
+///     WarningKind::HighCardinalityTag { tag, .. } if tag == "request_id"
+/// ));
+/// ```
+pub fn lint(measurements: &[Measurement]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    // Keys by lowercased key, per measurement name
+    let mut keys: BTreeMap<&str, BTreeMap<String, &str>> = BTreeMap::new();
+
+    for (index, measurement) in measurements.iter().enumerate() {
+        let mut warn = |kind| {
+            warnings.push(Warning {
+                index,
+                measurement: measurement.name().to_string(),
+                kind,
+            })
+        };
+
+        for (tag, value) in measurement.tags() {
+            if looks_unique(value) {
+                warn(WarningKind::HighCardinalityTag {
+                    tag: tag.to_string(),
+                    value: value.to_string(),
+                });
+            }
+            if value.len() > MAX_STRING_LENGTH {
+                warn(WarningKind::LongString {
+                    key: tag.to_string(),
+                    length: value.len(),
+                });
+            }
+        }
+        for (field, value) in measurement.fields() {
+            match value {
+                Field::String(value) if value.len() > MAX_STRING_LENGTH => {
+                    warn(WarningKind::LongString {
+                        key: field.to_string(),
+                        length: value.len(),
+                    })
+                }
+                _ if looks_like_timestamp(field, value) => warn(WarningKind::TimestampField {
+                    field: field.to_string(),
+                }),
+                _ => {}
+            }
+        }
+
+        let seen = keys.entry(measurement.name()).or_default();
+        let names = measurement
+            .tags()
+            .map(|(tag, _)| tag)
+            .chain(measurement.fields().map(|(field, _)| field));
+        for key in names {
+            match seen.get(&key.to_lowercase()) {
+                Some(other) if *other != key => warn(WarningKind::KeyCaseConflict {
+                    key: key.to_string(),
+                    other: other.to_string(),
+                }),
+                Some(_) => {}
+                None => {
+                    seen.insert(key.to_lowercase(), key);
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// Whether a tag value looks like a UUID or another long random identifier.
+fn looks_unique(value: &str) -> bool {
+    let is_uuid = value.len() == 36
+        && value.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+    let is_long_hex = value.len() >= 24
+        && value.bytes().all(|b| b.is_ascii_hexdigit())
+        && value.bytes().any(|b| b.is_ascii_digit());
+    is_uuid || is_long_hex
+}
+
+/// Whether a numeric field looks like a Unix timestamp between 2000 and 2100, either by its
+/// name or by being in the range of millisecond, microsecond or nanosecond timestamps.
+fn looks_like_timestamp(name: &str, value: &Field) -> bool {
+    const START_SECONDS: f64 = 946_684_800.0;
+    const END_SECONDS: f64 = 4_102_444_800.0;
+
+    let value = match value {
+        Field::Integer(_) | Field::UInteger(_) | Field::Float(_) => value.as_f64(),
+        _ => None,
+    };
+    let value = match value {
+        Some(value) => value,
+        None => return false,
+    };
+    let in_range = |scale: f64| (START_SECONDS * scale..END_SECONDS * scale).contains(&value);
+    let name = name.to_lowercase();
+    let timestamp_name =
+        name == "ts" || name.contains("time") || name.ends_with("_at") || name.ends_with("_ts");
+
+    (timestamp_name && in_range(1.0)) || in_range(1e3) || in_range(1e6) || in_range(1e9)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lint_batch() {
+        let batch = vec![
+            Measurement::builder("m")
+                .tag("host", "web-1")
+                .tag("trace", "0af7651916cd43dd8448eb211c80319c")
+                .field("Value", 1)
+                .field("created_at", 1_622_493_622)
+                .field("count", 1_622_493_622)
+                .field("event", 1_622_493_622_000_i64)
+                .field("log", "x".repeat(MAX_STRING_LENGTH + 1))
+                .build()
+                .unwrap(),
+            Measurement::builder("m").field("value", 2).build().unwrap(),
+            Measurement::builder("other")
+                .field("value", 2)
+                .build()
+                .unwrap(),
+        ];
+
+        let mut kinds: Vec<_> = lint(&batch).into_iter().map(|w| format!("{}", w)).collect();
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec![
+                "measurement 'm' (#0): field 'created_at' looks like a timestamp",
+                "measurement 'm' (#0): field 'event' looks like a timestamp",
+                "measurement 'm' (#0): tag 'trace' has a value that looks unique ('0af7651916cd43dd8448eb211c80319c')",
+                "measurement 'm' (#0): value of 'log' is 1025 bytes long",
+                "measurement 'm' (#1): key 'value' only differs in case from 'Value'",
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_type_conflicts() {
+        let batch = vec![
+            Measurement::builder("m")
+                .field("value", 1)
+                .field("ok", true)
+                .build()
+                .unwrap(),
+            Measurement::builder("other")
+                .field("value", 1.5)
+                .build()
+                .unwrap(),
+            Measurement::builder("m")
+                .field("value", 2.5)
+                .build()
+                .unwrap(),
+            Measurement::builder("m")
+                .field("value", 3.5)
+                .build()
+                .unwrap(),
+            Measurement::builder("m")
+                .field("value", "4")
+                .field("ok", true)
+                .build()
+                .unwrap(),
+        ];
+        let conflicts: Vec<_> = type_conflicts(&batch)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            conflicts,
+            vec![
+                "field 'value' of measurement 'm' is integer (#0), but float (#2)",
+                "field 'value' of measurement 'm' is integer (#0), but string (#4)",
+            ]
+        );
+        assert!(type_conflicts(&batch[..2]).is_empty());
+    }
+
+    #[test]
+    fn lint_lines() {
+        let lines = "# comment\r\n\
+            m,host=a\\:b value=1i 1622493622\r\n\
+            \n\
+            m value=1.5,note=\"C:\\temp\" 1622493622000 \n\
+            m value\n\
+            other value=2.5 -9300000000";
+        let diagnostics: Vec<_> = lint_line_protocol_with_precision(lines, Precision::Seconds)
+            .into_iter()
+            .map(|d| (d.line, d.offset, d.to_string()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    2,
+                    19,
+                    "line 2 (offset 19): backslash doesn't escape anything".to_string()
+                ),
+                (
+                    4,
+                    65,
+                    "line 4 (offset 65): backslash doesn't escape anything".to_string()
+                ),
+                (
+                    4,
+                    47,
+                    "line 4 (offset 47): field 'value' of 'm' is float, but was integer on line 2"
+                        .to_string()
+                ),
+                (
+                    4,
+                    72,
+                    "line 4 (offset 72): timestamp 1622493622000 is out of range".to_string()
+                ),
+                (4, 85, "line 4 (offset 85): trailing whitespace".to_string()),
+                (
+                    5,
+                    94,
+                    "line 5 (offset 94): missing value of field 'value'".to_string()
+                ),
+                (
+                    6,
+                    111,
+                    "line 6 (offset 111): timestamp -9300000000 is out of range".to_string()
+                ),
+            ]
+        );
+
+        let overflowing = "m f=1 99999999999999999999999999999999999999";
+        assert_eq!(
+            lint_line_protocol_with_precision(overflowing, Precision::Seconds)
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>(),
+            ["line 1 (offset 6): timestamp 99999999999999999999999999999999999999 is out of range"]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Reusable presets for building many similar measurements, see `MeasurementTemplate`.
+
+use crate::{Field, Key, MeasurementBuilder};
+
+/// A preset measurement name, tags and fields that builders can be created from, for sampling
+/// loops that write the same series over and over.
+///
+/// ## Example
+/// ```rust
+/// # use influxrs::MeasurementTemplate;
+/// let template = MeasurementTemplate::new("cpu")
+///     .tag("host", "web-1")
+///     .field("cores", 8);
+/// for usage in [0.25, 0.5] {
+///     let measurement = template.instance().field("usage", usage).build().unwrap();
+///     assert_eq!(measurement.tag("host"), Some("web-1"));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MeasurementTemplate {
+    builder: MeasurementBuilder,
+}
+
+impl MeasurementTemplate {
+    pub fn new(measurement_name: impl Into<String>) -> Self {
+        Self {
+            builder: MeasurementBuilder::new(measurement_name),
+        }
+    }
+
+    /// Preset a tag of every instance.
+    pub fn tag(mut self, name: impl Into<Key>, value: impl Into<String>) -> Self {
+        self.builder = self.builder.tag(name, value);
+        self
+    }
+
+    /// Preset a field of every instance.
+    pub fn field(mut self, name: impl Into<Key>, value: impl Into<Field>) -> Self {
+        self.builder = self.builder.field(name, value);
+        self
+    }
+
+    /// A builder with the preset name, tags and fields, with room for a few more fields without
+    /// reallocating.
+    pub fn instance(&self) -> MeasurementBuilder {
+        let mut builder = self.builder.clone();
+        builder.fields.reserve(4);
+        builder
+    }
+}
+
+/// A template presetting everything that was set on the builder, including its policies and
+/// timestamp.
+impl From<MeasurementBuilder> for MeasurementTemplate {
+    fn from(builder: MeasurementBuilder) -> Self {
+        Self { builder }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmptyTagPolicy;
+
+    #[test]
+    fn instances() {
+        let template = MeasurementTemplate::from(
+            MeasurementBuilder::new("m")
+                .tag("empty", "")
+                .empty_tag_policy(EmptyTagPolicy::Drop),
+        )
+        .tag("host", "a")
+        .field("preset", true);
+
+        let first = template.instance().field("value", 1).build().unwrap();
+        let second = template
+            .instance()
+            .tag("host", "b")
+            .field("value", 2)
+            .build()
+            .unwrap();
+
+        assert_eq!(first.tags().collect::<Vec<_>>(), vec![("host", "a")]);
+        assert_eq!(first.field("preset"), Some(&Field::Bool(true)));
+        assert_eq!(second.tag("host"), Some("b"));
+        assert_eq!(second.field("value"), Some(&Field::Integer(2)));
+    }
+}